@@ -6,3 +6,6 @@ pub(crate) const RAW_BYTES: Tag = Tag::new(80000);
 pub(crate) const EVAL: Tag = Tag::new(80001);
 pub(crate) const EVAL_FORMAT: Tag = Tag::new(80002);
 pub(crate) const JSON: Tag = Tag::new(80003);
+pub(crate) const TYPST: Tag = Tag::new(80004);
+pub(crate) const JSON5: Tag = Tag::new(80005);
+pub(crate) const ASSET: Tag = Tag::new(80006);
@@ -1,22 +1,152 @@
-pub fn is_json(s: &[u8]) -> bool {
-    let mut validator = JsonValidator::new(s);
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    UnexpectedToken(u8),
+    UnexpectedEnd,
+    BadEscape,
+    InvalidUtf8,
+    ControlCharacter,
+    TrailingData,
+    TooDeep,
+}
+
+impl Reason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reason::UnexpectedToken(_) => "unexpected token",
+            Reason::UnexpectedEnd => "unexpected end",
+            Reason::BadEscape => "bad escape",
+            Reason::InvalidUtf8 => "invalid utf-8",
+            Reason::ControlCharacter => "control character in string",
+            Reason::TrailingData => "trailing data",
+            Reason::TooDeep => "nesting too deep",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::UnexpectedToken(b) if b.is_ascii_graphic() => {
+                write!(f, "{} '{}'", self.name(), *b as char)
+            }
+            Reason::UnexpectedToken(b) => write!(f, "{} 0x{:02x}", self.name(), b),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonError {
+    // byte offset
+    pub offset: usize,
+    // line and column (in characters) start at 1
+    pub line: usize,
+    pub column: usize,
+    pub reason: Reason,
+}
+
+impl JsonError {
+    pub(crate) fn at(input: &[u8], pos: usize, reason: Reason) -> Self {
+        let before = &input[..pos.min(input.len())];
+        let line_start = before
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        JsonError {
+            offset: pos,
+            line: before.iter().filter(|b| **b == b'\n').count() + 1,
+            // utf-8 continuation bytes are not counted
+            column: before[line_start..]
+                .iter()
+                .filter(|b| (**b >> 6) != 0b10)
+                .count()
+                + 1,
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {} (offset {})",
+            self.reason, self.line, self.column, self.offset
+        )
+    }
+}
+
+// nested arrays and objects allowed by `validate`
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+pub fn validate(s: &[u8]) -> Result<(), JsonError> {
+    validate_with_max_depth(s, DEFAULT_MAX_DEPTH)
+}
+
+pub fn validate_with_max_depth(s: &[u8], max_depth: usize) -> Result<(), JsonError> {
+    let mut validator = JsonValidator::new(s, max_depth);
     validator.skip_whitespace();
-    if validator.validate_value() {
-        validator.skip_whitespace();
-        validator.is_end_of_input()
+    validator.validate_value()?;
+    validator.skip_whitespace();
+    if validator.is_end_of_input() {
+        Ok(())
     } else {
-        false
+        Err(validator.error(Reason::TrailingData))
     }
 }
 
+#[cfg(test)]
+pub fn is_json(s: &[u8]) -> bool {
+    validate(s).is_ok()
+}
+
+// validates json bytes, the error carries the position and reason
+pub fn validated_str(s: &[u8]) -> Result<&str, minicbor::decode::Error> {
+    validate(s).map_err(|err| {
+        minicbor::decode::Error::type_mismatch(minicbor::data::Type::Bytes)
+            .with_message(format!("invalid json: {}", err))
+    })?;
+    std::str::from_utf8(s).map_err(|e| {
+        minicbor::decode::Error::type_mismatch(minicbor::data::Type::Bytes).with_message(e)
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Container {
+    Object,
+    Array,
+}
+
 struct JsonValidator<'a> {
     input: &'a [u8],
     pos: usize,
+    // open arrays and objects, the validator never recurses
+    stack: Vec<Container>,
+    max_depth: usize,
 }
 
 impl<'a> JsonValidator<'a> {
-    fn new(input: &'a [u8]) -> Self {
-        Self { input, pos: 0 }
+    fn new(input: &'a [u8], max_depth: usize) -> Self {
+        Self {
+            input,
+            pos: 0,
+            stack: Vec::new(),
+            max_depth,
+        }
+    }
+
+    fn error(&self, reason: Reason) -> JsonError {
+        JsonError::at(self.input, self.pos, reason)
+    }
+
+    // the current byte is not allowed at this position
+    fn unexpected(&self) -> JsonError {
+        match self.current() {
+            Some(b) => self.error(Reason::UnexpectedToken(b)),
+            None => self.error(Reason::UnexpectedEnd),
+        }
     }
 
     fn is_end_of_input(&mut self) -> bool {
@@ -44,130 +174,119 @@ impl<'a> JsonValidator<'a> {
         }
     }
 
-    fn validate_value(&mut self) -> bool {
-        match self.current() {
-            Some(b'{') => self.advance_and_validate_object(),
-            Some(b'[') => self.advance_and_validate_array(),
-            Some(b'"') => self.advance_and_validate_string(),
-
-            Some(b'0') => self.advance_and_validate_fractional(),
-            Some(b'-' | b'+') => self.advance_and_validate_signed_number(),
-            Some((b'1'..=b'9')) => self.advance_and_validate_number(),
-
-            Some(b't') => self.advance_and_parse_literal(b"rue"),
-            Some(b'f') => self.advance_and_parse_literal(b"alse"),
-            Some(b'n') => self.advance_and_parse_literal(b"ull"),
-            _ => false,
-        }
-    }
-
-    // |{...
-    fn advance_and_validate_object(&mut self) -> bool {
-        self.advance();
-        self.skip_whitespace();
-
-        // {|
-
-        if self.current() == Some(b'}') {
-            self.advance(); // {}|
-            return true;
-        }
-
+    fn validate_value(&mut self) -> Result<(), JsonError> {
         loop {
-            if self.current() != Some(b'"') {
-                return false;
-            }
+            // |value
+            match self.current() {
+                Some(b'{') => {
+                    self.check_depth()?;
+                    self.advance();
+                    self.skip_whitespace();
 
-            // {"|
+                    // {|
 
-            if !self.advance_and_validate_string() {
-                return false;
-            }
-            self.skip_whitespace();
+                    if self.current() == Some(b'}') {
+                        self.advance(); // {}|
+                    } else {
+                        self.stack.push(Container::Object);
+                        self.validate_key()?;
+                        continue;
+                    }
+                }
+                Some(b'[') => {
+                    self.check_depth()?;
+                    self.advance();
+                    self.skip_whitespace();
 
-            // {"key"|
+                    // [|
 
-            if self.current() != Some(b':') {
-                return false;
-            }
-            self.advance();
-            self.skip_whitespace();
+                    if self.current() == Some(b']') {
+                        self.advance(); // []|
+                    } else {
+                        self.stack.push(Container::Array);
+                        continue;
+                    }
+                }
+                Some(b'"') => self.advance_and_validate_string()?,
 
-            // {"key":|
+                Some(b'0') => self.advance_and_validate_fractional()?,
+                Some(b'-' | b'+') => self.advance_and_validate_signed_number()?,
+                Some((b'1'..=b'9')) => self.advance_and_validate_number()?,
 
-            if !self.validate_value() {
-                return false;
+                Some(b't') => self.advance_and_parse_literal(b"rue")?,
+                Some(b'f') => self.advance_and_parse_literal(b"alse")?,
+                Some(b'n') => self.advance_and_parse_literal(b"ull")?,
+                _ => return Err(self.unexpected()),
             }
-            self.skip_whitespace();
-
-            // {"key":value|
-
-            match self.current() {
-                Some(b',') => {
-                    self.advance();
-                    self.skip_whitespace();
 
-                    // {"key":value,|
-                }
-                Some(b'}') => {
-                    self.advance();
-                    return true; // {"key":value}|
+            // value| closes the surrounding arrays and objects until the next value
+            loop {
+                self.skip_whitespace();
+                match (self.stack.last(), self.current()) {
+                    (None, _) => return Ok(()),
+                    (Some(_), Some(b',')) => {
+                        self.advance();
+                        self.skip_whitespace();
+                        if let Some(Container::Object) = self.stack.last() {
+                            // {"key":value,|
+                            self.validate_key()?;
+                        }
+                        // [value,|
+                        break;
+                    }
+                    (Some(Container::Object), Some(b'}'))
+                    | (Some(Container::Array), Some(b']')) => {
+                        self.advance();
+                        self.stack.pop();
+                    }
+                    _ => return Err(self.unexpected()),
                 }
-                _ => return false,
             }
         }
     }
 
-    // |[...
-    fn advance_and_validate_array(&mut self) -> bool {
-        self.advance();
-        self.skip_whitespace();
-
-        // [|
-
-        if self.current() == Some(b']') {
-            self.advance(); // []|
-            return true;
+    // |[ or |{
+    fn check_depth(&self) -> Result<(), JsonError> {
+        if self.stack.len() >= self.max_depth {
+            return Err(self.error(Reason::TooDeep));
         }
+        Ok(())
+    }
 
-        loop {
-            if !self.validate_value() {
-                return false;
-            }
-            self.skip_whitespace();
-
-            // [value|
+    // {|"key":
+    fn validate_key(&mut self) -> Result<(), JsonError> {
+        if self.current() != Some(b'"') {
+            return Err(self.unexpected());
+        }
+        self.advance_and_validate_string()?;
+        self.skip_whitespace();
 
-            match self.current() {
-                Some(b',') => {
-                    self.advance();
-                    self.skip_whitespace();
+        // {"key"|
 
-                    // [value,|
-                }
-                Some(b']') => {
-                    self.advance();
-                    return true; // [value]|
-                }
-                _ => return false,
-            }
+        if self.current() != Some(b':') {
+            return Err(self.unexpected());
         }
+        self.advance();
+        self.skip_whitespace();
+
+        // {"key":|
+        Ok(())
     }
 
-    fn validate_continuation_bytes(&mut self, len: usize) -> bool {
-        for _ in 1..len {
+    fn validate_continuation_bytes(&mut self, len: usize) -> Result<(), JsonError> {
+        for _ in 0..len {
             match self.current() {
                 Some(c) if (c >> 6) == 0b10 => {
                     self.advance();
                 }
-                _ => return false,
+                _ => return Err(self.error(Reason::InvalidUtf8)),
             }
         }
-        return true;
+        Ok(())
     }
 
     // |"
-    fn advance_and_validate_string(&mut self) -> bool {
+    fn advance_and_validate_string(&mut self) -> Result<(), JsonError> {
         self.advance();
 
         // "|
@@ -176,7 +295,7 @@ impl<'a> JsonValidator<'a> {
             match self.current() {
                 Some(b'"') => {
                     self.advance(); // ""|
-                    return true;
+                    return Ok(());
                 }
                 Some(b'\\') => {
                     self.advance();
@@ -195,13 +314,15 @@ impl<'a> JsonValidator<'a> {
                                     Some(b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F') => {
                                         self.advance();
                                     }
-                                    _ => return false,
+                                    None => return Err(self.error(Reason::UnexpectedEnd)),
+                                    _ => return Err(self.error(Reason::BadEscape)),
                                 }
                             }
 
                             // "\u0000|
                         }
-                        _ => return false,
+                        None => return Err(self.error(Reason::UnexpectedEnd)),
+                        _ => return Err(self.error(Reason::BadEscape)),
                     }
                 }
                 Some(b) => {
@@ -209,61 +330,55 @@ impl<'a> JsonValidator<'a> {
                         b if (b >> 7) == 0 => {
                             // one byte
                             if b < 0x20 {
-                                return false;
+                                return Err(self.error(Reason::ControlCharacter));
                             }
                             self.advance();
                         }
                         b if (b >> 5) == 0b110 => {
                             // two bytes (110xxxxx 10xxxxxx)
                             self.advance();
-                            if !self.validate_continuation_bytes(1) {
-                                return false;
-                            }
+                            self.validate_continuation_bytes(1)?;
                         }
                         b if (b >> 4) == 0b1110 => {
                             // three bytes (1110xxxx 10xxxxxx 10xxxxxx)
                             self.advance();
-                            if !self.validate_continuation_bytes(2) {
-                                return false;
-                            }
+                            self.validate_continuation_bytes(2)?;
                         }
                         b if (b >> 3) == 0b11110 => {
                             // four bytes (11110xxx 10xxxxxx 10xxxxxx, 10xxxxxx)
                             self.advance();
-                            if !self.validate_continuation_bytes(3) {
-                                return false;
-                            }
+                            self.validate_continuation_bytes(3)?;
                         }
-                        _ => return false,
+                        _ => return Err(self.error(Reason::InvalidUtf8)),
                     };
                 }
-                _ => return false,
+                None => return Err(self.error(Reason::UnexpectedEnd)),
             }
         }
     }
 
     // |+0123.0123
-    fn advance_and_validate_signed_number(&mut self) -> bool {
+    fn advance_and_validate_signed_number(&mut self) -> Result<(), JsonError> {
         self.advance();
         // +|0123.0123
         match self.current() {
             Some(b'0') => self.advance_and_validate_fractional(),
             Some((b'1'..=b'9')) => self.advance_and_validate_number(),
-            _ => false,
+            _ => Err(self.unexpected()),
         }
     }
 
-    fn advance_and_validate_fractional(&mut self) -> bool {
+    fn advance_and_validate_fractional(&mut self) -> Result<(), JsonError> {
         self.advance();
         self.validate_fractional()
     }
 
     // 123|.0123
-    fn validate_fractional(&mut self) -> bool {
+    fn validate_fractional(&mut self) -> Result<(), JsonError> {
         if self.current() == Some(b'.') {
             self.advance();
             if !matches!(self.current(), Some((b'0'..=b'9'))) {
-                return false;
+                return Err(self.unexpected());
             }
             while matches!(self.current(), Some((b'0'..=b'9'))) {
                 self.advance();
@@ -277,18 +392,18 @@ impl<'a> JsonValidator<'a> {
                 self.advance();
             }
             if !matches!(self.current(), Some((b'0'..=b'9'))) {
-                return false;
+                return Err(self.unexpected());
             }
             while matches!(self.current(), Some((b'0'..=b'9'))) {
                 self.advance();
             }
         }
 
-        true
+        Ok(())
     }
 
     // |123.0123
-    fn advance_and_validate_number(&mut self) -> bool {
+    fn advance_and_validate_number(&mut self) -> Result<(), JsonError> {
         self.advance();
 
         // 1|23.0123
@@ -301,7 +416,7 @@ impl<'a> JsonValidator<'a> {
         self.validate_fractional()
     }
 
-    fn advance_and_parse_literal(&mut self, literal: &[u8]) -> bool {
+    fn advance_and_parse_literal(&mut self, literal: &[u8]) -> Result<(), JsonError> {
         self.advance();
 
         for expected in literal {
@@ -309,16 +424,18 @@ impl<'a> JsonValidator<'a> {
                 Some(c) if &c == expected => {
                     self.advance();
                 }
-                _ => return false,
+                _ => return Err(self.unexpected()),
             }
         }
-        true
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::is_json;
+    use proptest::prelude::*;
+
+    use super::{is_json, validate, validate_with_max_depth, JsonError, Reason, DEFAULT_MAX_DEPTH};
 
     #[test]
     fn test_valid_literals() {
@@ -494,4 +611,125 @@ mod tests {
         assert!(!is_json(b"[]{}"));
         assert!(!is_json(b"\"\"\"\""));
     }
+
+    fn error(s: &[u8]) -> (usize, usize, usize, Reason) {
+        let JsonError {
+            offset,
+            line,
+            column,
+            reason,
+        } = validate(s).unwrap_err();
+        (offset, line, column, reason)
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eq!(
+            error(b"{\"a\":1,}"),
+            (7, 1, 8, Reason::UnexpectedToken(b'}'))
+        );
+        assert_eq!(
+            error(b"[\n  1,\n  2,\n]"),
+            (12, 4, 1, Reason::UnexpectedToken(b']'))
+        );
+        assert_eq!(
+            error("[\"\u{e4}\", x]".as_bytes()),
+            (7, 1, 7, Reason::UnexpectedToken(b'x'))
+        );
+        assert_eq!(error(b"\"\\x\""), (2, 1, 3, Reason::BadEscape));
+        assert_eq!(error(b"\"\xff\""), (1, 1, 2, Reason::InvalidUtf8));
+        assert_eq!(error(b"\"a\nb\""), (2, 1, 3, Reason::ControlCharacter));
+        assert_eq!(error(b"[1"), (2, 1, 3, Reason::UnexpectedEnd));
+        assert_eq!(error(b"[] []"), (3, 1, 4, Reason::TrailingData));
+        assert_eq!(
+            validate(b"{\"a\":1,}").unwrap_err().to_string(),
+            "unexpected token '}' at line 1, column 8 (offset 7)"
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+        assert!(is_json(nested(DEFAULT_MAX_DEPTH).as_bytes()));
+        assert_eq!(
+            error(nested(DEFAULT_MAX_DEPTH + 1).as_bytes()),
+            (DEFAULT_MAX_DEPTH, 1, DEFAULT_MAX_DEPTH + 1, Reason::TooDeep)
+        );
+        assert_eq!(validate_with_max_depth(b"{\"a\": [{}]}", 3), Ok(()));
+        assert_eq!(
+            validate_with_max_depth(b"{\"a\": [{}]}", 2)
+                .unwrap_err()
+                .reason,
+            Reason::TooDeep
+        );
+
+        // the validator does not recurse, so any depth fits on the stack
+        let deep = format!("{}1{}", "[{\"a\":".repeat(100_000), "}]".repeat(100_000));
+        assert!(validate_with_max_depth(deep.as_bytes(), usize::MAX).is_ok());
+        assert_eq!(
+            validate_with_max_depth(&deep.as_bytes()[..deep.len() - 1], usize::MAX)
+                .unwrap_err()
+                .reason,
+            Reason::UnexpectedEnd
+        );
+    }
+
+    fn json() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            Just("null".to_string()),
+            any::<bool>().prop_map(|b| b.to_string()),
+            any::<i64>().prop_map(|v| v.to_string()),
+            (-1e300..1e300f64).prop_map(|v| format!("{:e}", v)),
+            any::<String>().prop_map(|s| crate::cbor::jsstring::string(&s)),
+        ];
+        leaf.prop_recursive(8, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8)
+                    .prop_map(|values| format!("[ {} ]", values.join(" ,\n"))),
+                prop::collection::vec((any::<String>(), inner), 0..8).prop_map(|entries| {
+                    format!(
+                        "{{{}}}",
+                        entries
+                            .iter()
+                            .map(|(k, v)| format!("{}\t: {}", crate::cbor::jsstring::string(k), v))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    )
+                }),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_fuzz_valid(json in json()) {
+            prop_assert_eq!(validate(json.as_bytes()), Ok(()));
+        }
+
+        #[test]
+        fn test_fuzz_bytes(input in prop::collection::vec(any::<u8>(), 0..512)) {
+            if let Err(err) = validate(&input) {
+                prop_assert!(err.offset <= input.len());
+            }
+        }
+
+        #[test]
+        fn test_fuzz_tokens(input in "[\\[\\]{}\",: \n0-9a-z+.\\\\-]{0,512}", max_depth in 0..16usize) {
+            if let Err(err) = validate_with_max_depth(input.as_bytes(), max_depth) {
+                prop_assert!(err.offset <= input.len());
+                prop_assert!(err.line <= input.lines().count().max(1));
+            }
+        }
+
+        #[test]
+        fn test_fuzz_truncated(json in json(), cut in any::<prop::sample::Index>()) {
+            let json = json.as_bytes();
+            // every prefix of a valid value (except single numbers) is incomplete
+            let cut = cut.index(json.len());
+            if matches!(json.first(), Some(b'[' | b'{' | b'"')) && cut > 0 {
+                prop_assert!(validate(&json[..cut]).is_err());
+            }
+        }
+    }
 }
@@ -0,0 +1,672 @@
+use crate::cbor::{
+    json::{JsonError, Reason, DEFAULT_MAX_DEPTH},
+    jsstring,
+    rquickjs::freeze::FREEZE,
+};
+
+// https://spec.json5.org/ (also covers jsonc: comments and trailing commas)
+
+#[derive(Clone, Copy)]
+enum Output {
+    // js literal, optionally with frozen objects and arrays
+    Js { freeze: bool },
+    // strict json, non finite numbers become `null`
+    Json,
+}
+
+// converts json5 into a js expression
+pub fn to_js(s: &[u8], freeze: bool) -> Result<String, JsonError> {
+    Json5Parser::new(s, Output::Js { freeze })
+        .parse()
+        .map(|js| format!("({})", js))
+}
+
+// converts json5 into strict json
+pub fn to_json(s: &[u8]) -> Result<String, JsonError> {
+    Json5Parser::new(s, Output::Json).parse()
+}
+
+#[cfg(test)]
+pub fn is_json5(s: &[u8]) -> bool {
+    to_json(s).is_ok()
+}
+
+fn decode_error(err: JsonError) -> minicbor::decode::Error {
+    minicbor::decode::Error::type_mismatch(minicbor::data::Type::Bytes)
+        .with_message(format!("invalid json5: {}", err))
+}
+
+pub fn js_literal(s: &[u8], freeze: bool) -> Result<String, minicbor::decode::Error> {
+    to_js(s, freeze).map_err(decode_error)
+}
+
+pub fn json_text(s: &[u8]) -> Result<String, minicbor::decode::Error> {
+    to_json(s).map_err(decode_error)
+}
+
+// an open array or object with the converted values so far
+enum Container {
+    // the key of the value which is parsed next
+    Object { entries: Vec<String>, key: String },
+    Array { values: Vec<String> },
+}
+
+impl Container {
+    fn push(&mut self, value: String) {
+        match self {
+            Container::Object { entries, key } => entries.push(format!("{}:{}", key, value)),
+            Container::Array { values } => values.push(value),
+        }
+    }
+
+    fn close(&self) -> u8 {
+        match self {
+            Container::Object { .. } => b'}',
+            Container::Array { .. } => b']',
+        }
+    }
+
+    fn literal(self) -> String {
+        match self {
+            Container::Object { entries, .. } => format!("{{{}}}", entries.join(",")),
+            Container::Array { values } => format!("[{}]", values.join(",")),
+        }
+    }
+}
+
+struct Json5Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    output: Output,
+}
+
+impl<'a> Json5Parser<'a> {
+    fn new(input: &'a [u8], output: Output) -> Self {
+        Self {
+            input,
+            pos: 0,
+            output,
+        }
+    }
+
+    fn parse(&mut self) -> Result<String, JsonError> {
+        self.skip_trivia()?;
+        let value = self.value()?;
+        self.skip_trivia()?;
+        if self.pos < self.input.len() {
+            return Err(self.error(Reason::TrailingData));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, reason: Reason) -> JsonError {
+        JsonError::at(self.input, self.pos, reason)
+    }
+
+    fn unexpected(&self) -> JsonError {
+        match self.current() {
+            Some(b) => self.error(Reason::UnexpectedToken(b)),
+            None => self.error(Reason::UnexpectedEnd),
+        }
+    }
+
+    fn current(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &[u8]) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    // decodes the utf-8 character at the current position
+    fn char(&self) -> Result<char, JsonError> {
+        let len = match self.current() {
+            None => return Err(self.error(Reason::UnexpectedEnd)),
+            Some(b) if (b >> 7) == 0 => 1,
+            Some(b) if (b >> 5) == 0b110 => 2,
+            Some(b) if (b >> 4) == 0b1110 => 3,
+            Some(b) if (b >> 3) == 0b11110 => 4,
+            Some(_) => return Err(self.error(Reason::InvalidUtf8)),
+        };
+        self.input
+            .get(self.pos..self.pos + len)
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .and_then(|s| s.chars().next())
+            .ok_or_else(|| self.error(Reason::InvalidUtf8))
+    }
+
+    // whitespace and comments
+    fn skip_trivia(&mut self) -> Result<(), JsonError> {
+        loop {
+            match self.current() {
+                Some(b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c) => self.advance(),
+                // no-break space
+                Some(0xc2) if self.peek(1) == Some(0xa0) => self.pos += 2,
+                // line and paragraph separator, byte order mark
+                Some(0xe2) if self.starts_with(&[0xe2, 0x80, 0xa8]) => self.pos += 3,
+                Some(0xe2) if self.starts_with(&[0xe2, 0x80, 0xa9]) => self.pos += 3,
+                Some(0xef) if self.starts_with(&[0xef, 0xbb, 0xbf]) => self.pos += 3,
+                Some(b'/') if self.peek(1) == Some(b'/') => {
+                    while !matches!(self.current(), None | Some(b'\n' | b'\r')) {
+                        self.advance();
+                    }
+                }
+                Some(b'/') if self.peek(1) == Some(b'*') => {
+                    let start = self.pos;
+                    self.pos += 2;
+                    loop {
+                        match self.current() {
+                            Some(b'*') if self.peek(1) == Some(b'/') => {
+                                self.pos += 2;
+                                break;
+                            }
+                            Some(_) => self.advance(),
+                            None => {
+                                return Err(JsonError::at(self.input, start, Reason::UnexpectedEnd))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // open arrays and objects are kept on a stack, the parser never recurses
+    fn value(&mut self) -> Result<String, JsonError> {
+        let mut stack: Vec<Container> = Vec::new();
+        loop {
+            // |value
+            let mut value = match self.current() {
+                Some(b'{') => {
+                    self.enter(&stack)?;
+                    if self.current() == Some(b'}') {
+                        self.advance(); // {}|
+                        self.freeze("{}".to_string())
+                    } else {
+                        let key = self.entry_key()?;
+                        stack.push(Container::Object {
+                            entries: Vec::new(),
+                            key,
+                        });
+                        continue;
+                    }
+                }
+                Some(b'[') => {
+                    self.enter(&stack)?;
+                    if self.current() == Some(b']') {
+                        self.advance(); // []|
+                        self.freeze("[]".to_string())
+                    } else {
+                        stack.push(Container::Array { values: Vec::new() });
+                        continue;
+                    }
+                }
+                Some(b'"' | b'\'') => jsstring::string(&self.string()?),
+                Some(b'-' | b'+' | b'.' | b'0'..=b'9') => self.number()?,
+                Some(b'I' | b'N') => self.number()?,
+                Some(b'n') => self.literal("null")?,
+                Some(b't') => self.literal("true")?,
+                Some(b'f') => self.literal("false")?,
+                _ => return Err(self.unexpected()),
+            };
+
+            // value| closes the surrounding arrays and objects until the next value
+            loop {
+                self.skip_trivia()?;
+                let Some(container) = stack.last_mut() else {
+                    return Ok(value);
+                };
+                container.push(value);
+                let close = container.close();
+                if self.current() == Some(b',') {
+                    self.advance();
+                    self.skip_trivia()?;
+                } else if self.current() != Some(close) {
+                    return Err(self.unexpected());
+                }
+                // value,| is followed by the next value unless it is a trailing comma
+                if self.current() != Some(close) {
+                    if let Some(Container::Object { key, .. }) = stack.last_mut() {
+                        *key = self.entry_key()?;
+                    }
+                    break;
+                }
+                self.advance();
+                value = match stack.pop() {
+                    Some(container) => self.freeze(container.literal()),
+                    None => unreachable!(),
+                };
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &'static str) -> Result<String, JsonError> {
+        if !self.starts_with(literal.as_bytes()) {
+            return Err(self.unexpected());
+        }
+        self.pos += literal.len();
+        Ok(literal.to_string())
+    }
+
+    fn number(&mut self) -> Result<String, JsonError> {
+        let negative = match self.current() {
+            Some(b'-') => {
+                self.advance();
+                true
+            }
+            Some(b'+') => {
+                self.advance();
+                false
+            }
+            _ => false,
+        };
+
+        let v = if self.starts_with(b"Infinity") {
+            self.pos += "Infinity".len();
+            f64::INFINITY
+        } else if self.starts_with(b"NaN") {
+            self.pos += "NaN".len();
+            f64::NAN
+        } else if self.current() == Some(b'0') && matches!(self.peek(1), Some(b'x' | b'X')) {
+            self.pos += 2;
+            let mut v = 0f64;
+            let mut digits = 0;
+            while let Some(d) = self.current().and_then(|b| (b as char).to_digit(16)) {
+                v = v * 16.0 + d as f64;
+                digits += 1;
+                self.advance();
+            }
+            if digits == 0 {
+                return Err(self.unexpected());
+            }
+            v
+        } else {
+            let start = self.pos;
+            let mut digits = 0;
+            if self.current() == Some(b'0') && self.peek(1).is_some_and(|b| b.is_ascii_digit()) {
+                // no octal or leading zeros
+                self.advance();
+                return Err(self.unexpected());
+            }
+            while self.current().is_some_and(|b| b.is_ascii_digit()) {
+                digits += 1;
+                self.advance();
+            }
+            if self.current() == Some(b'.') {
+                self.advance();
+                while self.current().is_some_and(|b| b.is_ascii_digit()) {
+                    digits += 1;
+                    self.advance();
+                }
+            }
+            if digits == 0 {
+                return Err(self.unexpected());
+            }
+            if matches!(self.current(), Some(b'e' | b'E')) {
+                self.advance();
+                if matches!(self.current(), Some(b'+' | b'-')) {
+                    self.advance();
+                }
+                if !self.current().is_some_and(|b| b.is_ascii_digit()) {
+                    return Err(self.unexpected());
+                }
+                while self.current().is_some_and(|b| b.is_ascii_digit()) {
+                    self.advance();
+                }
+            }
+            // only ascii was consumed
+            std::str::from_utf8(&self.input[start..self.pos])
+                .ok()
+                .and_then(|s| s.parse::<f64>().ok())
+                .ok_or_else(|| JsonError::at(self.input, start, Reason::UnexpectedToken(b'.')))?
+        };
+
+        let v = if negative { -v } else { v };
+        Ok(match self.output {
+            Output::Js { .. } => jsstring::float(v),
+            Output::Json if v.is_finite() => v.to_string(),
+            Output::Json => "null".to_string(),
+        })
+    }
+
+    fn hex(&mut self, len: usize) -> Result<u32, JsonError> {
+        let mut v = 0;
+        for _ in 0..len {
+            match self.current() {
+                Some(b) if b.is_ascii_hexdigit() => {
+                    v = v * 16 + (b as char).to_digit(16).unwrap_or_default();
+                    self.advance();
+                }
+                None => return Err(self.error(Reason::UnexpectedEnd)),
+                _ => return Err(self.error(Reason::BadEscape)),
+            }
+        }
+        Ok(v)
+    }
+
+    // |"..." or |'...'
+    fn string(&mut self) -> Result<String, JsonError> {
+        let quote = self.current();
+        self.advance();
+
+        let mut s = String::new();
+        loop {
+            match self.current() {
+                None => return Err(self.error(Reason::UnexpectedEnd)),
+                b if b == quote => {
+                    self.advance();
+                    return Ok(s);
+                }
+                Some(b'\n' | b'\r') => return Err(self.error(Reason::ControlCharacter)),
+                Some(b'\\') => {
+                    self.advance();
+                    match self.current() {
+                        None => return Err(self.error(Reason::UnexpectedEnd)),
+                        Some(b'b') => s.push('\u{8}'),
+                        Some(b'f') => s.push('\u{c}'),
+                        Some(b'n') => s.push('\n'),
+                        Some(b'r') => s.push('\r'),
+                        Some(b't') => s.push('\t'),
+                        Some(b'v') => s.push('\u{b}'),
+                        Some(b'0') if !self.peek(1).is_some_and(|b| b.is_ascii_digit()) => {
+                            s.push('\0')
+                        }
+                        Some(b'0'..=b'9') => return Err(self.error(Reason::BadEscape)),
+                        Some(b'x') => {
+                            self.advance();
+                            let v = self.hex(2)?;
+                            s.push(char::from_u32(v).unwrap_or(char::REPLACEMENT_CHARACTER));
+                            continue;
+                        }
+                        Some(b'u') => {
+                            self.advance();
+                            let mut v = self.hex(4)?;
+                            // surrogate pair
+                            if (0xd800..0xdc00).contains(&v) && self.starts_with(b"\\u") {
+                                let pos = self.pos;
+                                self.pos += 2;
+                                let low = self.hex(4)?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    v = 0x10000 + ((v - 0xd800) << 10) + (low - 0xdc00);
+                                } else {
+                                    self.pos = pos;
+                                }
+                            }
+                            s.push(char::from_u32(v).unwrap_or(char::REPLACEMENT_CHARACTER));
+                            continue;
+                        }
+                        // line continuation
+                        Some(b'\n') => {}
+                        Some(b'\r') => {
+                            if self.peek(1) == Some(b'\n') {
+                                self.advance();
+                            }
+                        }
+                        Some(_) => {
+                            let c = self.char()?;
+                            if c != '\u{2028}' && c != '\u{2029}' {
+                                s.push(c);
+                            }
+                            self.pos += c.len_utf8();
+                            continue;
+                        }
+                    }
+                    self.advance();
+                }
+                Some(_) => {
+                    let c = self.char()?;
+                    s.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    // "key", 'key' or key
+    fn key(&mut self) -> Result<String, JsonError> {
+        let key = match self.current() {
+            Some(b'"' | b'\'') => self.string()?,
+            _ => {
+                let mut key = String::new();
+                loop {
+                    let c = match self.current() {
+                        Some(b) if b.is_ascii() => b as char,
+                        Some(_) => self.char()?,
+                        None => break,
+                    };
+                    let valid = match c {
+                        '$' | '_' => true,
+                        '\u{200c}' | '\u{200d}' => !key.is_empty(),
+                        c if c.is_ascii_digit() => !key.is_empty(),
+                        c => c.is_alphabetic() || (!key.is_empty() && c.is_alphanumeric()),
+                    };
+                    if !valid {
+                        break;
+                    }
+                    key.push(c);
+                    self.pos += c.len_utf8();
+                }
+                if key.is_empty() {
+                    return Err(self.unexpected());
+                }
+                key
+            }
+        };
+        Ok(match self.output {
+            // a `__proto__` key would set the prototype instead of a property
+            Output::Js { .. } if key == "__proto__" => "[\"__proto__\"]".to_string(),
+            _ => jsstring::string(&key),
+        })
+    }
+
+    // |[ or |{ to [| or {| with the following trivia
+    fn enter(&mut self, stack: &[Container]) -> Result<(), JsonError> {
+        if stack.len() >= DEFAULT_MAX_DEPTH {
+            return Err(self.error(Reason::TooDeep));
+        }
+        self.advance();
+        self.skip_trivia()
+    }
+
+    // {|key: to {key:|value
+    fn entry_key(&mut self) -> Result<String, JsonError> {
+        let key = self.key()?;
+        self.skip_trivia()?;
+        if self.current() != Some(b':') {
+            return Err(self.unexpected());
+        }
+        self.advance();
+        self.skip_trivia()?;
+        Ok(key)
+    }
+
+    fn freeze(&self, literal: String) -> String {
+        match self.output {
+            Output::Js { freeze: true } => format!("globalThis.{}.freeze({})", FREEZE, literal),
+            _ => literal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use proptest::prelude::*;
+    use rquickjs::{Context, Runtime};
+
+    use super::{is_json5, to_js, to_json};
+    use crate::cbor::{
+        con,
+        json::{self, Reason, DEFAULT_MAX_DEPTH},
+        rquickjs::DecodeOptions,
+    };
+
+    #[test]
+    fn test_json5() {
+        assert_eq!(
+            to_json(
+                br#"// config
+                {
+                    unquoted: 'single',
+                    "double": "quotes", /* block
+                    comment */
+                    hex: 0xFF,
+                    numbers: [+1, -.5, 5., 1e3, Infinity, -Infinity, NaN,],
+                    nested: {a: null, b: true, c: false,},
+                }"#
+            )
+            .unwrap(),
+            r#"{"unquoted":"single","double":"quotes","hex":255,"numbers":[1,-0.5,5,1000,null,null,null],"nested":{"a":null,"b":true,"c":false}}"#
+        );
+        assert_eq!(
+            to_js(b"[Infinity, -Infinity, NaN, -0, -1]", false).unwrap(),
+            "([Infinity,(-Infinity),NaN,(-0),(-1)])"
+        );
+        assert_eq!(
+            to_js(b"{__proto__: 1, $_a1: 2}", true).unwrap(),
+            r#"(globalThis.__ctxjs_freeze.freeze({["__proto__"]:1,"$_a1":2}))"#
+        );
+        assert_eq!(
+            to_json(
+                br#"['\x41\u00e4\uD83D\uDE00\'\"\v\0', 'a\
+b']"#
+            )
+            .unwrap(),
+            "[\"A\u{e4}\u{1f600}'\\\"\\u000b\\u0000\",\"ab\"]"
+        );
+        assert_eq!(
+            to_json("{\u{e4}\u{f6}: 1}".as_bytes()).unwrap(),
+            "{\"\u{e4}\u{f6}\":1}"
+        );
+        assert_eq!(
+            to_json(b"\"</script>\"").unwrap(),
+            "\"\\u003c/script\\u003e\""
+        );
+        assert!(is_json5(b"\xef\xbb\xbf 1 // end"));
+    }
+
+    #[test]
+    fn test_invalid_json5() {
+        for s in [
+            &b""[..],
+            b"[,]",
+            b"{,}",
+            b"[1,,]",
+            b"{a 1}",
+            b"{1a: 1}",
+            b"01",
+            b"0x",
+            b".",
+            b"1e",
+            b"'\\1'",
+            b"'a\nb'",
+            b"undefined",
+            b"/* open",
+            b"[1] 2",
+            b"'\xff'",
+        ] {
+            assert!(!is_json5(s), "{}", String::from_utf8_lossy(s));
+        }
+
+        let err = to_json(b"{\n  a: 1,\n  b: /* x */ ,\n}").unwrap_err();
+        assert_eq!(
+            (err.line, err.column, err.reason),
+            (3, 14, Reason::UnexpectedToken(b','))
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .tag(con::JSON5)
+            .and_then(|e| e.bytes(b"{__proto__: [1], nan: NaN, a: {b: 'c'},}"))
+            .unwrap();
+        let options = DecodeOptions {
+            freeze: true,
+            ..Default::default()
+        };
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            crate::cbor::rquickjs::freeze::install(&ctx).unwrap();
+            let value =
+                crate::cbor::rquickjs::decode::decode(&mut Decoder::new(&data), &ctx, options)
+                    .unwrap();
+            ctx.globals().set("value", value).unwrap();
+            assert!(ctx
+                .eval::<bool, _>(
+                    "Object.getPrototypeOf(value) === Object.prototype \
+                    && Array.isArray(value.__proto__) \
+                    && Number.isNaN(value.nan) \
+                    && value.a.b === 'c' \
+                    && Object.isFrozen(value.a)"
+                )
+                .unwrap());
+
+            let literal = crate::cbor::jsstring::decode(&mut Decoder::new(&data), options).unwrap();
+            assert!(ctx
+                .eval::<bool, _>(format!(
+                    "JSON.stringify({}) === JSON.stringify(value)",
+                    literal
+                ))
+                .unwrap());
+        });
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(is_json5(nested(DEFAULT_MAX_DEPTH).as_bytes()));
+        assert_eq!(
+            to_json(nested(DEFAULT_MAX_DEPTH + 1).as_bytes())
+                .unwrap_err()
+                .reason,
+            Reason::TooDeep
+        );
+        assert_eq!(
+            to_json(nested(100_000).as_bytes()).unwrap_err().reason,
+            Reason::TooDeep
+        );
+    }
+
+    #[test]
+    fn test_small_stack() {
+        // the parser does not recurse, so the deepest input fits on a stack
+        // as small as the one of the wasm plugin
+        let deep = format!(
+            "{}1{}",
+            "[{a:".repeat(DEFAULT_MAX_DEPTH / 2),
+            "}]".repeat(DEFAULT_MAX_DEPTH / 2)
+        );
+        let json = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || to_json(deep.as_bytes()))
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap();
+        assert!(json.starts_with("[{\"a\":[{\"a\":"));
+        assert_eq!(json::validate(json.as_bytes()), Ok(()));
+    }
+
+    proptest! {
+        #[test]
+        fn test_fuzz_to_json(input in "[\\[\\]{}'\",: \n/*0-9a-zA-Z+.\\\\-]{0,256}") {
+            // whatever is accepted becomes strict json
+            if let Ok(json) = to_json(input.as_bytes()) {
+                prop_assert_eq!(json::validate(json.as_bytes()), Ok(()));
+            }
+        }
+    }
+}
@@ -1,54 +1,189 @@
 use minicbor::{data::Type, Decoder};
 
 use crate::{
-    cbor::{self, con, rquickjs::args},
+    cbor::{
+        self, con,
+        rquickjs::{args, freeze::FREEZE, typst::CLASSES, DecodeOptions, NumberPolicy},
+        typst::TypstValue,
+    },
     strfmt,
 };
 
-pub(crate) fn decode<'a, 'js>(decoder: &'a mut Decoder) -> Result<String, minicbor::decode::Error> {
+// escapes everything which could end or change the surrounding code, like
+// line terminators or `</script>`
+pub(crate) fn string(s: &str) -> String {
+    let mut jsstring = String::with_capacity(s.len() + 2);
+    jsstring.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => jsstring += "\\\"",
+            '\\' => jsstring += "\\\\",
+            '\n' => jsstring += "\\n",
+            '\r' => jsstring += "\\r",
+            '\t' => jsstring += "\\t",
+            '<' | '>' | '\u{2028}' | '\u{2029}' => jsstring += &format!("\\u{:04x}", c as u32),
+            c if c.is_control() => jsstring += &format!("\\u{:04x}", c as u32),
+            c => jsstring.push(c),
+        }
+    }
+    jsstring.push('"');
+    jsstring
+}
+
+// negative numbers are wrapped, so they can follow any operator (`a-{x}`)
+pub(crate) fn float(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "Infinity" } else { "(-Infinity)" }.to_string()
+    } else if v == 0.0 && v.is_sign_negative() {
+        "(-0)".to_string()
+    } else if v < 0.0 {
+        format!("({})", v)
+    } else {
+        v.to_string()
+    }
+}
+
+fn integer(v: i128, numbers: NumberPolicy) -> String {
+    let suffix = if numbers.is_big_int(v) { "n" } else { "" };
+    if v < 0 {
+        format!("({}{})", v, suffix)
+    } else {
+        format!("{}{}", v, suffix)
+    }
+}
+
+fn bytes(b: &[u8]) -> String {
+    format!(
+        "new Uint8Array([{}])",
+        b.iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+fn typst_value(value: &TypstValue) -> String {
+    let args = match value {
+        TypstValue::Length { pt, em } => format!("{},{}", float(*pt), float(*em)),
+        TypstValue::Angle { deg } => float(*deg),
+        TypstValue::Ratio { ratio } => float(*ratio),
+        TypstValue::Relative { ratio, pt, em } => {
+            format!("{},{},{}", float(*ratio), float(*pt), float(*em))
+        }
+        TypstValue::Fraction { fr } => float(*fr),
+        TypstValue::Color { space, components } => format!(
+            "{},[{}]",
+            string(space),
+            components
+                .iter()
+                .map(|c| float(*c))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        TypstValue::Datetime { date, time } => [
+            date.map(|d| d.0),
+            date.map(|d| d.1),
+            date.map(|d| d.2),
+            time.map(|t| t.0),
+            time.map(|t| t.1),
+            time.map(|t| t.2),
+        ]
+        .iter()
+        .map(|v| {
+            v.map_or("null".to_string(), |v| {
+                integer(v.into(), NumberPolicy::Number)
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(","),
+        TypstValue::Duration { seconds } => float(*seconds),
+    };
+    format!(
+        "new globalThis.{}.{}({})",
+        CLASSES,
+        value.class_name(),
+        args
+    )
+}
+
+// string keys are quoted, all other keys are computed like `object.set` would do
+fn key(decoder: &mut Decoder, options: DecodeOptions) -> Result<String, minicbor::decode::Error> {
+    if decoder.datatype()? == Type::String {
+        return Ok(string(decoder.str()?));
+    }
+    Ok(format!(
+        "[{}]",
+        decode(
+            decoder,
+            DecodeOptions {
+                typst: false,
+                ..options
+            },
+        )?
+    ))
+}
+
+// see `freeze::FREEZE`
+fn deep_freeze(jsstring: String, options: DecodeOptions) -> String {
+    if options.freeze {
+        format!("globalThis.{}.deep({})", FREEZE, jsstring)
+    } else {
+        jsstring
+    }
+}
+
+fn freeze(jsstring: String, options: DecodeOptions) -> String {
+    if options.freeze {
+        format!("globalThis.{}.freeze({})", FREEZE, jsstring)
+    } else {
+        jsstring
+    }
+}
+
+pub(crate) fn decode<'a, 'js>(
+    decoder: &'a mut Decoder,
+    options: DecodeOptions,
+) -> Result<String, minicbor::decode::Error> {
     return Ok(match decoder.datatype()? {
         Type::Bool => if decoder.bool()? { "true" } else { "false" }.to_string(),
-        Type::Null => "null".to_string(),
-        Type::Undefined => "undefined".to_string(),
-        Type::U8 => decoder.u8()?.to_string(),
-        Type::U16 => decoder.u16()?.to_string(),
-        Type::U32 => decoder.u32()?.to_string(),
-        Type::U64 => decoder.u64()?.to_string(),
-        Type::I8 => decoder.i8()?.to_string(),
-        Type::I16 => decoder.i16()?.to_string(),
-        Type::I32 => decoder.i32()?.to_string(),
-        Type::I64 => decoder.i64()?.to_string(),
-        Type::Int => decoder.int()?.to_string(),
-        Type::F16 => decoder.f16()?.to_string(),
-        Type::F32 => decoder.f32()?.to_string(),
-        Type::F64 => decoder.f64()?.to_string(),
+        Type::Null => {
+            decoder.null()?;
+            "null".to_string()
+        }
+        Type::Undefined => {
+            // `undefined` is not a keyword and could be shadowed
+            decoder.undefined()?;
+            "void 0".to_string()
+        }
+        Type::U8 => integer(decoder.u8()?.into(), options.numbers),
+        Type::U16 => integer(decoder.u16()?.into(), options.numbers),
+        Type::U32 => integer(decoder.u32()?.into(), options.numbers),
+        Type::U64 => integer(decoder.u64()?.into(), options.numbers),
+        Type::I8 => integer(decoder.i8()?.into(), options.numbers),
+        Type::I16 => integer(decoder.i16()?.into(), options.numbers),
+        Type::I32 => integer(decoder.i32()?.into(), options.numbers),
+        Type::I64 => integer(decoder.i64()?.into(), options.numbers),
+        Type::Int => integer(decoder.int()?.into(), options.numbers),
+        Type::F16 => float(decoder.f16()?.into()),
+        Type::F32 => float(decoder.f32()?.into()),
+        Type::F64 => float(decoder.f64()?),
         Type::Simple => decoder.simple()?.to_string(),
         Type::Bytes => match decoder.bytes()? {
             // $ctxjs_cbor_
             [b'$', b'c', b't', b'x', b'j', b's', b'_', b'c', b'b', b'o', b'r', b'_', b @ ..] => {
-                decode(&mut Decoder::new(b))?
-            }
-            b => {
-                let mut jsstring = String::new();
-                jsstring += "new Uint8Array([";
-                let mut first = true;
-
-                for ele in b {
-                    if first {
-                        first = false
-                    } else {
-                        jsstring += ","
-                    }
-
-                    jsstring += &ele.to_string();
-                }
-
-                jsstring + "])"
+                decode(&mut Decoder::new(b), options)?
             }
+            b => bytes(b),
+        },
+        Type::String => match decoder.str()? {
+            s if options.typst => match cbor::typst::parse(s) {
+                Some(value) => deep_freeze(typst_value(&value), options),
+                None => string(s),
+            },
+            s => string(s),
         },
-        Type::String => {
-            format!("\"{}\"", decoder.str()?.replace("\"", "\\\""))
-        }
         minicbor::data::Type::Array => {
             let mut jsstring = String::new();
             jsstring += "[";
@@ -58,62 +193,58 @@ pub(crate) fn decode<'a, 'js>(decoder: &'a mut Decoder) -> Result<String, minicb
                     jsstring += ","
                 }
 
-                jsstring += &decode(decoder)?;
+                jsstring += &decode(decoder, options)?;
             }
 
-            jsstring + "]"
+            freeze(jsstring + "]", options)
         }
         minicbor::data::Type::Map => {
             let mut jsstring = String::new();
             jsstring += "{";
 
             for i in 0..super::utils::map_length(decoder)? {
-                if i == 0 {
-                    jsstring += &format!("{}:{}", decode(decoder)?, decode(decoder)?,);
-                } else {
-                    jsstring += &format!(",{}:{}", decode(decoder,)?, decode(decoder,)?,);
+                if i != 0 {
+                    jsstring += ","
                 }
+
+                jsstring += &key(decoder, options)?;
+                jsstring += ":";
+                jsstring += &decode(decoder, options)?;
             }
 
-            jsstring + "}"
+            jsstring += "}";
+            if options.freeze {
+                freeze(jsstring, options)
+            } else {
+                // otherwise it is a block at the start of a statement
+                format!("({})", jsstring)
+            }
         }
         minicbor::data::Type::Tag => match decoder.tag()? {
-            con::RAW_BYTES => {
-                let mut jsstring = String::new();
-                jsstring += "new Uint8Array([";
-                let mut first = true;
-
-                for ele in decoder.bytes()? {
-                    if first {
-                        first = false
-                    } else {
-                        jsstring += ","
-                    }
-
-                    jsstring += &ele.to_string();
-                }
-
-                jsstring + "])"
-            }
+            con::RAW_BYTES => bytes(decoder.bytes()?),
             con::EVAL => String::from_utf8(decoder.bytes()?.to_vec())
                 .map_err(|e| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e))?,
-            con::EVAL_FORMAT => String::from_utf8(
-                strfmt::strfmt(decoder.bytes()?, &args::string_map(decoder)?).map_err(|e| {
-                    minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e)
-                })?,
-            )
-            .map_err(|e| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e))?,
+            con::EVAL_FORMAT => String::from_utf8(args::format(decoder, options)?)
+                .map_err(|e| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e))?,
             con::JSON => {
-                let b = decoder.bytes()?;
-                if cbor::json::is_json(b) {
-                    String::from_utf8(b.to_vec()).map_err(|e| {
-                        minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e)
-                    })?
-                } else {
-                    Err(minicbor::decode::Error::type_mismatch(Type::Bytes)
-                        .with_message("invalid json"))?
-                }
+                // json is not a subset of js literals (`__proto__` keys)
+                deep_freeze(
+                    format!(
+                        "JSON.parse({})",
+                        string(cbor::json::validated_str(decoder.bytes()?)?)
+                    ),
+                    options,
+                )
             }
+            con::JSON5 => cbor::json5::js_literal(decoder.bytes()?, options.freeze)?,
+            con::ASSET => format!("{}.data({})", crate::host::ASSETS, string(decoder.str()?)),
+            con::TYPST => decode(
+                decoder,
+                DecodeOptions {
+                    typst: true,
+                    ..options
+                },
+            )?,
             t => {
                 return Err(minicbor::decode::Error::tag_mismatch(t)
                     .with_message(format!("unsupported tagged data {}", t)))
@@ -125,3 +256,384 @@ pub(crate) fn decode<'a, 'js>(decoder: &'a mut Decoder) -> Result<String, minicb
         }
     });
 }
+
+fn json(decoder: &mut Decoder) -> Result<String, minicbor::decode::Error> {
+    Ok(match decoder.datatype()? {
+        Type::Bool => decoder.bool()?.to_string(),
+        Type::Null => {
+            decoder.null()?;
+            "null".to_string()
+        }
+        Type::Undefined => {
+            decoder.undefined()?;
+            "null".to_string()
+        }
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::I8
+        | Type::I16
+        | Type::I32
+        | Type::I64
+        | Type::Int => i128::from(decoder.int()?).to_string(),
+        Type::F16 | Type::F32 | Type::F64 => match decoder.f64()? {
+            v if v.is_finite() => v.to_string(),
+            _ => "null".to_string(),
+        },
+        Type::Simple => decoder.simple()?.to_string(),
+        Type::Bytes => match decoder.bytes()? {
+            // $ctxjs_cbor_
+            [b'$', b'c', b't', b'x', b'j', b's', b'_', b'c', b'b', b'o', b'r', b'_', b @ ..] => {
+                json(&mut Decoder::new(b))?
+            }
+            b => format!(
+                "[{}]",
+                b.iter()
+                    .map(|b| b.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        },
+        Type::String => string(decoder.str()?),
+        Type::Array => {
+            let mut items = vec![];
+            for _ in 0..super::utils::array_length(decoder)? {
+                items.push(json(decoder)?);
+            }
+            format!("[{}]", items.join(","))
+        }
+        Type::Map => {
+            let mut entries = vec![];
+            for _ in 0..super::utils::map_length(decoder)? {
+                let key = match decoder.datatype()? {
+                    Type::String => string(decoder.str()?),
+                    _ => string(json(decoder)?.trim_matches('"')),
+                };
+                entries.push(format!("{}:{}", key, json(decoder)?));
+            }
+            format!("{{{}}}", entries.join(","))
+        }
+        Type::Tag => match decoder.tag()? {
+            con::RAW_BYTES => json(decoder)?,
+            con::TYPST => json(decoder)?,
+            con::JSON => cbor::json::validated_str(decoder.bytes()?)?.to_string(),
+            con::JSON5 => cbor::json5::json_text(decoder.bytes()?)?,
+            t => {
+                return Err(minicbor::decode::Error::tag_mismatch(t)
+                    .with_message(format!("unsupported tagged data {} in json", t)))
+            }
+        },
+        other => {
+            return Err(minicbor::decode::Error::type_mismatch(other)
+                .with_message("unknown or unsupported type"))
+        }
+    })
+}
+
+// formats a strfmt placeholder, none if the path does not exist
+pub(crate) fn format(
+    value: &[u8],
+    placeholder: &strfmt::Placeholder,
+    options: DecodeOptions,
+) -> Result<Option<String>, minicbor::decode::Error> {
+    let Some(value) = cbor::utils::lookup(value, &placeholder.path)? else {
+        return Ok(None);
+    };
+    let mut decoder = Decoder::new(value);
+
+    Ok(Some(match placeholder.specifier {
+        None => decode(&mut decoder, options)?,
+        Some("json") => json(&mut decoder)?,
+        Some("str") => match decoder.datatype()? {
+            Type::String => string(decoder.str()?),
+            _ => string(&json(&mut decoder)?),
+        },
+        Some("raw") => match decoder.datatype()? {
+            Type::String => decoder.str()?.to_string(),
+            Type::Bytes => String::from_utf8(decoder.bytes()?.to_vec())
+                .map_err(|e| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(e))?,
+            other => {
+                return Err(minicbor::decode::Error::type_mismatch(other)
+                    .with_message("raw requires a string"))
+            }
+        },
+        Some("bytes") => match decoder.datatype()? {
+            Type::String => bytes(decoder.str()?.as_bytes()),
+            Type::Bytes => bytes(decoder.bytes()?),
+            other => {
+                return Err(minicbor::decode::Error::type_mismatch(other)
+                    .with_message("bytes requires bytes or a string"))
+            }
+        },
+        Some(other) => {
+            return Err(minicbor::decode::Error::message(format!(
+                "unsupported specifier {}",
+                other
+            )))
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use proptest::prelude::*;
+    use rquickjs::{CatchResultExt, Context, Function, Runtime, Value};
+
+    use crate::cbor::rquickjs::{typst, DecodeOptions, NumberPolicy};
+
+    #[derive(Debug, Clone)]
+    enum Cbor {
+        Null,
+        Undefined,
+        Bool(bool),
+        Int(i64),
+        UInt(u64),
+        Float(f64),
+        Str(String),
+        Bytes(Vec<u8>),
+        Array(Vec<Cbor>),
+        Map(Vec<(Cbor, Cbor)>),
+    }
+
+    fn encode(encoder: &mut Encoder<&mut Vec<u8>>, value: &Cbor) {
+        match value {
+            Cbor::Null => encoder.null().map(|_| ()),
+            Cbor::Undefined => encoder.undefined().map(|_| ()),
+            Cbor::Bool(v) => encoder.bool(*v).map(|_| ()),
+            Cbor::Int(v) => encoder.i64(*v).map(|_| ()),
+            Cbor::UInt(v) => encoder.u64(*v).map(|_| ()),
+            Cbor::Float(v) => encoder.f64(*v).map(|_| ()),
+            Cbor::Str(v) => encoder.str(v).map(|_| ()),
+            Cbor::Bytes(v) => encoder.bytes(v).map(|_| ()),
+            Cbor::Array(v) => {
+                encoder.array(v.len() as _).unwrap();
+                v.iter().for_each(|v| encode(encoder, v));
+                Ok(())
+            }
+            Cbor::Map(v) => {
+                encoder.map(v.len() as _).unwrap();
+                v.iter().for_each(|(k, v)| {
+                    encode(encoder, k);
+                    encode(encoder, v);
+                });
+                Ok(())
+            }
+        }
+        .unwrap();
+    }
+
+    fn string() -> impl Strategy<Value = String> {
+        prop_oneof![
+            any::<String>(),
+            "[a-z\"'`\\\\\n\r\t\u{0}\u{2028}\u{2029}<>/${}-]*",
+            Just("</script><!--".to_string()),
+            Just("12pt + 1em".to_string()),
+            Just("rgb(\"#ff0000\")".to_string()),
+            Just("datetime(year: 2024, month: 1, day: 2)".to_string()),
+        ]
+    }
+
+    fn cbor() -> impl Strategy<Value = Cbor> {
+        let leaf = prop_oneof![
+            Just(Cbor::Null),
+            Just(Cbor::Undefined),
+            any::<bool>().prop_map(Cbor::Bool),
+            any::<i64>().prop_map(Cbor::Int),
+            any::<u64>().prop_map(Cbor::UInt),
+            any::<f64>().prop_map(Cbor::Float),
+            prop_oneof![
+                Just(f64::NAN),
+                Just(f64::INFINITY),
+                Just(f64::NEG_INFINITY),
+                Just(-0.0)
+            ]
+            .prop_map(Cbor::Float),
+            string().prop_map(Cbor::Str),
+            prop::collection::vec(any::<u8>(), 0..8).prop_map(Cbor::Bytes),
+        ];
+        let key = prop_oneof![
+            string().prop_map(Cbor::Str),
+            any::<i64>().prop_map(Cbor::Int)
+        ];
+        leaf.prop_recursive(4, 64, 8, move |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Cbor::Array),
+                prop::collection::vec((key.clone(), inner), 0..8).prop_map(Cbor::Map),
+            ]
+        })
+    }
+
+    const EQUALS: &str = r#"(a, b) => {
+      const eq = (a, b) => {
+        if (Object.is(a, b)) return true;
+        if (typeof a !== "object" || typeof b !== "object" || a === null || b === null) return false;
+        if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) return false;
+        if (ArrayBuffer.isView(a)) return a.length === b.length && a.every((v, i) => v === b[i]);
+        const ka = Reflect.ownKeys(a);
+        const kb = Reflect.ownKeys(b);
+        return ka.length === kb.length && ka.every((k, i) => k === kb[i] && eq(a[k], b[k]));
+      };
+      return eq(a, b);
+    }"#;
+
+    proptest! {
+        #[test]
+        fn test_eval_equals_decode(
+            value in cbor(),
+            numbers in prop_oneof![
+                Just(NumberPolicy::Safe),
+                Just(NumberPolicy::Number),
+                Just(NumberPolicy::BigInt)
+            ],
+            typst in any::<bool>(),
+        ) {
+            let mut data = vec![];
+            encode(&mut Encoder::new(&mut data), &value);
+            let options = DecodeOptions {
+                numbers,
+                typst,
+                ..Default::default()
+            };
+
+            let runtime = Runtime::new().unwrap();
+            let context = Context::full(&runtime).unwrap();
+            context.with(|ctx| {
+                typst::install(&ctx).unwrap();
+
+                let js = super::decode(&mut Decoder::new(&data), options).unwrap();
+                let emitted: Value = ctx
+                    .eval(js.as_str())
+                    .catch(&ctx)
+                    .map_err(|e| format!("{}: {}", js, e))
+                    .unwrap();
+                let decoded =
+                    crate::cbor::rquickjs::decode(&mut Decoder::new(&data), &ctx, options).unwrap();
+
+                let equals: Function = ctx.eval(EQUALS).unwrap();
+                let equal: bool = equals.call((emitted, decoded)).unwrap();
+                prop_assert!(equal, "{}", js);
+                Ok(())
+            })?;
+        }
+    }
+
+    #[test]
+    fn test_deep_freeze() {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data);
+        encoder
+            .array(3)
+            .and_then(|e| {
+                e.tag(crate::cbor::con::JSON)?
+                    .bytes(br#"{"a": [{"b": 1}]}"#)
+            })
+            .and_then(|e| e.str("rgb(\"#ff0000\")")?.bytes(&[1, 2]))
+            .unwrap();
+        let options = DecodeOptions {
+            typst: true,
+            freeze: true,
+            ..Default::default()
+        };
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            typst::install(&ctx).unwrap();
+            crate::cbor::rquickjs::freeze::install(&ctx).unwrap();
+            let js = super::decode(&mut Decoder::new(&data), options).unwrap();
+            let emitted: Value = ctx.eval(js.as_str()).catch(&ctx).unwrap();
+            let decoded =
+                crate::cbor::rquickjs::decode(&mut Decoder::new(&data), &ctx, options).unwrap();
+            for value in [emitted, decoded] {
+                ctx.globals().set("value", value).unwrap();
+                let frozen: Vec<bool> = ctx
+                    .eval(
+                        "[value, value[0], value[0].a, value[0].a[0], value[1], value[1].components]
+                            .map((v) => Object.isFrozen(v)).concat([Object.isFrozen(value[2])])",
+                    )
+                    .unwrap();
+                assert_eq!(frozen, [true, true, true, true, true, true, false]);
+            }
+        });
+    }
+
+    #[test]
+    fn test_literals() {
+        let options = DecodeOptions::default();
+        let emit = |value: Cbor| {
+            let mut data = vec![];
+            encode(&mut Encoder::new(&mut data), &value);
+            super::decode(&mut Decoder::new(&data), options).unwrap()
+        };
+
+        assert_eq!(
+            emit(Cbor::Str("a\\\"\n\u{2028}</script>".to_string())),
+            r#""a\\\"\n\u2028\u003c/script\u003e""#
+        );
+        assert_eq!(emit(Cbor::Float(f64::INFINITY)), "Infinity");
+        assert_eq!(emit(Cbor::Float(f64::NEG_INFINITY)), "(-Infinity)");
+        assert_eq!(emit(Cbor::Float(f64::NAN)), "NaN");
+        assert_eq!(emit(Cbor::Int(-1)), "(-1)");
+        assert_eq!(
+            emit(Cbor::Map(vec![
+                (Cbor::Str("a b".to_string()), Cbor::Null),
+                (Cbor::Int(1), Cbor::Undefined)
+            ])),
+            r#"({"a b":null,[1]:void 0})"#
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let value = Cbor::Map(vec![
+            (
+                Cbor::Str("series".to_string()),
+                Cbor::Array(vec![Cbor::Map(vec![
+                    (Cbor::Str("name".to_string()), Cbor::Str("a\"b".to_string())),
+                    (Cbor::Str("value".to_string()), Cbor::Float(f64::NAN)),
+                ])]),
+            ),
+            (
+                Cbor::Str("code".to_string()),
+                Cbor::Str("1 + 1".to_string()),
+            ),
+        ]);
+        let mut data = vec![];
+        encode(&mut Encoder::new(&mut data), &value);
+
+        let mut arguments = vec![];
+        let mut encoder = Encoder::new(&mut arguments);
+        encoder.map(1).unwrap().str("data").unwrap();
+        encode(&mut encoder, &value);
+        let arguments = crate::cbor::rquickjs::args::arguments(
+            &mut Decoder::new(&arguments),
+            Default::default(),
+        )
+        .unwrap();
+
+        let format = |s: &str| {
+            crate::strfmt::strfmt(s.as_bytes(), &arguments, false)
+                .map(|s| String::from_utf8(s).unwrap())
+                .map_err(|e| e.to_string())
+        };
+
+        assert_eq!(format("{data.series.0.name}"), Ok(r#""a\"b""#.to_string()));
+        assert_eq!(
+            format("{data.series.0:json}"),
+            Ok(r#"{"name":"a\"b","value":null}"#.to_string())
+        );
+        assert_eq!(
+            format("{data.series.0.value:str}"),
+            Ok(r#""null""#.to_string())
+        );
+        assert_eq!(format("{data.code:raw}"), Ok("1 + 1".to_string()));
+        assert_eq!(
+            format("{data.code:bytes}"),
+            Ok("new Uint8Array([49,32,43,32,49])".to_string())
+        );
+        assert_eq!(format("{data.series.1}"), Ok("{data.series.1}".to_string()));
+        assert!(format("{data.series:raw}").is_err());
+    }
+}
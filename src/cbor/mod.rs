@@ -1,5 +1,8 @@
 pub mod con;
 pub mod json;
+pub mod json5;
 pub mod jsstring;
 pub mod rquickjs;
+pub mod schema;
+pub mod typst;
 pub mod utils;
@@ -3,26 +3,103 @@ use std::collections::HashMap;
 use minicbor::Decoder;
 use rquickjs::{Ctx, Value};
 
-use crate::cbor;
+use crate::{
+    cbor::{self, rquickjs::DecodeOptions},
+    strfmt,
+};
 
 pub(crate) fn array<'js>(
     ctx: &Ctx<'js>,
     decoder: &mut Decoder,
+    options: DecodeOptions,
 ) -> Result<Vec<Value<'js>>, minicbor::decode::Error> {
     let len = cbor::utils::array_length(decoder)?;
     let mut array = Vec::with_capacity(len as _);
     for _ in 0..len {
-        array.push(cbor::rquickjs::decode(decoder, ctx)?);
+        array.push(cbor::rquickjs::decode(decoder, ctx, options)?);
     }
     Ok(array)
 }
 pub(crate) fn string_map<'js, 'd>(
-    decoder: &'d mut Decoder,
+    decoder: &mut Decoder<'d>,
+    options: DecodeOptions,
 ) -> Result<HashMap<&'d str, String>, minicbor::decode::Error> {
     let len = cbor::utils::map_length(decoder)?;
     let mut map = HashMap::with_capacity(len as _);
     for _ in 0..len {
-        map.insert(decoder.str()?, cbor::jsstring::decode(decoder)?);
+        map.insert(decoder.str()?, cbor::jsstring::decode(decoder, options)?);
     }
     Ok(map)
 }
+pub(crate) fn named<'js, 'd>(
+    ctx: &Ctx<'js>,
+    decoder: &mut Decoder<'d>,
+    options: DecodeOptions,
+) -> Result<Vec<(&'d str, Value<'js>)>, minicbor::decode::Error> {
+    let len = cbor::utils::map_length(decoder)?;
+    let mut named = Vec::with_capacity(len as _);
+    for _ in 0..len {
+        named.push((
+            decoder.str()?,
+            cbor::rquickjs::decode(decoder, ctx, options)?,
+        ));
+    }
+    Ok(named)
+}
+
+// the not yet decoded values of named arguments for strfmt
+pub(crate) struct Arguments<'b> {
+    values: HashMap<&'b str, &'b [u8]>,
+    options: DecodeOptions,
+}
+
+impl strfmt::Arguments for Arguments<'_> {
+    fn get(&self, placeholder: &strfmt::Placeholder) -> Result<Option<String>, String> {
+        match self.values.get(placeholder.name) {
+            Some(value) => cbor::jsstring::format(value, placeholder, self.options)
+                .map_err(|err| err.to_string()),
+            None => Ok(None),
+        }
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.values.keys().copied().collect()
+    }
+}
+
+pub(crate) fn arguments<'b>(
+    decoder: &mut Decoder<'b>,
+    options: DecodeOptions,
+) -> Result<Arguments<'b>, minicbor::decode::Error> {
+    let len = cbor::utils::map_length(decoder)?;
+    let mut values = HashMap::with_capacity(len as _);
+    for _ in 0..len {
+        let name = decoder.str()?;
+        let start = decoder.position();
+        decoder.skip()?;
+        values.insert(name, &decoder.input()[start..decoder.position()]);
+    }
+    Ok(Arguments { values, options })
+}
+
+// formats `[js, arguments]` or `[js, arguments, strict]`
+pub(crate) fn format(
+    decoder: &mut Decoder,
+    options: DecodeOptions,
+) -> Result<Vec<u8>, minicbor::decode::Error> {
+    let len = cbor::utils::array_length(decoder)?;
+    if len != 2 && len != 3 {
+        return Err(
+            minicbor::decode::Error::type_mismatch(minicbor::data::Type::Array)
+                .with_message("mismatch length"),
+        );
+    }
+
+    let js = decoder.bytes()?;
+    let arguments = arguments(decoder, options)?;
+    let strict = len == 3 && decoder.bool()?;
+
+    strfmt::strfmt(js, &arguments, strict).map_err(|err| {
+        minicbor::decode::Error::message(format!("can not format js string: {}", err))
+    })
+}
@@ -1,10 +1,60 @@
-use minicbor::{
-    data::{Int, Type},
-    Decoder,
-};
-use rquickjs::{context::EvalOptions, CatchResultExt, Ctx, Value};
+use minicbor::{data::Type, Decoder};
+use rquickjs::{context::EvalOptions, BigInt, CatchResultExt, Ctx, Value};
 
-use crate::{cbor::con, strfmt};
+use crate::cbor::{self, con};
+
+pub(crate) const MAX_SAFE_INTEGER: i128 = (1 << 53) - 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum NumberPolicy {
+    // number if the integer is a safe integer, otherwise a BigInt
+    #[default]
+    Safe,
+    Number,
+    BigInt,
+}
+
+impl NumberPolicy {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "safe" => Some(NumberPolicy::Safe),
+            "number" => Some(NumberPolicy::Number),
+            "bigint" => Some(NumberPolicy::BigInt),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_big_int(&self, v: i128) -> bool {
+        match self {
+            NumberPolicy::Safe => v.abs() > MAX_SAFE_INTEGER,
+            NumberPolicy::Number => false,
+            NumberPolicy::BigInt => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct DecodeOptions {
+    pub numbers: NumberPolicy,
+    // strings are interpreted as typst values (length, color, ...) if possible
+    pub typst: bool,
+    // large maps and arrays are decoded on access (see `lazy`)
+    pub lazy: bool,
+    // arrays and objects are deep-frozen
+    pub freeze: bool,
+}
+
+// bits of the per call flags byte (see `DecodeOptions::with_flags`)
+pub(crate) const DECODE_FLAG_LAZY: u8 = 1;
+pub(crate) const DECODE_FLAG_FREEZE: u8 = 2;
+
+impl DecodeOptions {
+    pub(crate) fn with_flags(mut self, flags: u8) -> Self {
+        self.lazy = flags & DECODE_FLAG_LAZY != 0;
+        self.freeze = flags & DECODE_FLAG_FREEZE != 0;
+        self
+    }
+}
 
 // pub fn decode_to_rquickjs<'b, 'js>(
 //     b: &'b [u8],
@@ -20,7 +70,8 @@ fn eval<'a, 'js>(
 ) -> Result<Value<'js>, minicbor::decode::Error> {
     let mut options = EvalOptions::default();
     options.global = true;
-    ctx.eval_with_options::<rquickjs::Value, _>(decoder.str()?, options)
+    // `value.eval` encodes the code as bytes, like the other code tags
+    ctx.eval_with_options::<rquickjs::Value, _>(decoder.bytes()?, options)
         .catch(&ctx)
         .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))
 }
@@ -28,75 +79,154 @@ fn eval<'a, 'js>(
 fn eval_format<'a, 'js>(
     decoder: &'a mut Decoder,
     ctx: &Ctx<'js>,
+    options: DecodeOptions,
 ) -> Result<Value<'js>, minicbor::decode::Error> {
-    crate::cbor::utils::array_fixed_length(decoder, 2)?;
-
-    let js = decoder.bytes()?;
-    let arguments = super::args::string_map(decoder)?;
+    let js = super::args::format(decoder, options)?;
 
-    let mut options = EvalOptions::default();
-    options.global = true;
-    ctx.eval_with_options::<rquickjs::Value, _>(
-        strfmt::strfmt(js, &arguments)
-            .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))?,
-        options,
-    )
-    .catch(&ctx)
-    .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))
+    let mut eval_options = EvalOptions::default();
+    eval_options.global = true;
+    ctx.eval_with_options::<rquickjs::Value, _>(js, eval_options)
+        .catch(&ctx)
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))
 }
 
 fn json<'a, 'js>(
     decoder: &'a mut Decoder,
     ctx: &Ctx<'js>,
+    options: DecodeOptions,
 ) -> Result<Value<'js>, minicbor::decode::Error> {
-    ctx.json_parse(decoder.str()?)
+    let value = ctx
+        .json_parse(cbor::json::validated_str(decoder.bytes()?)?)
+        .catch(&ctx)
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))?;
+    if options.freeze {
+        deep_freeze(ctx, value, Type::Bytes)
+    } else {
+        Ok(value)
+    }
+}
+
+fn json5<'a, 'js>(
+    decoder: &'a mut Decoder,
+    ctx: &Ctx<'js>,
+    options: DecodeOptions,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    // the literal only contains values, the parser already validated everything
+    ctx.eval::<rquickjs::Value, _>(cbor::json5::js_literal(decoder.bytes()?, options.freeze)?)
         .catch(&ctx)
         .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))
 }
 
+fn asset<'js>(
+    decoder: &mut Decoder,
+    ctx: &Ctx<'js>,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    let name = decoder.str()?;
+    crate::host::asset_data(ctx, name)
+        .catch(ctx)
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::String).with_message(err))?
+        .ok_or_else(|| {
+            minicbor::decode::Error::type_mismatch(Type::String)
+                .with_message(format!("unknown asset {}", name))
+        })
+}
+
+fn typst_string<'a, 'js>(
+    s: &str,
+    ctx: &Ctx<'js>,
+    options: DecodeOptions,
+) -> Result<Option<Value<'js>>, minicbor::decode::Error> {
+    let value = cbor::typst::parse(s)
+        .map(|value| super::typst::to_js(ctx, &value).catch(&ctx))
+        .transpose()
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::String).with_message(err))?;
+    match value {
+        Some(value) if options.freeze => deep_freeze(ctx, value, Type::String).map(Some),
+        value => Ok(value),
+    }
+}
+
+fn integer<'js>(
+    ctx: &Ctx<'js>,
+    v: i128,
+    numbers: NumberPolicy,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    if !numbers.is_big_int(v) {
+        return Ok(match i32::try_from(v) {
+            Ok(v) => Value::new_int(ctx.clone(), v),
+            Err(_) => Value::new_float(ctx.clone(), v as f64),
+        });
+    }
+
+    if let Ok(v) = i64::try_from(v) {
+        BigInt::from_i64(ctx.clone(), v)
+    } else if let Ok(v) = u64::try_from(v) {
+        BigInt::from_u64(ctx.clone(), v)
+    } else {
+        return Err(minicbor::decode::Error::type_mismatch(Type::Int)
+            .with_message(format!("integer {} out of range", v)));
+    }
+    .map(|v| v.into_value())
+    .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Int).with_message(err))
+}
+
+// calls a function of the hidden freeze global, see `freeze::FREEZE`
+fn call_freeze<'js>(
+    ctx: &Ctx<'js>,
+    function: &str,
+    value: Value<'js>,
+    t: Type,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    ctx.globals()
+        .get::<_, rquickjs::Object>(super::freeze::FREEZE)
+        .and_then(|freeze| freeze.get::<_, rquickjs::Function>(function))
+        .and_then(|freeze| freeze.call((value,)))
+        .catch(ctx)
+        .map_err(|err| minicbor::decode::Error::type_mismatch(t).with_message(err))
+}
+
+fn freeze<'js>(
+    ctx: &Ctx<'js>,
+    value: Value<'js>,
+    t: Type,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    call_freeze(ctx, "freeze", value, t)
+}
+
+// for values with nested objects which are not decoded one by one (json,
+// typst values), typed arrays are not frozen
+fn deep_freeze<'js>(
+    ctx: &Ctx<'js>,
+    value: Value<'js>,
+    t: Type,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    call_freeze(ctx, "deep", value, t)
+}
+
 pub(crate) fn decode<'a, 'js>(
     decoder: &'a mut Decoder,
     ctx: &Ctx<'js>,
+    options: DecodeOptions,
 ) -> Result<Value<'js>, minicbor::decode::Error> {
     Ok(match decoder.datatype()? {
         Type::Bool => rquickjs::Value::new_bool(ctx.clone(), decoder.bool()?),
-        Type::Null => Value::new_null(ctx.clone()),
-        Type::Undefined => Value::new_undefined(ctx.clone()),
-        Type::U8 => Value::new_int(ctx.clone(), decoder.u8()?.into()),
-        Type::U16 => Value::new_int(ctx.clone(), decoder.u16()?.into()),
-        Type::U32 => match decoder.u32()? {
-            v if (v as i32) as u32 == v => Value::new_int(ctx.clone(), v as i32),
-            v => Value::new_big_int(ctx.clone(), v.into()),
-        },
-        Type::U64 => Value::new_big_int(
-            ctx.clone(),
-            decoder
-                .u64()?
-                .try_into()
-                .map_err(|err: std::num::TryFromIntError| {
-                    minicbor::decode::Error::type_mismatch(Type::U64).with_message(err)
-                })?,
-        ),
-        Type::I8 => Value::new_int(ctx.clone(), decoder.i8()?.into()),
-        Type::I16 => Value::new_int(ctx.clone(), decoder.i16()?.into()),
-        Type::I32 => Value::new_int(ctx.clone(), decoder.i32()?.into()),
-        Type::I64 => Value::new_big_int(ctx.clone(), decoder.i64()?.into()),
-        Type::Int => match decoder.int()? {
-            v if v > Int::from(i32::MAX) => Value::new_big_int(
-                ctx.clone(),
-                v.try_into()
-                    .map_err(|err: minicbor::data::TryFromIntError| {
-                        minicbor::decode::Error::type_mismatch(Type::Int).with_message(err)
-                    })?,
-            ),
-            v => Value::new_int(
-                ctx.clone(),
-                v.try_into()
-                    .map_err(|err: minicbor::data::TryFromIntError| {
-                        minicbor::decode::Error::type_mismatch(Type::Int).with_message(err)
-                    })?,
-            ),
-        },
+        Type::Null => {
+            decoder.null()?;
+            Value::new_null(ctx.clone())
+        }
+        Type::Undefined => {
+            decoder.undefined()?;
+            Value::new_undefined(ctx.clone())
+        }
+        Type::U8 => integer(ctx, decoder.u8()?.into(), options.numbers)?,
+        Type::U16 => integer(ctx, decoder.u16()?.into(), options.numbers)?,
+        Type::U32 => integer(ctx, decoder.u32()?.into(), options.numbers)?,
+        Type::U64 => integer(ctx, decoder.u64()?.into(), options.numbers)?,
+        Type::I8 => integer(ctx, decoder.i8()?.into(), options.numbers)?,
+        Type::I16 => integer(ctx, decoder.i16()?.into(), options.numbers)?,
+        Type::I32 => integer(ctx, decoder.i32()?.into(), options.numbers)?,
+        Type::I64 => integer(ctx, decoder.i64()?.into(), options.numbers)?,
+        Type::Int => integer(ctx, decoder.int()?.into(), options.numbers)?,
         Type::F16 => Value::new_float(ctx.clone(), decoder.f16()?.into()),
         Type::F32 => Value::new_float(ctx.clone(), decoder.f32()?.into()),
         Type::F64 => Value::new_float(ctx.clone(), decoder.f64()?.into()),
@@ -104,7 +234,7 @@ pub(crate) fn decode<'a, 'js>(
         Type::Bytes => match decoder.bytes()? {
             // $ctxjs_cbor_
             [b'$', b'c', b't', b'x', b'j', b's', b'_', b'c', b'b', b'o', b'r', b'_', b @ ..] => {
-                decode(&mut Decoder::new(b), ctx)?
+                decode(&mut Decoder::new(b), ctx, options)?
             }
             b => rquickjs::TypedArray::new(ctx.clone(), b)
                 .map_err(|err| {
@@ -112,33 +242,79 @@ pub(crate) fn decode<'a, 'js>(
                 })?
                 .into_value(),
         },
-        Type::String => rquickjs::String::from_str(ctx.clone(), decoder.str()?)
-            .map_err(|err| minicbor::decode::Error::type_mismatch(Type::String).with_message(err))?
-            .into_value(),
+        Type::String => match decoder.str()? {
+            s if options.typst => match typst_string(s, ctx, options)? {
+                Some(value) => value,
+                None => rquickjs::String::from_str(ctx.clone(), s)
+                    .map_err(|err| {
+                        minicbor::decode::Error::type_mismatch(Type::String).with_message(err)
+                    })?
+                    .into_value(),
+            },
+            s => rquickjs::String::from_str(ctx.clone(), s)
+                .map_err(|err| {
+                    minicbor::decode::Error::type_mismatch(Type::String).with_message(err)
+                })?
+                .into_value(),
+        },
         Type::Array => {
+            let len = crate::cbor::utils::array_length(decoder)?;
+            if options.lazy && len >= super::lazy::LAZY_MIN_LENGTH {
+                return super::lazy::array(decoder, ctx, len, options);
+            }
+
             let array = rquickjs::Array::new(ctx.clone()).map_err(|err| {
                 minicbor::decode::Error::type_mismatch(Type::Array).with_message(err)
             })?;
-            for i in 0..crate::cbor::utils::array_length(decoder)? {
-                array.set(i as _, decode(decoder, ctx)?).map_err(|err| {
-                    minicbor::decode::Error::type_mismatch(Type::Array).with_message(err)
-                })?;
+            for i in 0..len {
+                array
+                    .set(i as _, decode(decoder, ctx, options)?)
+                    .map_err(|err| {
+                        minicbor::decode::Error::type_mismatch(Type::Array).with_message(err)
+                    })?;
             }
 
-            rquickjs::Value::from_array(array)
+            let array = rquickjs::Value::from_array(array);
+            if options.freeze {
+                freeze(ctx, array, Type::Array)?
+            } else {
+                array
+            }
         }
         Type::Map => {
+            let len = crate::cbor::utils::map_length(decoder)?;
+            if options.lazy && len >= super::lazy::LAZY_MIN_LENGTH {
+                if let Some(value) = super::lazy::map(decoder, ctx, len, options)? {
+                    return Ok(value);
+                }
+            }
+
             let object = rquickjs::Object::new(ctx.clone()).map_err(|err| {
                 minicbor::decode::Error::type_mismatch(Type::Map).with_message(err)
             })?;
-            for _ in 0..crate::cbor::utils::map_length(decoder)? {
+            for _ in 0..len {
                 object
-                    .set(decode(decoder, ctx)?, decode(decoder, ctx)?)
+                    .set(
+                        decode(
+                            decoder,
+                            ctx,
+                            DecodeOptions {
+                                typst: false,
+                                ..options
+                            },
+                        )?,
+                        decode(decoder, ctx, options)?,
+                    )
                     .map_err(|err| {
                         minicbor::decode::Error::type_mismatch(Type::Map).with_message(err)
                     })?;
             }
-            rquickjs::Value::from_object(object)
+            let object = rquickjs::Value::from_object(object);
+            if options.freeze {
+                freeze(ctx, object, Type::Map)?
+            } else {
+                object
+            }
         }
         Type::Tag => match decoder.tag()? {
             con::RAW_BYTES => rquickjs::TypedArray::new(ctx.clone(), decoder.bytes()?)
@@ -147,8 +323,18 @@ pub(crate) fn decode<'a, 'js>(
                 })?
                 .into_value(),
             con::EVAL => eval(decoder, ctx)?,
-            con::EVAL_FORMAT => eval_format(decoder, ctx)?,
-            con::JSON => json(decoder, ctx)?,
+            con::EVAL_FORMAT => eval_format(decoder, ctx, options)?,
+            con::JSON => json(decoder, ctx, options)?,
+            con::JSON5 => json5(decoder, ctx, options)?,
+            con::ASSET => asset(decoder, ctx)?,
+            con::TYPST => decode(
+                decoder,
+                ctx,
+                DecodeOptions {
+                    typst: true,
+                    ..options
+                },
+            )?,
             t => {
                 return Err(minicbor::decode::Error::tag_mismatch(t)
                     .with_message(format!("unsupported tagged data {}", t)))
@@ -159,3 +345,32 @@ pub(crate) fn decode<'a, 'js>(
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{Context, Runtime};
+
+    use super::{decode, DecodeOptions};
+    use crate::cbor::con;
+
+    #[test]
+    fn test_eval() {
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .tag(con::EVAL)
+            .and_then(|e| e.bytes(b"1 + 2"))
+            .unwrap();
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            let value = decode(&mut Decoder::new(&data), &ctx, DecodeOptions::default()).unwrap();
+            assert_eq!(value.as_int(), Some(3));
+            let js =
+                crate::cbor::jsstring::decode(&mut Decoder::new(&data), DecodeOptions::default())
+                    .unwrap();
+            assert_eq!(js, "1 + 2");
+        });
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt;
 
+use crate::cbor::rquickjs::MAX_SAFE_INTEGER;
 use crate::cbor::utils::get_typed_array_type;
 
 use crate::cbor::utils::TypedArrayType;
@@ -7,6 +8,12 @@ use minicbor::{encode::Write, Encoder};
 
 pub type Result<T, W> = std::result::Result<T, Error<W>>;
 
+#[derive(Clone, Copy, Default)]
+pub(crate) struct EncodeOptions {
+    // floats without a fractional part (and in the safe integer range) are encoded as integers
+    pub integral_floats_as_ints: bool,
+}
+
 pub enum Error<W: Write> {
     CborEncode(minicbor::encode::Error<W::Error>),
     RquickJSError(rquickjs::Error),
@@ -71,9 +78,12 @@ macro_rules! encode_rquickjs_typed_array {
     }};
 }
 
-pub fn encode_to_bytes<'js>(v: &rquickjs::Value<'js>) -> Result<Vec<u8>, Vec<u8>> {
+pub(crate) fn encode_to_bytes<'js>(
+    v: &rquickjs::Value<'js>,
+    options: EncodeOptions,
+) -> Result<Vec<u8>, Vec<u8>> {
     let mut encoder = Encoder::new(Vec::new());
-    encode(&mut encoder, v)?;
+    encode(&mut encoder, v, options)?;
     Ok(encoder.into_writer())
 }
 
@@ -120,9 +130,27 @@ fn encode_typed_array<'a, 'js, W: Write>(
     }
 }
 
+fn encode_float<'a, W: Write>(
+    encoder: &'a mut Encoder<W>,
+    v: f64,
+    options: EncodeOptions,
+) -> Result<&'a mut Encoder<W>, W> {
+    // -0.0 stays a float, an integer has no sign for zero
+    if options.integral_floats_as_ints
+        && v.fract() == 0.0
+        && v.abs() <= MAX_SAFE_INTEGER as f64
+        && !(v == 0.0 && v.is_sign_negative())
+    {
+        Ok(encoder.i64(v as i64)?)
+    } else {
+        Ok(encoder.f64(v)?)
+    }
+}
+
 fn encode<'a, 'js, W: Write>(
     encoder: &'a mut Encoder<W>,
     v: &rquickjs::Value<'js>,
+    options: EncodeOptions,
 ) -> Result<&'a mut Encoder<W>, W> {
     Ok(match v.type_of() {
         rquickjs::Type::Uninitialized => encoder.undefined()?,
@@ -134,9 +162,13 @@ fn encode<'a, 'js, W: Write>(
         rquickjs::Type::Int => encoder.i32(v.as_int().ok_or_else(|| {
             rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::Int.as_str())
         })?)?,
-        rquickjs::Type::Float => encoder.f64(v.as_float().ok_or_else(|| {
-            rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::Float.as_str())
-        })?)?,
+        rquickjs::Type::Float => encode_float(
+            encoder,
+            v.as_float().ok_or_else(|| {
+                rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::Float.as_str())
+            })?,
+            options,
+        )?,
         rquickjs::Type::String => encoder.str(
             &v.as_string()
                 .ok_or_else(|| {
@@ -154,7 +186,7 @@ fn encode<'a, 'js, W: Write>(
             } else {
                 encoder.array(arr.len() as _)?;
                 for item in arr.values() {
-                    encode(encoder, &item?)?;
+                    encode(encoder, &item?, options)?;
                 }
                 encoder
             }
@@ -166,6 +198,7 @@ fn encode<'a, 'js, W: Write>(
                     rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::Promise.as_str())
                 })?
                 .finish()?,
+            options,
         )?,
         rquickjs::Type::Object => {
             let object = v.as_object().ok_or_else(|| {
@@ -174,25 +207,173 @@ fn encode<'a, 'js, W: Write>(
 
             if let Some(t) = get_typed_array_type(object) {
                 encode_typed_array(encoder, object, t)?
+            } else if let Some(s) = super::typst::to_typst_string(object)? {
+                encoder.str(&s)?
             } else {
                 encoder.map(object.len() as _)?;
                 for key in object.keys::<String>() {
                     let key = key?;
                     let value = object.get(&key)?;
                     encoder.str(&key)?;
-                    encode(encoder, &value)?;
+                    encode(encoder, &value, options)?;
                 }
                 encoder
             }
         }
-        rquickjs::Type::BigInt => encoder.i64(
-            v.as_big_int()
-                .ok_or_else(|| {
-                    rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::BigInt.as_str())
-                })?
-                .clone()
-                .to_i64()?,
-        )?,
+        rquickjs::Type::Proxy => {
+            let object = v.as_object().ok_or_else(|| {
+                rquickjs::Error::new_from_js(v.type_name(), rquickjs::Type::Object.as_str())
+            })?;
+
+            let is_array: bool = object
+                .ctx()
+                .globals()
+                .get::<_, rquickjs::Object>("Array")?
+                .get::<_, rquickjs::Function>("isArray")?
+                .call((v.clone(),))?;
+
+            if is_array {
+                let len: usize = object.get("length")?;
+                encoder.array(len as _)?;
+                for i in 0..len {
+                    encode(encoder, &object.get(i as u32)?, options)?;
+                }
+            } else {
+                let keys = object
+                    .keys::<String>()
+                    .collect::<rquickjs::Result<Vec<_>>>()?;
+                encoder.map(keys.len() as _)?;
+                for key in keys {
+                    let value = object.get(&key)?;
+                    encoder.str(&key)?;
+                    encode(encoder, &value, options)?;
+                }
+            }
+            encoder
+        }
+        // BigInt::to_i64 wraps around, so the decimal digits are parsed to
+        // encode values up to u64::MAX and to reject larger ones
+        rquickjs::Type::BigInt => {
+            let rquickjs::convert::Coerced(digits) =
+                v.get::<rquickjs::convert::Coerced<String>>()?;
+            if let Ok(n) = digits.parse::<i64>() {
+                encoder.i64(n)?
+            } else if let Ok(n) = digits.parse::<u64>() {
+                encoder.u64(n)?
+            } else {
+                Err(rquickjs::Error::new_from_js_message(
+                    "bigint",
+                    "integer",
+                    format!("{} is out of the 64-bit integer range", digits),
+                ))?
+            }
+        }
         t => Err(rquickjs::Error::new_from_js(t.as_str(), "Vec<u8>"))?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{Context, Ctx, Runtime};
+
+    use super::{encode_to_bytes, EncodeOptions};
+    use crate::cbor::rquickjs::{decode, DecodeOptions, NumberPolicy};
+
+    fn with_ctx(test: impl for<'js> FnOnce(&Ctx<'js>)) {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| test(&ctx));
+    }
+
+    fn encode_js(ctx: &Ctx, js: &str, integral_floats_as_ints: bool) -> Vec<u8> {
+        let value = ctx.eval(js).unwrap();
+        let options = EncodeOptions {
+            integral_floats_as_ints,
+        };
+        encode_to_bytes(&value, options).unwrap()
+    }
+
+    fn cbor(encode: impl FnOnce(&mut Encoder<Vec<u8>>)) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new());
+        encode(&mut encoder);
+        encoder.into_writer()
+    }
+
+    #[test]
+    fn test_integral_floats_as_ints() {
+        with_ctx(|ctx| {
+            assert_eq!(
+                encode_js(ctx, "2.5 * 2", true),
+                cbor(|e| {
+                    e.i64(5).unwrap();
+                })
+            );
+            assert_eq!(
+                encode_js(ctx, "2.5 * 2", false),
+                cbor(|e| {
+                    e.f64(5.0).unwrap();
+                })
+            );
+            assert_eq!(
+                encode_js(ctx, "2.5", true),
+                cbor(|e| {
+                    e.f64(2.5).unwrap();
+                })
+            );
+            assert_eq!(
+                encode_js(ctx, "-0", true),
+                cbor(|e| {
+                    e.f64(-0.0).unwrap();
+                })
+            );
+            assert_eq!(
+                encode_js(ctx, "2 ** 60", true),
+                cbor(|e| {
+                    e.f64(2f64.powi(60)).unwrap();
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn test_roundtrip_numbers() {
+        with_ctx(|ctx| {
+            for (numbers, v, js_type) in [
+                (NumberPolicy::BigInt, 42i64, "bigint"),
+                (NumberPolicy::Safe, 42, "number"),
+                (NumberPolicy::Safe, 1 << 60, "bigint"),
+                (NumberPolicy::Safe, -(1 << 60), "bigint"),
+                (NumberPolicy::Number, -7, "number"),
+            ] {
+                let data = cbor(|e| {
+                    e.i64(v).unwrap();
+                });
+                let options = DecodeOptions {
+                    numbers,
+                    ..Default::default()
+                };
+                let value = decode(&mut Decoder::new(&data), ctx, options).unwrap();
+                ctx.globals().set("value", value.clone()).unwrap();
+                assert_eq!(ctx.eval::<String, _>("typeof value").unwrap(), js_type);
+
+                let encoded = encode_to_bytes(&value, EncodeOptions::default()).unwrap();
+                assert_eq!(Decoder::new(&encoded).i64().unwrap(), v);
+            }
+
+            let data = cbor(|e| {
+                e.u64(u64::MAX).unwrap();
+            });
+            let options = DecodeOptions {
+                numbers: NumberPolicy::BigInt,
+                ..Default::default()
+            };
+            let value = decode(&mut Decoder::new(&data), ctx, options).unwrap();
+            let encoded = encode_to_bytes(&value, EncodeOptions::default()).unwrap();
+            assert_eq!(Decoder::new(&encoded).u64().unwrap(), u64::MAX);
+
+            let value = ctx.eval("2n ** 64n").unwrap();
+            assert!(encode_to_bytes(&value, EncodeOptions::default()).is_err());
+        });
+    }
+}
@@ -0,0 +1,189 @@
+use minicbor::{data::Type, Decoder};
+use rquickjs::{
+    context::EvalOptions, function::Args, CatchResultExt, Ctx, Function, Object, Value,
+};
+
+use crate::cbor::rquickjs::{args, DecodeOptions};
+
+// hidden global which caches the compiled functions by parameters and source
+const CACHE: &str = "__ctxjs_eval_with";
+
+// the oldest function is dropped when the cache is full
+const CACHE_SIZE: usize = 256;
+
+const PRELUDE: &str = r##"((limit) => {
+  const cache = new Map();
+  Object.defineProperty(globalThis, "__ctxjs_eval_with", {
+    value: Object.freeze({
+      get: (key) => cache.get(key),
+      set: (key, func) => {
+        if (cache.size >= limit) cache.delete(cache.keys().next().value);
+        cache.set(key, func);
+      },
+      size: () => cache.size,
+    }),
+  });
+})"##;
+
+pub(crate) fn install(ctx: &Ctx) -> rquickjs::Result<()> {
+    ctx.eval::<Function, _>(PRELUDE)?
+        .call::<_, ()>((CACHE_SIZE,))
+}
+
+fn compile_with_options<'js>(ctx: &Ctx<'js>, source: String) -> rquickjs::Result<Function<'js>> {
+    let mut options = EvalOptions::default();
+    options.global = true;
+    options.strict = false;
+    ctx.eval_with_options(source, options)
+}
+
+// a single expression is returned, everything else has to use `return`
+fn compile<'js>(
+    ctx: &Ctx<'js>,
+    js: &str,
+    names: &[&str],
+) -> Result<Function<'js>, minicbor::decode::Error> {
+    let params = names.join(",");
+    let key = format!("{}\n{}", params, js);
+
+    let cache: Object = ctx
+        .globals()
+        .get(CACHE)
+        .map_err(minicbor::decode::Error::message)?;
+    if let Some(func) = cache
+        .get::<_, Function>("get")
+        .and_then(|get| get.call::<_, Option<Function>>((key.as_str(),)))
+        .map_err(minicbor::decode::Error::message)?
+    {
+        return Ok(func);
+    }
+
+    let func = match compile_with_options(
+        ctx,
+        format!("(function({}) {{ return (\n{}\n); }})", params, js),
+    )
+    .catch(ctx)
+    {
+        Ok(func) => func,
+        Err(expression) => {
+            compile_with_options(ctx, format!("(function({}) {{\n{}\n}})", params, js))
+                .catch(ctx)
+                .map_err(|body| {
+                    minicbor::decode::Error::message(format!(
+                        "compile error: {} (as expression: {})",
+                        body.to_string().trim_end(),
+                        expression.to_string().trim_end()
+                    ))
+                })?
+        }
+    };
+
+    cache
+        .get::<_, Function>("set")
+        .and_then(|set| set.call::<_, ()>((key, func.clone())))
+        .map_err(minicbor::decode::Error::message)?;
+    Ok(func)
+}
+
+pub(crate) fn eval_with<'js>(
+    ctx: &Ctx<'js>,
+    js: &[u8],
+    decoder: &mut Decoder,
+    options: DecodeOptions,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    let js = std::str::from_utf8(js)
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Bytes).with_message(err))?;
+
+    let named = args::named(ctx, decoder, options)?;
+    if let Some((name, _)) = named
+        .iter()
+        .find(|(name, _)| !crate::define_vars::is_identifier(name))
+    {
+        return Err(minicbor::decode::Error::message(format!(
+            "invalid argument name {}",
+            name
+        )));
+    }
+
+    let names: Vec<&str> = named.iter().map(|(name, _)| *name).collect();
+    let func = compile(ctx, js, &names)?;
+
+    let mut args = Args::new(ctx.clone(), named.len());
+    args.push_args(named.into_iter().map(|(_, value)| value))
+        .map_err(minicbor::decode::Error::message)?;
+
+    func.call_arg(args)
+        .catch(ctx)
+        .map_err(|err| minicbor::decode::Error::message(format!("eval error: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{Context, Ctx, Runtime};
+
+    use super::eval_with;
+
+    fn run<'js>(ctx: &Ctx<'js>, js: &str, a: i64, b: i64) -> Result<i64, String> {
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .map(2)
+            .and_then(|e| e.str("a")?.i64(a)?.str("b")?.i64(b))
+            .unwrap();
+        eval_with(
+            ctx,
+            js.as_bytes(),
+            &mut Decoder::new(&data),
+            Default::default(),
+        )
+        .map_err(|e| e.to_string())
+        .and_then(|v| v.as_int().map(|v| v as i64).ok_or("not an int".to_string()))
+    }
+
+    #[test]
+    fn test_eval_with() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+
+            assert_eq!(run(&ctx, "a + b", 1, 2), Ok(3));
+            assert_eq!(run(&ctx, "a + b", 3, 4), Ok(7));
+            assert_eq!(run(&ctx, "const c = a * b;\nreturn c;", 3, 4), Ok(12));
+            assert_eq!(run(&ctx, "a - b // comment", 3, 4), Ok(-1));
+            assert!(run(&ctx, "a +", 1, 2).is_err());
+
+            let cached: i64 = ctx.eval("globalThis.__ctxjs_eval_with.size()").unwrap();
+            assert_eq!(cached, 3);
+
+            // both errors are reported
+            let err = run(&ctx, "a +", 1, 2).unwrap_err();
+            assert!(err.contains("'}'") && err.contains("')'"), "{}", err);
+        });
+    }
+
+    #[test]
+    fn test_cache() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+
+            // the same code with other names is another function
+            assert_eq!(run(&ctx, "a", 1, 2), Ok(1));
+            let mut data = vec![];
+            Encoder::new(&mut data)
+                .map(1)
+                .and_then(|e| e.str("x")?.i64(5))
+                .unwrap();
+            assert!(eval_with(&ctx, b"a", &mut Decoder::new(&data), Default::default()).is_err());
+
+            for i in 0..super::CACHE_SIZE + 10 {
+                assert_eq!(run(&ctx, &format!("a + {}", i), 1, 0), Ok(1 + i as i64));
+            }
+            let cached: usize = ctx.eval("globalThis.__ctxjs_eval_with.size()").unwrap();
+            assert_eq!(cached, super::CACHE_SIZE);
+            assert_eq!(run(&ctx, "a + 0", 1, 0), Ok(1));
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use rquickjs::Ctx;
+
+// hidden global with `freeze` and `deep` (deep-freeze), the builtins are
+// captured at install, so js code which replaces `Object.freeze` can not turn
+// freezing of decoded values off
+pub(crate) const FREEZE: &str = "__ctxjs_freeze";
+
+const PRELUDE: &str = r##"(() => {
+  const { freeze, isFrozen, defineProperty } = Object;
+  const { ownKeys } = Reflect;
+  const { isView } = ArrayBuffer;
+  const Buffer = ArrayBuffer;
+
+  // frozen before the properties, so cycles end, typed arrays and array
+  // buffers can not be frozen and are skipped
+  const deep = (value) => {
+    if (value === null || typeof value !== "object" || isFrozen(value)) return value;
+    if (isView(value) || value instanceof Buffer) return value;
+    freeze(value);
+    for (const key of ownKeys(value)) deep(value[key]);
+    return value;
+  };
+
+  defineProperty(globalThis, "__ctxjs_freeze", { value: freeze({ freeze, deep }) });
+})();"##;
+
+pub(crate) fn install(ctx: &Ctx) -> rquickjs::Result<()> {
+    ctx.eval::<(), _>(PRELUDE)
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{Context, Runtime};
+
+    use crate::cbor::rquickjs::{decode, DecodeOptions};
+
+    #[test]
+    fn test_replaced_object_freeze() {
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .array(2)
+            .and_then(|e| e.map(1)?.str("a")?.u8(1))
+            .and_then(|e| e.tag(crate::cbor::con::JSON)?.bytes(br#"{"b": [1]}"#))
+            .unwrap();
+        let options = DecodeOptions {
+            freeze: true,
+            ..Default::default()
+        };
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+            ctx.eval::<(), _>("Object.freeze = (value) => value;")
+                .unwrap();
+            let value = decode(&mut Decoder::new(&data), &ctx, options).unwrap();
+            let js = crate::cbor::jsstring::decode(&mut Decoder::new(&data), options).unwrap();
+            let emitted: rquickjs::Value = ctx.eval(js.as_str()).unwrap();
+            for value in [value, emitted] {
+                ctx.globals().set("value", value).unwrap();
+                let frozen: Vec<bool> = ctx
+                    .eval("[value, value[0], value[1], value[1].b].map((v) => Object.isFrozen(v))")
+                    .unwrap();
+                assert_eq!(frozen, [true, true, true, true]);
+            }
+        });
+    }
+}
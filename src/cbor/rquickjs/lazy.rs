@@ -0,0 +1,337 @@
+use std::{collections::HashMap, rc::Rc};
+
+use minicbor::{data::Type, Decoder};
+use rquickjs::{Array, Ctx, Exception, Function, IntoJs, Object, Value};
+
+use crate::cbor::{self, rquickjs::DecodeOptions};
+
+// maps and arrays with less entries are always decoded directly
+pub(crate) const LAZY_MIN_LENGTH: u64 = 128;
+
+// hidden global which holds the proxy factory
+const FACTORY: &str = "__ctxjs_lazy";
+
+const PRELUDE: &str = r##"(() => {
+  const index = (p) => {
+    if (typeof p !== "string") return -1;
+    const i = Number(p);
+    return Number.isInteger(i) && i >= 0 && String(i) === p ? i : -1;
+  };
+
+  // keys are the not yet decoded map keys or the length of the array,
+  // a frozen proxy rejects all changes without decoding everything like Object.freeze would,
+  // so Object.isFrozen stays false for it (its target has to stay extensible)
+  const lazy = (target, keys, decode, frozen) => {
+    const isArray = Array.isArray(target);
+    const pending = isArray ? new Uint8Array(keys).fill(1) : new Set(keys);
+    const order = isArray ? null : keys;
+    const orderSet = isArray ? null : new Set(keys);
+    const has = isArray
+      ? (p) => { const i = index(p); return i >= 0 && i < pending.length && pending[i] === 1; }
+      : (p) => pending.has(p);
+    const drop = isArray ? (p) => { pending[index(p)] = 0; } : (p) => pending.delete(p);
+    const load = (t, p) => {
+      if (has(p)) {
+        const value = decode(p);
+        drop(p);
+        t[p] = value;
+      }
+    };
+    const loadAll = (t) => {
+      if (isArray) {
+        for (let i = 0; i < pending.length; i++) load(t, String(i));
+      } else {
+        for (const k of [...pending]) load(t, k);
+      }
+    };
+
+    return new Proxy(target, {
+      get(t, p, r) { load(t, p); return Reflect.get(t, p, r); },
+      set(t, p, v) { if (frozen) return false; drop(p); return Reflect.set(t, p, v); },
+      has(t, p) { return has(p) || Reflect.has(t, p); },
+      deleteProperty(t, p) { if (frozen) return false; drop(p); return Reflect.deleteProperty(t, p); },
+      defineProperty(t, p, d) { if (frozen) return false; load(t, p); return Reflect.defineProperty(t, p, d); },
+      getOwnPropertyDescriptor(t, p) { load(t, p); return Reflect.getOwnPropertyDescriptor(t, p); },
+      preventExtensions(t) { loadAll(t); return Reflect.preventExtensions(t); },
+      ownKeys(t) {
+        const own = Reflect.ownKeys(t);
+        if (isArray) {
+          const keys = [];
+          for (let i = 0; i < pending.length; i++) {
+            if (pending[i] === 1 || Object.hasOwn(t, i)) keys.push(String(i));
+          }
+          return keys.concat(own.filter((k) => { const i = index(k); return i < 0 || i >= pending.length; }));
+        }
+        return order
+          .filter((k) => pending.has(k) || Object.hasOwn(t, k))
+          .concat(own.filter((k) => !orderSet.has(k)));
+      },
+    });
+  };
+
+  Object.defineProperty(globalThis, "__ctxjs_lazy", { value: lazy });
+})();"##;
+
+pub(crate) fn install(ctx: &Ctx) -> rquickjs::Result<()> {
+    ctx.eval::<(), _>(PRELUDE)
+}
+
+// the cbor data of one map or array, offsets are relative to the buffer
+struct Entries<K> {
+    buffer: Vec<u8>,
+    offsets: K,
+    options: DecodeOptions,
+}
+
+impl<K> Entries<K> {
+    fn decode<'js>(&self, ctx: &Ctx<'js>, offset: usize) -> rquickjs::Result<Value<'js>> {
+        cbor::rquickjs::decode(&mut Decoder::new(&self.buffer[offset..]), ctx, self.options)
+            .map_err(|err| Exception::throw_type(ctx, &format!("lazy decode error: {}", err)))
+    }
+}
+
+fn proxy<'js>(
+    ctx: &Ctx<'js>,
+    target: Object<'js>,
+    keys: impl IntoJs<'js>,
+    decode: Function<'js>,
+    options: DecodeOptions,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    ctx.globals()
+        .get::<_, Function>(FACTORY)
+        .and_then(|factory| factory.call((target, keys, decode, options.freeze)))
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Map).with_message(err))
+}
+
+pub(crate) fn array<'js>(
+    decoder: &mut Decoder,
+    ctx: &Ctx<'js>,
+    len: u64,
+    options: DecodeOptions,
+) -> Result<Value<'js>, minicbor::decode::Error> {
+    let start = decoder.position();
+    let mut offsets = Vec::with_capacity(len as _);
+    for _ in 0..len {
+        offsets.push(decoder.position() - start);
+        decoder.skip()?;
+    }
+
+    let entries = Rc::new(Entries {
+        buffer: decoder.input()[start..decoder.position()].to_vec(),
+        offsets,
+        options,
+    });
+
+    let decode = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, key: String| -> rquickjs::Result<Value<'js>> {
+            match key
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| entries.offsets.get(i))
+            {
+                Some(offset) => entries.decode(&ctx, *offset),
+                None => Ok(Value::new_undefined(ctx)),
+            }
+        },
+    )
+    .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Array).with_message(err))?;
+
+    let target = Array::new(ctx.clone())
+        .and_then(|array| {
+            array.as_object().set("length", len)?;
+            Ok(array.into_object())
+        })
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Array).with_message(err))?;
+
+    proxy(ctx, target, len as f64, decode, options)
+}
+
+// returns none if the map contains keys which are not strings
+pub(crate) fn map<'js>(
+    decoder: &mut Decoder,
+    ctx: &Ctx<'js>,
+    len: u64,
+    options: DecodeOptions,
+) -> Result<Option<Value<'js>>, minicbor::decode::Error> {
+    let start = decoder.position();
+    let mut keys: Vec<String> = Vec::with_capacity(len as _);
+    let mut offsets = HashMap::with_capacity(len as _);
+    for _ in 0..len {
+        if decoder.datatype()? != Type::String {
+            decoder.set_position(start);
+            return Ok(None);
+        }
+        let key = decoder.str()?.to_string();
+        offsets.insert(key.clone(), decoder.position() - start);
+        keys.push(key);
+        decoder.skip()?;
+    }
+
+    let entries = Rc::new(Entries {
+        buffer: decoder.input()[start..decoder.position()].to_vec(),
+        offsets,
+        options,
+    });
+
+    let decode = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, key: String| -> rquickjs::Result<Value<'js>> {
+            match entries.offsets.get(&key) {
+                Some(offset) => entries.decode(&ctx, *offset),
+                None => Ok(Value::new_undefined(ctx)),
+            }
+        },
+    )
+    .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Map).with_message(err))?;
+
+    let target = Object::new(ctx.clone())
+        .map_err(|err| minicbor::decode::Error::type_mismatch(Type::Map).with_message(err))?;
+
+    proxy(ctx, target, keys, decode, options).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{Context, Ctx, Runtime};
+
+    use crate::cbor::rquickjs::{decode, DecodeOptions};
+
+    fn with_lazy(data: &[u8], freeze: bool, test: impl for<'js> FnOnce(&Ctx<'js>)) {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+            crate::cbor::rquickjs::freeze::install(&ctx).unwrap();
+            let options = DecodeOptions {
+                lazy: true,
+                freeze,
+                ..Default::default()
+            };
+            let value = decode(&mut Decoder::new(data), &ctx, options).unwrap();
+            ctx.globals().set("value", value).unwrap();
+            test(&ctx);
+        });
+    }
+
+    fn eval<'js>(ctx: &Ctx<'js>, js: &str) -> String {
+        ctx.eval::<String, _>(js).unwrap()
+    }
+
+    #[test]
+    fn test_array() {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data);
+        encoder.array(200).unwrap();
+        for i in 0..200u32 {
+            encoder.map(1).unwrap().str("i").unwrap().u32(i).unwrap();
+        }
+
+        with_lazy(&data, false, |ctx| {
+            assert_eq!(eval(ctx, "String(value.length)"), "200");
+            assert_eq!(eval(ctx, "String(value[150].i)"), "150");
+            assert_eq!(eval(ctx, "String(value[150] === value[150])"), "true");
+            assert_eq!(eval(ctx, "String(value[200])"), "undefined");
+            assert_eq!(
+                eval(ctx, "String(value.map((v) => v.i).reduce((a, b) => a + b))"),
+                "19900"
+            );
+            assert_eq!(eval(ctx, "String([...value].length)"), "200");
+            assert_eq!(eval(ctx, "String(Object.keys(value).length)"), "200");
+            assert_eq!(eval(ctx, "String(Array.isArray(value))"), "true");
+            assert_eq!(
+                eval(ctx, "JSON.stringify(value.slice(0, 2))"),
+                r#"[{"i":0},{"i":1}]"#
+            );
+        });
+    }
+
+    #[test]
+    fn test_map() {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data);
+        encoder.map(200).unwrap();
+        for i in 0..200u32 {
+            encoder.str(&format!("k{}", i)).unwrap().u32(i).unwrap();
+        }
+
+        with_lazy(&data, false, |ctx| {
+            assert_eq!(eval(ctx, "String(value.k42)"), "42");
+            assert_eq!(eval(ctx, "String('k199' in value)"), "true");
+            assert_eq!(eval(ctx, "String('k200' in value)"), "false");
+            assert_eq!(
+                eval(ctx, "Object.keys(value).slice(0, 3).join()"),
+                "k0,k1,k2"
+            );
+            assert_eq!(
+                eval(ctx, "String(Object.values(value).reduce((a, b) => a + b))"),
+                "19900"
+            );
+            assert_eq!(
+                eval(
+                    ctx,
+                    "value.k0 = 'x'; delete value.k1; Object.keys(value).slice(0, 2).join()"
+                ),
+                "k0,k2"
+            );
+            assert_eq!(eval(ctx, "value.k0"), "x");
+        });
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data);
+        encoder.array(130).unwrap();
+        for i in 0..130u32 {
+            encoder.u32(i).unwrap();
+        }
+
+        with_lazy(&data, false, |ctx| {
+            let value: rquickjs::Value = ctx.globals().get("value").unwrap();
+            let encoded =
+                crate::cbor::rquickjs::encode_to_bytes(&value, Default::default()).unwrap();
+            assert_eq!(encoded, data);
+        });
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut data = vec![];
+        let mut encoder = Encoder::new(&mut data);
+        encoder.map(200).unwrap();
+        for i in 0..200u32 {
+            encoder.str(&format!("k{}", i)).unwrap();
+            encoder.array(1).unwrap().u32(i).unwrap();
+        }
+
+        with_lazy(&data, true, |ctx| {
+            assert_eq!(
+                eval(
+                    ctx,
+                    "'use strict'; try { value.k0 = 1; 'changed' } catch (e) { e.name }"
+                ),
+                "TypeError"
+            );
+            assert_eq!(
+                eval(
+                    ctx,
+                    "try { delete value.k1; } catch (e) {} String(value.k1[0])"
+                ),
+                "1"
+            );
+            // the proxy itself is not frozen, its values are
+            assert_eq!(eval(ctx, "String(Object.isFrozen(value))"), "false");
+            assert_eq!(eval(ctx, "String(Object.isExtensible(value))"), "true");
+            assert_eq!(eval(ctx, "String(Object.isFrozen(value.k2))"), "true");
+            assert_eq!(
+                eval(
+                    ctx,
+                    "try { value.k2.push(3); 'changed' } catch (e) { e.name }"
+                ),
+                "TypeError"
+            );
+        });
+    }
+}
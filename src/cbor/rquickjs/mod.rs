@@ -1,6 +1,10 @@
 pub mod args;
 pub mod decode;
 pub mod encode;
+pub mod eval_with;
+pub mod freeze;
+pub mod lazy;
+pub mod typst;
 
 pub(crate) use decode::*;
 pub(crate) use encode::*;
@@ -0,0 +1,206 @@
+use rquickjs::{
+    function::{Constructor, This},
+    Ctx, Function, Object, Value,
+};
+
+use crate::cbor::typst::TypstValue;
+
+// hidden global which holds the classes, `typst` is only an alias for js code
+pub(crate) const CLASSES: &str = "__ctxjs_typst";
+
+const PRELUDE: &str = r##"(() => {
+  const fmt = (n) => String(Math.round(n * 1e9) / 1e9);
+  const pct = (n) => fmt(n * 100) + "%";
+  const hex = (n) => Math.round(Math.min(Math.max(n, 0), 1) * 255).toString(16).padStart(2, "0");
+
+  class TypstValue {
+    toJSON() { return this.toString(); }
+  }
+
+  class Length extends TypstValue {
+    constructor(pt = 0, em = 0) { super(); this.pt = pt; this.em = em; }
+    toPt(fontSize = 11) { return this.pt + this.em * fontSize; }
+    toMm(fontSize) { return this.toPt(fontSize) * 25.4 / 72; }
+    toCm(fontSize) { return this.toPt(fontSize) * 2.54 / 72; }
+    toIn(fontSize) { return this.toPt(fontSize) / 72; }
+    toPx(dpi = 96, fontSize) { return this.toPt(fontSize) * dpi / 72; }
+    toCss() {
+      if (this.em === 0 || this.pt === 0) return this.toString();
+      return `calc(${fmt(this.pt)}pt + ${fmt(this.em)}em)`;
+    }
+    toString() {
+      if (this.em === 0) return fmt(this.pt) + "pt";
+      if (this.pt === 0) return fmt(this.em) + "em";
+      return `${fmt(this.pt)}pt + ${fmt(this.em)}em`;
+    }
+  }
+
+  class Angle extends TypstValue {
+    constructor(deg = 0) { super(); this.deg = deg; }
+    toDeg() { return this.deg; }
+    toRad() { return this.deg * Math.PI / 180; }
+    toCss() { return this.toString(); }
+    toString() { return fmt(this.deg) + "deg"; }
+  }
+
+  class Ratio extends TypstValue {
+    constructor(ratio = 0) { super(); this.ratio = ratio; }
+    valueOf() { return this.ratio; }
+    toCss() { return this.toString(); }
+    toString() { return pct(this.ratio); }
+  }
+
+  class Relative extends TypstValue {
+    constructor(ratio = 0, pt = 0, em = 0) { super(); this.ratio = new Ratio(ratio); this.length = new Length(pt, em); }
+    toPt(base, fontSize) { return this.ratio.ratio * base + this.length.toPt(fontSize); }
+    toCss() {
+      const parts = [pct(this.ratio.ratio)];
+      if (this.length.pt !== 0) parts.push(fmt(this.length.pt) + "pt");
+      if (this.length.em !== 0) parts.push(fmt(this.length.em) + "em");
+      return `calc(${parts.join(" + ")})`;
+    }
+    toString() { return `${this.ratio} + ${this.length}`; }
+  }
+
+  class Fraction extends TypstValue {
+    constructor(fr = 0) { super(); this.fr = fr; }
+    valueOf() { return this.fr; }
+    toCss() { return this.toString(); }
+    toString() { return fmt(this.fr) + "fr"; }
+  }
+
+  class Color extends TypstValue {
+    constructor(space = "rgb", components = [0, 0, 0, 1]) { super(); this.space = space; this.components = components; }
+    get alpha() { return this.space === "cmyk" ? 1 : this.components[this.components.length - 1]; }
+    toRgb() {
+      const [a, b, c, d] = this.components;
+      const hsv = (h, s, v) => {
+        const f = (n) => { const k = (n + h / 60) % 6; return v - v * s * Math.max(0, Math.min(k, 4 - k, 1)); };
+        return [f(5), f(3), f(1)];
+      };
+      const linear = (n) => n <= 0.0031308 ? 12.92 * n : 1.055 * Math.pow(n, 1 / 2.4) - 0.055;
+      switch (this.space) {
+        case "rgb": return [a, b, c, this.alpha];
+        case "luma": return [a, a, a, this.alpha];
+        case "cmyk": return [(1 - a) * (1 - d), (1 - b) * (1 - d), (1 - c) * (1 - d), 1];
+        case "linear-rgb": return [linear(a), linear(b), linear(c), this.alpha];
+        case "hsv": return [...hsv(a, b, c), this.alpha];
+        case "hsl": {
+          const v = c + b * Math.min(c, 1 - c);
+          return [...hsv(a, v === 0 ? 0 : 2 * (1 - c / v), v), this.alpha];
+        }
+        default: return undefined;
+      }
+    }
+    toHex() {
+      const rgb = this.toRgb();
+      if (rgb === undefined) return undefined;
+      return "#" + rgb.slice(0, 3).map(hex).join("") + (rgb[3] < 1 ? hex(rgb[3]) : "");
+    }
+    toCss() {
+      const [a, b, c] = this.components;
+      const alpha = this.alpha < 1 ? ` / ${pct(this.alpha)}` : "";
+      switch (this.space) {
+        case "oklab": return `oklab(${pct(a)} ${fmt(b)} ${fmt(c)}${alpha})`;
+        case "oklch": return `oklch(${pct(a)} ${fmt(b)} ${fmt(c)}deg${alpha})`;
+        case "hsl": return `hsl(${fmt(a)}deg ${pct(b)} ${pct(c)}${alpha})`;
+        default: return this.toHex();
+      }
+    }
+    toString() {
+      const [a, b, c, d] = this.components;
+      const alpha = this.alpha < 1 ? `, ${pct(this.alpha)}` : "";
+      switch (this.space) {
+        case "rgb": return `rgb("${this.toHex()}")`;
+        case "luma": return `luma(${pct(a)}${alpha})`;
+        case "cmyk": return `cmyk(${[a, b, c, d].map(pct).join(", ")})`;
+        case "oklab": return `oklab(${pct(a)}, ${fmt(b)}, ${fmt(c)}${alpha})`;
+        case "oklch": return `oklch(${pct(a)}, ${fmt(b)}, ${fmt(c)}deg${alpha})`;
+        case "linear-rgb": return `color.linear-rgb(${[a, b, c].map(pct).join(", ")}${alpha})`;
+        case "hsl": return `color.hsl(${fmt(a)}deg, ${pct(b)}, ${pct(c)}${alpha})`;
+        case "hsv": return `color.hsv(${fmt(a)}deg, ${pct(b)}, ${pct(c)}${alpha})`;
+        default: return `${this.space}(${this.components.map(fmt).join(", ")})`;
+      }
+    }
+  }
+
+  class Datetime extends TypstValue {
+    constructor(year = null, month = null, day = null, hour = null, minute = null, second = null) {
+      super();
+      Object.assign(this, { year, month, day, hour, minute, second });
+    }
+    toDate() {
+      return new Date(Date.UTC(this.year ?? 1970, (this.month ?? 1) - 1, this.day ?? 1, this.hour ?? 0, this.minute ?? 0, this.second ?? 0));
+    }
+    toString() {
+      const fields = ["year", "month", "day", "hour", "minute", "second"].filter((k) => this[k] !== null);
+      return `datetime(${fields.map((k) => `${k}: ${this[k]}`).join(", ")})`;
+    }
+  }
+
+  class Duration extends TypstValue {
+    constructor(seconds = 0) { super(); this.seconds = seconds; }
+    toSeconds() { return this.seconds; }
+    toMinutes() { return this.seconds / 60; }
+    toHours() { return this.seconds / 3600; }
+    toDays() { return this.seconds / 86400; }
+    toWeeks() { return this.seconds / 604800; }
+    toMs() { return this.seconds * 1000; }
+    toString() {
+      let rest = this.seconds;
+      const parts = [];
+      for (const [k, f] of [["weeks", 604800], ["days", 86400], ["hours", 3600], ["minutes", 60]]) {
+        const n = Math.trunc(rest / f);
+        if (n !== 0) parts.push(`${k}: ${n}`);
+        rest -= n * f;
+      }
+      if (rest !== 0 || parts.length === 0) parts.push(`seconds: ${fmt(rest)}`);
+      return `duration(${parts.join(", ")})`;
+    }
+  }
+
+  const classes = { TypstValue, Length, Angle, Ratio, Relative, Fraction, Color, Datetime, Duration };
+  Object.defineProperty(globalThis, "__ctxjs_typst", { value: classes });
+  if (!("typst" in globalThis)) globalThis.typst = classes;
+})();"##;
+
+pub(crate) fn install(ctx: &Ctx) -> rquickjs::Result<()> {
+    ctx.eval::<(), _>(PRELUDE)
+}
+
+pub(crate) fn to_js<'js>(ctx: &Ctx<'js>, value: &TypstValue) -> rquickjs::Result<Value<'js>> {
+    let class: Constructor = ctx
+        .globals()
+        .get::<_, Object>(CLASSES)?
+        .get(value.class_name())?;
+
+    match value {
+        TypstValue::Length { pt, em } => class.construct((*pt, *em)),
+        TypstValue::Angle { deg } => class.construct((*deg,)),
+        TypstValue::Ratio { ratio } => class.construct((*ratio,)),
+        TypstValue::Relative { ratio, pt, em } => class.construct((*ratio, *pt, *em)),
+        TypstValue::Fraction { fr } => class.construct((*fr,)),
+        TypstValue::Color { space, components } => class.construct((*space, components.clone())),
+        TypstValue::Datetime { date, time } => class.construct((
+            date.map(|d| d.0),
+            date.map(|d| d.1),
+            date.map(|d| d.2),
+            time.map(|t| t.0),
+            time.map(|t| t.1),
+            time.map(|t| t.2),
+        )),
+        TypstValue::Duration { seconds } => class.construct((*seconds,)),
+    }
+}
+
+pub(crate) fn to_typst_string<'js>(object: &Object<'js>) -> rquickjs::Result<Option<String>> {
+    let Some(classes) = object.ctx().globals().get::<_, Option<Object>>(CLASSES)? else {
+        return Ok(None);
+    };
+    let base: Value = classes.get("TypstValue")?;
+    if !object.is_instance_of(&base) {
+        return Ok(None);
+    }
+    let to_string: Function = object.get("toString")?;
+    Ok(Some(to_string.call((This(object.clone()),))?))
+}
@@ -0,0 +1,726 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use minicbor::{data::Type, Decoder};
+
+use crate::cbor::{self, con, json::DEFAULT_MAX_DEPTH};
+
+// a practical subset of json schema (draft 2020-12) checked on cbor values:
+// type, enum, const, minimum, maximum, exclusiveMinimum, exclusiveMaximum,
+// minLength, maxLength, pattern, items, prefixItems, minItems, maxItems,
+// properties, required, additionalProperties and local $ref (`#/$defs/name`),
+// unknown keywords are ignored
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+    // code (eval, json, ...) which is only known after it was evaluated
+    Opaque,
+}
+
+impl Value {
+    pub fn decode(decoder: &mut Decoder) -> Result<Value, minicbor::decode::Error> {
+        Value::decode_nested(decoder, 0)
+    }
+
+    // the decoder recurses, so the depth is limited like for json
+    fn decode_nested(
+        decoder: &mut Decoder,
+        depth: usize,
+    ) -> Result<Value, minicbor::decode::Error> {
+        if depth >= DEFAULT_MAX_DEPTH {
+            return Err(minicbor::decode::Error::message(format!(
+                "value is nested deeper than {} levels",
+                DEFAULT_MAX_DEPTH
+            )));
+        }
+        let depth = depth + 1;
+        Ok(match decoder.datatype()? {
+            Type::Bool => Value::Bool(decoder.bool()?),
+            Type::Null => {
+                decoder.null()?;
+                Value::Null
+            }
+            Type::Undefined => {
+                decoder.undefined()?;
+                Value::Null
+            }
+            Type::U8
+            | Type::U16
+            | Type::U32
+            | Type::U64
+            | Type::I8
+            | Type::I16
+            | Type::I32
+            | Type::I64
+            | Type::Int => Value::Int(i128::from(decoder.int()?)),
+            Type::F16 | Type::F32 | Type::F64 => Value::Float(decoder.f64()?),
+            Type::Simple => Value::Int(decoder.simple()?.into()),
+            Type::Bytes => match decoder.bytes()? {
+                // $ctxjs_cbor_
+                [b'$', b'c', b't', b'x', b'j', b's', b'_', b'c', b'b', b'o', b'r', b'_', b @ ..] => {
+                    Value::decode_nested(&mut Decoder::new(b), depth)?
+                }
+                b => Value::Bytes(b.to_vec()),
+            },
+            Type::String => Value::Str(decoder.str()?.to_string()),
+            Type::Array => {
+                let len = cbor::utils::array_length(decoder)?;
+                let mut values = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    values.push(Value::decode_nested(decoder, depth)?);
+                }
+                Value::Array(values)
+            }
+            Type::Map => {
+                let len = cbor::utils::map_length(decoder)?;
+                let mut entries = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    // js property keys are strings
+                    let key = match Value::decode_nested(decoder, depth)? {
+                        Value::Str(s) => s,
+                        Value::Int(v) => v.to_string(),
+                        Value::Bool(b) => b.to_string(),
+                        _ => {
+                            return Err(minicbor::decode::Error::type_mismatch(Type::Map)
+                                .with_message("unsupported key type"))
+                        }
+                    };
+                    entries.push((key, Value::decode_nested(decoder, depth)?));
+                }
+                Value::Map(entries)
+            }
+            Type::Tag => match decoder.tag()? {
+                con::RAW_BYTES | con::TYPST => Value::decode_nested(decoder, depth)?,
+                con::EVAL | con::EVAL_FORMAT | con::JSON | con::JSON5 | con::ASSET => {
+                    decoder.skip()?;
+                    Value::Opaque
+                }
+                t => {
+                    return Err(minicbor::decode::Error::tag_mismatch(t)
+                        .with_message(format!("unsupported tagged data {}", t)))
+                }
+            },
+            other => {
+                return Err(minicbor::decode::Error::type_mismatch(other)
+                    .with_message("unknown or unsupported type"))
+            }
+        })
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "number",
+            Value::Str(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Map(_) => "object",
+            Value::Opaque => "code",
+        }
+    }
+
+    fn is_type(&self, name: &str) -> bool {
+        match (self, name) {
+            (Value::Opaque, _) => true,
+            (Value::Float(v), "integer") => v.fract() == 0.0,
+            (Value::Int(_), "number") => true,
+            (v, name) => v.type_name() == name,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Value::Int(v) => Some(*v as f64),
+            Value::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // json equality, numbers are compared by value and objects ignore the order
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (a, b) if a.number().is_some() && b.number().is_some() => a.number() == b.number(),
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, v)| other.get(k).is_some_and(|other| v.equals(other)))
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    // json pointer to the invalid value
+    pub path: String,
+    // json pointer to the failed keyword in the schema
+    pub schema_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at \"{}\"", self.message, self.path)
+    }
+}
+
+fn pointer_segment(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+// validates the value and returns all errors
+pub fn validate(schema: &Value, value: &Value) -> Vec<SchemaError> {
+    let mut validator = Validator {
+        root: schema,
+        errors: Vec::new(),
+        patterns: HashMap::new(),
+        refs: HashSet::new(),
+        depth: 0,
+    };
+    validator.check(schema, "", value, "");
+    validator.errors
+}
+
+// decodes the cbor value and schema and validates them
+pub fn validate_cbor(schema: &[u8], value: &[u8]) -> Result<Vec<SchemaError>, String> {
+    let schema = Value::decode(&mut Decoder::new(schema))
+        .map_err(|e| format!("failed to deserialize schema: {}", e))?;
+    let value = Value::decode(&mut Decoder::new(value))
+        .map_err(|e| format!("failed to deserialize value: {}", e))?;
+    Ok(validate(&schema, &value))
+}
+
+struct Validator<'s> {
+    root: &'s Value,
+    errors: Vec<SchemaError>,
+    patterns: HashMap<&'s str, Result<regex_lite::Regex, String>>,
+    // the active $ref targets (by address) with the instance path, the same
+    // pair again is a cycle which makes no progress in the value
+    refs: HashSet<(*const Value, String)>,
+    // nested checks, the validator recurses for subschemas
+    depth: usize,
+}
+
+impl<'s> Validator<'s> {
+    fn error(&mut self, path: &str, schema_path: String, message: String) {
+        self.errors.push(SchemaError {
+            path: path.to_string(),
+            schema_path,
+            message,
+        });
+    }
+
+    // `#` or `#/json/pointer` inside the root schema
+    fn resolve(&self, reference: &str) -> Option<&'s Value> {
+        let pointer = reference.strip_prefix('#')?;
+        if pointer.is_empty() {
+            return Some(self.root);
+        }
+        let mut schema = self.root;
+        for segment in pointer.strip_prefix('/')?.split('/') {
+            let segment = segment.replace("~1", "/").replace("~0", "~");
+            schema = match schema {
+                Value::Map(_) => schema.get(&segment)?,
+                Value::Array(values) => values.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(schema)
+    }
+
+    fn check(&mut self, schema: &'s Value, schema_path: &str, value: &Value, path: &str) {
+        if self.depth >= DEFAULT_MAX_DEPTH {
+            return self.error(
+                path,
+                schema_path.to_string(),
+                format!("schema is nested deeper than {} levels", DEFAULT_MAX_DEPTH),
+            );
+        }
+        self.depth += 1;
+        self.check_entries(schema, schema_path, value, path);
+        self.depth -= 1;
+    }
+
+    fn check_entries(&mut self, schema: &'s Value, schema_path: &str, value: &Value, path: &str) {
+        let entries = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                return self.error(
+                    path,
+                    schema_path.to_string(),
+                    "no value is allowed".to_string(),
+                )
+            }
+            Value::Map(entries) => entries,
+            _ => {
+                return self.error(
+                    path,
+                    schema_path.to_string(),
+                    "invalid schema, expected an object or boolean".to_string(),
+                )
+            }
+        };
+
+        for (keyword, argument) in entries {
+            let keyword_path = format!("{}/{}", schema_path, pointer_segment(keyword));
+            self.keyword(schema, keyword, argument, keyword_path, value, path);
+        }
+    }
+
+    fn keyword(
+        &mut self,
+        schema: &'s Value,
+        keyword: &str,
+        argument: &'s Value,
+        keyword_path: String,
+        value: &Value,
+        path: &str,
+    ) {
+        if let Value::Opaque = value {
+            return;
+        }
+
+        match (keyword, argument) {
+            ("$ref", Value::Str(reference)) => match self.resolve(reference) {
+                Some(target) => {
+                    let key = (target as *const Value, path.to_string());
+                    if !self.refs.insert(key.clone()) {
+                        return self.error(
+                            path,
+                            keyword_path,
+                            format!("$ref \"{}\" is circular", reference),
+                        );
+                    }
+                    let target_path = reference[1..].to_string();
+                    self.check(target, &target_path, value, path);
+                    self.refs.remove(&key);
+                }
+                None => self.error(
+                    path,
+                    keyword_path,
+                    format!("unresolvable $ref \"{}\"", reference),
+                ),
+            },
+            ("type", Value::Str(name)) if !value.is_type(name) => {
+                self.error(
+                    path,
+                    keyword_path,
+                    format!("expected {}, found {}", name, value.type_name()),
+                );
+            }
+            ("type", Value::Array(names)) => {
+                let names: Vec<&str> = names
+                    .iter()
+                    .filter_map(|name| match name {
+                        Value::Str(name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                if !names.iter().any(|name| value.is_type(name)) {
+                    self.error(
+                        path,
+                        keyword_path,
+                        format!(
+                            "expected {}, found {}",
+                            names.join(" or "),
+                            value.type_name()
+                        ),
+                    );
+                }
+            }
+            ("enum", Value::Array(values)) if !values.iter().any(|v| v.equals(value)) => {
+                self.error(
+                    path,
+                    keyword_path,
+                    "value is not one of the enum values".to_string(),
+                );
+            }
+            ("const", v) if !v.equals(value) => {
+                self.error(
+                    path,
+                    keyword_path,
+                    "value is not the const value".to_string(),
+                );
+            }
+            ("minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum", limit) => {
+                let (Some(v), Some(limit)) = (value.number(), limit.number()) else {
+                    return;
+                };
+                let (valid, relation) = match keyword {
+                    "minimum" => (v >= limit, ">="),
+                    "maximum" => (v <= limit, "<="),
+                    "exclusiveMinimum" => (v > limit, ">"),
+                    _ => (v < limit, "<"),
+                };
+                if !valid {
+                    self.error(
+                        path,
+                        keyword_path,
+                        format!("expected a number {} {}, found {}", relation, limit, v),
+                    );
+                }
+            }
+            ("minLength" | "maxLength", limit) => {
+                let (Value::Str(s), Some(limit)) = (value, limit.number()) else {
+                    return;
+                };
+                let len = s.chars().count() as f64;
+                if (keyword == "minLength" && len < limit)
+                    || (keyword == "maxLength" && len > limit)
+                {
+                    self.error(
+                        path,
+                        keyword_path,
+                        format!("string length {} is not within {} {}", len, keyword, limit),
+                    );
+                }
+            }
+            ("pattern", Value::Str(pattern)) => {
+                let Value::Str(s) = value else {
+                    return;
+                };
+                let regex = self
+                    .patterns
+                    .entry(pattern.as_str())
+                    .or_insert_with(|| regex_lite::Regex::new(pattern).map_err(|e| e.to_string()));
+                match regex {
+                    Ok(regex) if regex.is_match(s) => {}
+                    Ok(_) => self.error(
+                        path,
+                        keyword_path,
+                        format!("string does not match pattern \"{}\"", pattern),
+                    ),
+                    Err(err) => {
+                        let message = format!("invalid pattern \"{}\": {}", pattern, err);
+                        self.error(path, keyword_path, message)
+                    }
+                }
+            }
+            ("minItems" | "maxItems", limit) => {
+                let (Value::Array(values), Some(limit)) = (value, limit.number()) else {
+                    return;
+                };
+                let len = values.len() as f64;
+                if (keyword == "minItems" && len < limit) || (keyword == "maxItems" && len > limit)
+                {
+                    self.error(
+                        path,
+                        keyword_path,
+                        format!("array length {} is not within {} {}", len, keyword, limit),
+                    );
+                }
+            }
+            ("prefixItems", Value::Array(schemas)) => {
+                let Value::Array(values) = value else {
+                    return;
+                };
+                for (i, (schema, value)) in schemas.iter().zip(values).enumerate() {
+                    self.check(
+                        schema,
+                        &format!("{}/{}", keyword_path, i),
+                        value,
+                        &format!("{}/{}", path, i),
+                    );
+                }
+            }
+            ("items", item) => {
+                let Value::Array(values) = value else {
+                    return;
+                };
+                // items only applies after the prefixItems
+                let skip = match schema.get("prefixItems") {
+                    Some(Value::Array(prefix)) => prefix.len(),
+                    _ => 0,
+                };
+                for (i, value) in values.iter().enumerate().skip(skip) {
+                    self.check(item, &keyword_path, value, &format!("{}/{}", path, i));
+                }
+            }
+            ("properties", Value::Map(properties)) => {
+                for (key, schema) in properties {
+                    if let Some(value) = value.get(key) {
+                        let key = pointer_segment(key);
+                        self.check(
+                            schema,
+                            &format!("{}/{}", keyword_path, key),
+                            value,
+                            &format!("{}/{}", path, key),
+                        );
+                    }
+                }
+            }
+            ("required", Value::Array(keys)) => {
+                let Value::Map(_) = value else {
+                    return;
+                };
+                for key in keys {
+                    if let Value::Str(key) = key {
+                        if value.get(key).is_none() {
+                            self.error(
+                                path,
+                                keyword_path.clone(),
+                                format!("missing required property \"{}\"", key),
+                            );
+                        }
+                    }
+                }
+            }
+            ("additionalProperties", additional) => {
+                let Value::Map(entries) = value else {
+                    return;
+                };
+                let properties = schema.get("properties");
+                for (key, value) in entries {
+                    if properties.is_some_and(|p| p.get(key).is_some()) {
+                        continue;
+                    }
+                    self.check(
+                        additional,
+                        &keyword_path,
+                        value,
+                        &format!("{}/{}", path, pointer_segment(key)),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::Decoder;
+
+    use super::{validate, Value};
+    use crate::cbor::json::DEFAULT_MAX_DEPTH;
+
+    fn s(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn errors(schema: &Value, value: &Value) -> Vec<(String, String)> {
+        validate(schema, value)
+            .into_iter()
+            .map(|err| (err.path, err.schema_path))
+            .collect()
+    }
+
+    #[test]
+    fn test_keywords() {
+        let schema = map(vec![
+            ("type", s("object")),
+            ("required", Value::Array(vec![s("title"), s("series")])),
+            (
+                "properties",
+                map(vec![
+                    (
+                        "title",
+                        map(vec![
+                            ("type", s("string")),
+                            ("minLength", Value::Int(1)),
+                            ("pattern", s("^[A-Z]")),
+                        ]),
+                    ),
+                    (
+                        "series",
+                        map(vec![
+                            ("type", s("array")),
+                            ("items", map(vec![("$ref", s("#/$defs/point"))])),
+                        ]),
+                    ),
+                    (
+                        "kind",
+                        map(vec![("enum", Value::Array(vec![s("bar"), s("line")]))]),
+                    ),
+                    ("a/b", map(vec![("type", s("integer"))])),
+                ]),
+            ),
+            (
+                "$defs",
+                map(vec![(
+                    "point",
+                    map(vec![
+                        ("type", s("array")),
+                        (
+                            "prefixItems",
+                            Value::Array(vec![map(vec![("type", s("number"))])]),
+                        ),
+                        ("items", map(vec![("minimum", Value::Int(0))])),
+                        ("maxItems", Value::Int(2)),
+                    ]),
+                )]),
+            ),
+        ]);
+
+        let valid = map(vec![
+            ("title", s("Chart")),
+            (
+                "series",
+                Value::Array(vec![Value::Array(vec![Value::Float(-1.5), Value::Int(2)])]),
+            ),
+            ("kind", s("bar")),
+            ("a/b", Value::Float(2.0)),
+        ]);
+        assert_eq!(errors(&schema, &valid), vec![]);
+
+        let invalid = map(vec![
+            ("title", s("chart")),
+            (
+                "series",
+                Value::Array(vec![
+                    Value::Array(vec![s("x"), Value::Int(-1)]),
+                    Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)]),
+                ]),
+            ),
+            ("kind", s("pie")),
+            ("a/b", Value::Float(2.5)),
+        ]);
+        assert_eq!(
+            errors(&schema, &invalid),
+            vec![
+                (
+                    "/title".to_string(),
+                    "/properties/title/pattern".to_string()
+                ),
+                (
+                    "/series/0/0".to_string(),
+                    "/$defs/point/prefixItems/0/type".to_string()
+                ),
+                (
+                    "/series/0/1".to_string(),
+                    "/$defs/point/items/minimum".to_string()
+                ),
+                ("/series/1".to_string(), "/$defs/point/maxItems".to_string()),
+                ("/kind".to_string(), "/properties/kind/enum".to_string()),
+                ("/a~1b".to_string(), "/properties/a~1b/type".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            errors(&schema, &map(vec![("title", s("A"))])),
+            vec![("".to_string(), "/required".to_string())]
+        );
+        assert_eq!(
+            validate(&schema, &Value::Opaque),
+            vec![],
+            "code is not validated"
+        );
+    }
+
+    #[test]
+    fn test_refs() {
+        // recursive schema
+        let tree = map(vec![
+            ("type", s("object")),
+            ("additionalProperties", map(vec![("$ref", s("#"))])),
+        ]);
+        let value = map(vec![("a", map(vec![("b", Value::Int(1))]))]);
+        assert_eq!(
+            errors(&tree, &value),
+            vec![("/a/b".to_string(), "/type".to_string())]
+        );
+
+        // deeper than any fixed limit of nested $ref
+        let mut deep = Value::Int(1);
+        for _ in 0..100 {
+            deep = map(vec![("a", deep)]);
+        }
+        let errors = validate(&tree, &deep);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "/a".repeat(100));
+
+        let cycle = map(vec![("$ref", s("#"))]);
+        assert_eq!(
+            validate(&cycle, &Value::Null)[0].message,
+            "$ref \"#\" is circular"
+        );
+        let indirect = map(vec![
+            ("$ref", s("#/$defs/a")),
+            (
+                "$defs",
+                map(vec![
+                    ("a", map(vec![("$ref", s("#/$defs/b"))])),
+                    ("b", map(vec![("$ref", s("#/$defs/a"))])),
+                ]),
+            ),
+        ]);
+        assert_eq!(validate(&indirect, &Value::Null).len(), 1);
+
+        // the validator recurses, so it stops at a depth which fits on the
+        // wasm stack (release builds need far less stack than debug builds)
+        let too_deep = std::thread::Builder::new()
+            .stack_size(32 << 20)
+            .spawn(move || {
+                let mut deep = Value::Int(1);
+                for _ in 0..DEFAULT_MAX_DEPTH {
+                    deep = map(vec![("a", deep)]);
+                }
+                validate(&tree, &deep)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(too_deep.len(), 1);
+        assert!(too_deep[0].message.contains("nested deeper"));
+
+        let missing = map(vec![("$ref", s("#/$defs/missing"))]);
+        assert_eq!(
+            validate(&missing, &Value::Null)[0].message,
+            "unresolvable $ref \"#/$defs/missing\""
+        );
+    }
+
+    #[test]
+    fn test_decode_depth() {
+        let nested = |depth: usize| {
+            let mut data = vec![0x81; depth - 1];
+            data.push(0x01);
+            data
+        };
+        // release builds need far less stack than debug builds
+        std::thread::Builder::new()
+            .stack_size(32 << 20)
+            .spawn(move || {
+                assert!(Value::decode(&mut Decoder::new(&nested(DEFAULT_MAX_DEPTH))).is_ok());
+                let err =
+                    Value::decode(&mut Decoder::new(&nested(DEFAULT_MAX_DEPTH + 1))).unwrap_err();
+                assert!(err.to_string().contains("nested deeper"), "{}", err);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+// Parses the `repr` strings typst uses when it serializes values that are not
+// native cbor types (length, angle, ratio, fraction, color, datetime and duration).
+
+#[derive(Debug, PartialEq)]
+pub enum TypstValue {
+    Length {
+        pt: f64,
+        em: f64,
+    },
+    Angle {
+        deg: f64,
+    },
+    Ratio {
+        ratio: f64,
+    },
+    Relative {
+        ratio: f64,
+        pt: f64,
+        em: f64,
+    },
+    Fraction {
+        fr: f64,
+    },
+    Color {
+        space: &'static str,
+        components: Vec<f64>,
+    },
+    Datetime {
+        date: Option<(i64, i64, i64)>,
+        time: Option<(i64, i64, i64)>,
+    },
+    Duration {
+        seconds: f64,
+    },
+}
+
+impl TypstValue {
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            TypstValue::Length { .. } => "Length",
+            TypstValue::Angle { .. } => "Angle",
+            TypstValue::Ratio { .. } => "Ratio",
+            TypstValue::Relative { .. } => "Relative",
+            TypstValue::Fraction { .. } => "Fraction",
+            TypstValue::Color { .. } => "Color",
+            TypstValue::Datetime { .. } => "Datetime",
+            TypstValue::Duration { .. } => "Duration",
+        }
+    }
+}
+
+pub fn parse(s: &str) -> Option<TypstValue> {
+    let s = s.trim();
+    if let Some(args) = call_args(s, "datetime") {
+        return parse_datetime(args);
+    }
+    if let Some(args) = call_args(s, "duration") {
+        return parse_duration(args);
+    }
+    if let Some(value) = parse_color(s) {
+        return Some(value);
+    }
+    parse_numeric(s)
+}
+
+fn call_args<'a>(s: &'a str, name: &str) -> Option<&'a str> {
+    s.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')
+}
+
+fn split_args(args: &str) -> Vec<&str> {
+    let args = args.trim();
+    if args.is_empty() {
+        return vec![];
+    }
+    args.split(',').map(|a| a.trim()).collect()
+}
+
+fn named_args(args: &str) -> Option<Vec<(&str, &str)>> {
+    split_args(args)
+        .into_iter()
+        .map(|a| a.split_once(':').map(|(k, v)| (k.trim(), v.trim())))
+        .collect()
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    // typst may print negative numbers with a unicode minus sign
+    let s = s.replace('\u{2212}', "-");
+    if s.is_empty()
+        || !s
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'.' | b'-' | b'+' | b'e' | b'E'))
+    {
+        return None;
+    }
+    s.parse::<f64>().ok()
+}
+
+fn parse_int(s: &str) -> Option<i64> {
+    s.replace('\u{2212}', "-").parse::<i64>().ok()
+}
+
+fn split_unit(s: &str) -> Option<(f64, &str)> {
+    let unit_start = s
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphabetic() || *c == '%')
+        .last()
+        .map(|(i, _)| i)?;
+    let (number, unit) = s.split_at(unit_start);
+    Some((parse_number(number.trim())?, unit))
+}
+
+// the signed terms of a sum like `50% - 2pt`
+fn split_terms(s: &str) -> Vec<(f64, &str)> {
+    let mut terms = Vec::new();
+    let mut sign = 1.0;
+    let mut rest = s;
+    loop {
+        let next = [" + ", " - ", " \u{2212} "]
+            .into_iter()
+            .filter_map(|op| rest.find(op).map(|i| (i, op)))
+            .min();
+        let Some((i, op)) = next else {
+            terms.push((sign, rest));
+            return terms;
+        };
+        terms.push((sign, &rest[..i]));
+        sign = if op == " + " { 1.0 } else { -1.0 };
+        rest = &rest[i + op.len()..];
+    }
+}
+
+fn parse_numeric(s: &str) -> Option<TypstValue> {
+    let mut pt = 0.0;
+    let mut em = 0.0;
+    let mut ratio: Option<f64> = None;
+    let mut has_length = false;
+    let terms = split_terms(s);
+
+    for (sign, term) in &terms {
+        let (value, unit) = split_unit(term.trim())?;
+        let value = sign * value;
+        match unit {
+            "pt" => pt += value,
+            "mm" => pt += value * 72.0 / 25.4,
+            "cm" => pt += value * 72.0 / 2.54,
+            "in" => pt += value * 72.0,
+            "em" => em += value,
+            "%" => {
+                ratio = Some(ratio.unwrap_or(0.0) + value / 100.0);
+                continue;
+            }
+            "deg" | "rad" | "fr" if terms.len() == 1 => {
+                return Some(match unit {
+                    "deg" => TypstValue::Angle { deg: value },
+                    "rad" => TypstValue::Angle {
+                        deg: value.to_degrees(),
+                    },
+                    _ => TypstValue::Fraction { fr: value },
+                });
+            }
+            _ => return None,
+        }
+        has_length = true;
+    }
+
+    Some(match (ratio, has_length) {
+        (Some(ratio), false) => TypstValue::Ratio { ratio },
+        (Some(ratio), true) => TypstValue::Relative { ratio, pt, em },
+        (None, _) => TypstValue::Length { pt, em },
+    })
+}
+
+fn parse_component(s: &str, scale: f64) -> Option<f64> {
+    if let Some(p) = s.strip_suffix('%') {
+        return Some(parse_number(p)? / 100.0);
+    }
+    if let Some(d) = s.strip_suffix("deg") {
+        return parse_number(d);
+    }
+    Some(parse_number(s)? / scale)
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<f64>> {
+    let hex = hex.strip_prefix('#')?;
+    let expanded: String = match hex.len() {
+        3 | 4 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 | 8 => hex.to_string(),
+        _ => return None,
+    };
+    let mut components = Vec::with_capacity(4);
+    for i in (0..expanded.len()).step_by(2) {
+        components.push(u8::from_str_radix(expanded.get(i..i + 2)?, 16).ok()? as f64 / 255.0);
+    }
+    if components.len() == 3 {
+        components.push(1.0);
+    }
+    Some(components)
+}
+
+fn parse_color(s: &str) -> Option<TypstValue> {
+    // (name, space, component count without alpha, plain number scale per component)
+    const SPACES: [(&str, &str, usize, [f64; 3]); 8] = [
+        ("rgb", "rgb", 3, [255.0, 255.0, 255.0]),
+        ("color.linear-rgb", "linear-rgb", 3, [255.0, 255.0, 255.0]),
+        ("luma", "luma", 1, [255.0, 1.0, 1.0]),
+        ("cmyk", "cmyk", 4, [1.0, 1.0, 1.0]),
+        ("oklab", "oklab", 3, [1.0, 1.0, 1.0]),
+        ("oklch", "oklch", 3, [1.0, 1.0, 1.0]),
+        ("color.hsl", "hsl", 3, [1.0, 1.0, 1.0]),
+        ("color.hsv", "hsv", 3, [1.0, 1.0, 1.0]),
+    ];
+
+    for (name, space, count, scales) in SPACES {
+        let Some(args) = call_args(s, name) else {
+            continue;
+        };
+        let args = split_args(args);
+
+        if space == "rgb" && args.len() == 1 {
+            let hex = args[0].strip_prefix('"')?.strip_suffix('"')?;
+            return Some(TypstValue::Color {
+                space,
+                components: parse_hex(hex)?,
+            });
+        }
+
+        // cmyk has no alpha channel
+        let max = if space == "cmyk" { count } else { count + 1 };
+        if args.len() < count || args.len() > max {
+            return None;
+        }
+
+        let mut components = Vec::with_capacity(count + 1);
+        for (i, arg) in args.iter().enumerate() {
+            let scale = if i < count { scales[i.min(2)] } else { 255.0 };
+            components.push(parse_component(arg, scale)?);
+        }
+        if space != "cmyk" && components.len() == count {
+            components.push(1.0);
+        }
+
+        return Some(TypstValue::Color { space, components });
+    }
+    None
+}
+
+fn parse_datetime(args: &str) -> Option<TypstValue> {
+    let mut fields = [None::<i64>; 6];
+    for (key, value) in named_args(args)? {
+        let i = match key {
+            "year" => 0,
+            "month" => 1,
+            "day" => 2,
+            "hour" => 3,
+            "minute" => 4,
+            "second" => 5,
+            _ => return None,
+        };
+        fields[i] = Some(parse_int(value)?);
+    }
+
+    let date = match (fields[0], fields[1], fields[2]) {
+        (Some(y), Some(m), Some(d)) => Some((y, m, d)),
+        (None, None, None) => None,
+        _ => return None,
+    };
+    let time = match (fields[3], fields[4], fields[5]) {
+        (Some(h), Some(m), Some(s)) => Some((h, m, s)),
+        (None, None, None) => None,
+        _ => return None,
+    };
+    if date.is_none() && time.is_none() {
+        return None;
+    }
+
+    Some(TypstValue::Datetime { date, time })
+}
+
+fn parse_duration(args: &str) -> Option<TypstValue> {
+    let mut seconds = 0.0;
+    for (key, value) in named_args(args)? {
+        let factor = match key {
+            "weeks" => 604800.0,
+            "days" => 86400.0,
+            "hours" => 3600.0,
+            "minutes" => 60.0,
+            "seconds" => 1.0,
+            _ => return None,
+        };
+        seconds += parse_number(value)? * factor;
+    }
+    Some(TypstValue::Duration { seconds })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, TypstValue};
+
+    #[test]
+    fn test_lengths() {
+        assert_eq!(
+            parse("12pt"),
+            Some(TypstValue::Length { pt: 12.0, em: 0.0 })
+        );
+        assert_eq!(
+            parse("1.5em"),
+            Some(TypstValue::Length { pt: 0.0, em: 1.5 })
+        );
+        assert_eq!(parse("1in"), Some(TypstValue::Length { pt: 72.0, em: 0.0 }));
+        assert_eq!(
+            parse("12pt + 1em"),
+            Some(TypstValue::Length { pt: 12.0, em: 1.0 })
+        );
+        assert_eq!(
+            parse("\u{2212}2pt"),
+            Some(TypstValue::Length { pt: -2.0, em: 0.0 })
+        );
+    }
+
+    #[test]
+    fn test_ratios_angles_fractions() {
+        assert_eq!(parse("50%"), Some(TypstValue::Ratio { ratio: 0.5 }));
+        assert_eq!(
+            parse("50% + 2pt"),
+            Some(TypstValue::Relative {
+                ratio: 0.5,
+                pt: 2.0,
+                em: 0.0
+            })
+        );
+        for relative in ["50% - 2pt", "50% + -2pt", "50% \u{2212} 2pt"] {
+            assert_eq!(
+                parse(relative),
+                Some(TypstValue::Relative {
+                    ratio: 0.5,
+                    pt: -2.0,
+                    em: 0.0
+                })
+            );
+        }
+        assert_eq!(
+            parse("-2pt - 1em"),
+            Some(TypstValue::Length { pt: -2.0, em: -1.0 })
+        );
+        assert_eq!(parse("45deg"), Some(TypstValue::Angle { deg: 45.0 }));
+        assert_eq!(parse("2fr"), Some(TypstValue::Fraction { fr: 2.0 }));
+        assert_eq!(parse("45deg + 1pt"), None);
+    }
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(
+            parse("rgb(\"#ff0000\")"),
+            Some(TypstValue::Color {
+                space: "rgb",
+                components: vec![1.0, 0.0, 0.0, 1.0]
+            })
+        );
+        assert_eq!(
+            parse("luma(50%)"),
+            Some(TypstValue::Color {
+                space: "luma",
+                components: vec![0.5, 1.0]
+            })
+        );
+        assert_eq!(
+            parse("cmyk(10%, 20%, 30%, 40%)"),
+            Some(TypstValue::Color {
+                space: "cmyk",
+                components: vec![0.1, 0.2, 0.3, 0.4]
+            })
+        );
+        assert_eq!(
+            parse("oklch(50%, 0.1, 45deg)"),
+            Some(TypstValue::Color {
+                space: "oklch",
+                components: vec![0.5, 0.1, 45.0, 1.0]
+            })
+        );
+        assert!(parse("rgb(\"#ff00\")").is_some());
+        assert_eq!(parse("rgb(\"ff0000\")"), None);
+    }
+
+    #[test]
+    fn test_datetimes_durations() {
+        assert_eq!(
+            parse("datetime(year: 2024, month: 1, day: 2)"),
+            Some(TypstValue::Datetime {
+                date: Some((2024, 1, 2)),
+                time: None
+            })
+        );
+        assert_eq!(
+            parse("datetime(hour: 3, minute: 4, second: 5)"),
+            Some(TypstValue::Datetime {
+                date: None,
+                time: Some((3, 4, 5))
+            })
+        );
+        assert_eq!(parse("datetime(year: 2024)"), None);
+        assert_eq!(
+            parse("duration(days: 1, seconds: 5)"),
+            Some(TypstValue::Duration { seconds: 86405.0 })
+        );
+    }
+
+    #[test]
+    fn test_plain_strings() {
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("12"), None);
+        assert_eq!(parse("pt"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("12px"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use minicbor::Decoder;
+use minicbor::{data::Type, Decoder};
 use rquickjs::qjs;
 
 pub enum TypedArrayType {
@@ -62,6 +62,101 @@ pub fn map_length(decoder: &mut Decoder) -> Result<u64, minicbor::decode::Error>
     })
 }
 
+// follows the path through maps (string keys) and arrays (indices), tags are
+// skipped
+pub fn lookup<'b, S: AsRef<str>>(
+    value: &'b [u8],
+    path: &[S],
+) -> Result<Option<&'b [u8]>, minicbor::decode::Error> {
+    let mut decoder = Decoder::new(value);
+    'path: for segment in path {
+        let segment = segment.as_ref();
+        while decoder.datatype()? == Type::Tag {
+            decoder.tag()?;
+        }
+        match decoder.datatype()? {
+            Type::Map => {
+                for _ in 0..map_length(&mut decoder)? {
+                    let found = match decoder.datatype()? {
+                        Type::String => decoder.str()? == segment,
+                        _ => {
+                            decoder.skip()?;
+                            false
+                        }
+                    };
+                    if found {
+                        continue 'path;
+                    }
+                    decoder.skip()?;
+                }
+                return Ok(None);
+            }
+            Type::Array => {
+                let len = array_length(&mut decoder)?;
+                match segment.parse::<u64>() {
+                    Ok(index) if index < len => {
+                        for _ in 0..index {
+                            decoder.skip()?;
+                        }
+                    }
+                    _ => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        }
+    }
+    let start = decoder.position();
+    decoder.skip()?;
+    Ok(Some(&value[start..decoder.position()]))
+}
+
+// a json pointer (`/a/0`) or a dotted path (`a.0`), empty for the whole value
+pub fn parse_path(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        vec![]
+    } else if let Some(pointer) = path.strip_prefix('/') {
+        pointer
+            .split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect()
+    } else {
+        path.split('.').map(|segment| segment.to_string()).collect()
+    }
+}
+
+// the typst type name and the length of strings (characters), bytes, arrays
+// and maps, indefinite lengths are unknown
+pub fn value_info(value: &[u8]) -> Result<(&'static str, Option<u64>), minicbor::decode::Error> {
+    let mut decoder = Decoder::new(value);
+    while decoder.datatype()? == Type::Tag {
+        decoder.tag()?;
+    }
+    Ok(match decoder.datatype()? {
+        Type::Null | Type::Undefined => ("none", None),
+        Type::Bool => ("bool", None),
+        Type::F16 | Type::F32 | Type::F64 => ("float", None),
+        Type::String => ("str", Some(decoder.str()?.chars().count() as u64)),
+        Type::Bytes => ("bytes", Some(decoder.bytes()?.len() as u64)),
+        Type::StringIndef => ("str", None),
+        Type::BytesIndef => ("bytes", None),
+        Type::Array | Type::ArrayIndef => ("array", decoder.array()?),
+        Type::Map | Type::MapIndef => ("dictionary", decoder.map()?),
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::I8
+        | Type::I16
+        | Type::I32
+        | Type::I64
+        | Type::Int => ("int", None),
+        other => {
+            return Err(minicbor::decode::Error::type_mismatch(other)
+                .with_message(format!("unsupported type {}", other)))
+        }
+    })
+}
+
 // pub fn map_fixed_length(decoder: &mut Decoder, len: u64) -> Result<u64, minicbor::decode::Error> {
 //     if map_length(decoder)? == len {
 //         return Ok(len);
@@ -71,3 +166,55 @@ pub fn map_length(decoder: &mut Decoder) -> Result<u64, minicbor::decode::Error>
 //             .with_message("mismatch length"),
 //     )
 // }
+
+#[cfg(test)]
+mod tests {
+    use minicbor::Encoder;
+
+    use super::{lookup, parse_path, value_info};
+
+    #[test]
+    fn test_lookup() {
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .map(2)
+            .and_then(|e| {
+                e.str("a/b")?
+                    .array(2)?
+                    .u8(1)?
+                    .map(1)?
+                    .str("c")?
+                    .str("\u{e4}\u{f6}")?
+                    .str("d")?
+                    .bytes(&[1, 2, 3])
+            })
+            .unwrap();
+
+        assert_eq!(parse_path(""), Vec::<String>::new());
+        assert_eq!(parse_path("/a~1b/1/c~0"), vec!["a/b", "1", "c~"]);
+        assert_eq!(parse_path("d.0"), vec!["d", "0"]);
+
+        let info = |path: &str| {
+            lookup(&data, &parse_path(path))
+                .unwrap()
+                .map(|value| value_info(value).unwrap())
+        };
+        assert_eq!(info(""), Some(("dictionary", Some(2))));
+        assert_eq!(info("/a~1b"), Some(("array", Some(2))));
+        assert_eq!(info("/a~1b/0"), Some(("int", None)));
+        assert_eq!(info("/a~1b/1/c"), Some(("str", Some(2))));
+        assert_eq!(info("d"), Some(("bytes", Some(3))));
+        assert_eq!(info("/a~1b/2"), None);
+        assert_eq!(info("d.0"), None);
+
+        let mut data = vec![];
+        Encoder::new(&mut data)
+            .array(3)
+            .and_then(|e| e.u64(u64::MAX)?.i64(-1)?.simple(16))
+            .unwrap();
+        let info = |path: &str| value_info(lookup(&data, &[path]).unwrap().unwrap());
+        assert_eq!(info("0").unwrap(), ("int", None));
+        assert_eq!(info("1").unwrap(), ("int", None));
+        assert!(info("2").is_err());
+    }
+}
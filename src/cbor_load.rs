@@ -4,7 +4,9 @@ use rquickjs::{
 };
 
 use crate::cbor;
-use crate::strfmt;
+use crate::cbor::rquickjs::NumberPolicy;
+use crate::define_vars::{self, DefineMode};
+use crate::modules;
 
 const LOAD_EVAL: u8 = 0;
 const LOAD_EVAL_FORMAT: u8 = 1;
@@ -13,6 +15,18 @@ const LOAD_CALL_FUNCTION: u8 = 3;
 const LOAD_LOAD_MODULE_BYTECODE: u8 = 4;
 const LOAD_LOAD_MODULE_JS: u8 = 5;
 const LOAD_CALL_MODULE_FUNCTION: u8 = 6;
+const LOAD_NUMBER_POLICY: u8 = 7;
+const LOAD_EVAL_WITH: u8 = 8;
+const LOAD_DEFINE_GLOBALS: u8 = 9;
+const LOAD_SCHEMA: u8 = 10;
+const LOAD_ASSET: u8 = 11;
+const LOAD_REGISTER_MODULE: u8 = 12;
+const LOAD_MODULE_BASE: u8 = 13;
+const LOAD_IMPORT_MAP: u8 = 14;
+const LOAD_COMMONJS: u8 = 15;
+const LOAD_JSON_MODULE: u8 = 16;
+const LOAD_TEXT_MODULE: u8 = 17;
+const LOAD_BYTES_MODULE: u8 = 18;
 
 fn run_load_eval(js: &[u8], ctx: &Context) -> Result<(), minicbor::decode::Error> {
     let js: &str = std::str::from_utf8(js).map_err(|err| minicbor::decode::Error::custom(err))?;
@@ -35,23 +49,30 @@ fn cbor_decode_run_load_eval_format(
     decoder: &mut Decoder,
     ctx: &Context,
 ) -> Result<(), minicbor::decode::Error> {
-    cbor::utils::array_fixed_length(decoder, 2)?;
-
-    let js = decoder.bytes()?;
-    let arguments = cbor::rquickjs::args::string_map(decoder)?;
+    let js = cbor::rquickjs::args::format(decoder, crate::get_decode_options())?;
 
     let mut options = EvalOptions::default();
     options.global = true;
 
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        ctx.eval_with_options(
-            strfmt::strfmt(&js, &arguments).map_err(|err| {
-                minicbor::decode::Error::message(format!("can not format js string: {}", err))
-            })?,
-            options,
-        )
-        .catch(&ctx)
-        .map_err(|err: CaughtError| minicbor::decode::Error::message(err))
+        ctx.eval_with_options(js, options)
+            .catch(&ctx)
+            .map_err(|err: CaughtError| minicbor::decode::Error::message(err))
+    })?;
+    Ok(())
+}
+
+fn cbor_decode_run_load_eval_with(
+    decoder: &mut Decoder,
+    ctx: &Context,
+) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let js = decoder.bytes()?;
+
+    _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
+        cbor::rquickjs::eval_with::eval_with(&ctx, js, decoder, crate::get_decode_options())?;
+        Ok(())
     })?;
     Ok(())
 }
@@ -60,13 +81,38 @@ fn cbor_decode_run_load_define_vars(
     decoder: &mut Decoder,
     ctx: &Context,
 ) -> Result<(), minicbor::decode::Error> {
-    let variables = cbor::rquickjs::args::string_map(decoder)?
-        .into_iter()
-        .map(|(k, v)| format!("let {}={}", k, v))
-        .fold(String::new(), |a, b| a + &b + ";");
+    // either only the variables or an array of decode flags, (mode) and variables
+    let mut flags = 0;
+    let mut mode = DefineMode::default();
+    if decoder.datatype()? == minicbor::data::Type::Array {
+        let len = cbor::utils::array_length(decoder)?;
+        if len != 2 && len != 3 {
+            return Err(
+                minicbor::decode::Error::type_mismatch(minicbor::data::Type::Array)
+                    .with_message("mismatch length"),
+            );
+        }
+        flags = decoder.u8()?;
+        if len == 3 {
+            let name = decoder.str()?;
+            mode = DefineMode::parse(name).ok_or_else(|| {
+                minicbor::decode::Error::message(format!("unsupported mode {}", name))
+            })?;
+        }
+    }
+
+    let variables = define_vars::source(
+        cbor::rquickjs::args::string_map(decoder, crate::get_decode_options().with_flags(flags))?,
+        mode,
+    )
+    .map_err(minicbor::decode::Error::message)?;
+
+    let mut options = EvalOptions::default();
+    options.global = true;
+    options.strict = false;
 
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        ctx.eval(format!("{};", variables))
+        ctx.eval_with_options(variables, options)
             .catch(&ctx)
             .map_err(|err: CaughtError| {
                 minicbor::decode::Error::message(format!("eval error: {}", err.to_string()))
@@ -75,22 +121,52 @@ fn cbor_decode_run_load_define_vars(
     Ok(())
 }
 
-fn cbor_decode_run_call_function(
+fn cbor_decode_run_load_define_globals(
     decoder: &mut Decoder,
     ctx: &Context,
 ) -> Result<(), minicbor::decode::Error> {
     cbor::utils::array_fixed_length(decoder, 2)?;
 
+    let flags = decoder.u8()?;
+
+    ctx.with(|ctx| {
+        define_vars::define_globals(&ctx, decoder, crate::get_decode_options().with_flags(flags))
+    })
+}
+
+fn cbor_decode_run_call_function(
+    decoder: &mut Decoder,
+    ctx: &Context,
+) -> Result<(), minicbor::decode::Error> {
+    let len = cbor::utils::array_length(decoder)?;
+    if len != 2 && len != 3 {
+        return Err(
+            minicbor::decode::Error::type_mismatch(minicbor::data::Type::Array)
+                .with_message("mismatch length"),
+        );
+    }
+
     let fn_name = decoder.str()?;
+    let flags = if len == 3 { decoder.u8()? } else { 0 };
+
+    let start = decoder.position();
+    decoder.skip()?;
+    crate::check_schema(fn_name, &decoder.input()[start..decoder.position()])
+        .map_err(minicbor::decode::Error::message)?;
+    decoder.set_position(start);
 
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        let arguments: Vec<rquickjs::Value> =
-            cbor::rquickjs::args::array(&ctx, decoder).map_err(|e| {
-                minicbor::decode::Error::message(format!(
-                    "failed to deserialize arguments: {}",
-                    e.to_string()
-                ))
-            })?;
+        let arguments: Vec<rquickjs::Value> = cbor::rquickjs::args::array(
+            &ctx,
+            decoder,
+            crate::get_decode_options().with_flags(flags),
+        )
+        .map_err(|e| {
+            minicbor::decode::Error::message(format!(
+                "failed to deserialize arguments: {}",
+                e.to_string()
+            ))
+        })?;
 
         let mut args = Args::new(ctx.clone(), arguments.len());
         args.push_args(arguments).map_err(|e| {
@@ -114,16 +190,21 @@ fn run_load_module_byte_code(
     ctx: &Context,
 ) -> Result<(), minicbor::decode::Error> {
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        _ = unsafe { Module::load(ctx.clone(), bytecode) }
+        let m = unsafe { Module::load(ctx.clone(), bytecode) }
             .catch(&ctx)
             .map_err(|e| {
                 minicbor::decode::Error::message(format!("failed load bytecode: {}", e.to_string()))
-            })?
-            .eval()
-            .catch(&ctx)
-            .map_err(|e| {
-                minicbor::decode::Error::message(format!("failed eval bytecode: {}", e.to_string()))
             })?;
+        let name: String = m.name().map_err(|e| {
+            minicbor::decode::Error::message(format!("failed to get module name: {}", e))
+        })?;
+        crate::get_module_registry()
+            .map_err(minicbor::decode::Error::message)?
+            .borrow_mut()
+            .add_declared(&name);
+        _ = m.eval().catch(&ctx).map_err(|e| {
+            minicbor::decode::Error::message(format!("failed eval bytecode: {}", e.to_string()))
+        })?;
         Ok(())
     })?;
 
@@ -136,26 +217,27 @@ fn cbor_decode_run_load_module_js(
 ) -> Result<(), minicbor::decode::Error> {
     cbor::utils::array_fixed_length(decoder, 2)?;
 
-    let module_name = decoder.str()?;
+    let module_name = crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .register(decoder.str()?, modules::Source::Declared);
     let module_code = decoder.bytes()?;
 
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        Module::declare(ctx.clone(), module_name, module_code)
-            .catch(&ctx)
-            .map_err(|e| {
-                minicbor::decode::Error::message(format!(
-                    "failed load module code: {}",
-                    e.to_string()
-                ))
-            })?
-            .eval()
-            .catch(&ctx)
-            .map_err(|e| {
-                minicbor::decode::Error::message(format!(
-                    "failed eval module code: {}",
-                    e.to_string()
-                ))
-            })?;
+        Module::declare(
+            ctx.clone(),
+            module_name,
+            modules::strip_import_attributes(module_code),
+        )
+        .catch(&ctx)
+        .map_err(|e| {
+            minicbor::decode::Error::message(format!("failed load module code: {}", e.to_string()))
+        })?
+        .eval()
+        .catch(&ctx)
+        .map_err(|e| {
+            minicbor::decode::Error::message(format!("failed eval module code: {}", e.to_string()))
+        })?;
         Ok(())
     })?;
 
@@ -166,19 +248,30 @@ fn cbor_decode_run_call_module_function(
     decoder: &mut Decoder,
     ctx: &Context,
 ) -> Result<(), minicbor::decode::Error> {
-    cbor::utils::array_fixed_length(decoder, 3)?;
+    let len = cbor::utils::array_length(decoder)?;
+    if len != 3 && len != 4 {
+        return Err(
+            minicbor::decode::Error::type_mismatch(minicbor::data::Type::Array)
+                .with_message("mismatch length"),
+        );
+    }
 
     let module_name = decoder.str()?;
     let fn_name = decoder.str()?;
+    let flags = if len == 4 { decoder.u8()? } else { 0 };
 
     _ = ctx.with(|ctx| -> Result<(), minicbor::decode::Error> {
-        let arguments: Vec<rquickjs::Value> =
-            cbor::rquickjs::args::array(&ctx, decoder).map_err(|e| {
-                minicbor::decode::Error::message(format!(
-                    "failed to deserialize arguments: {}",
-                    e.to_string()
-                ))
-            })?;
+        let arguments: Vec<rquickjs::Value> = cbor::rquickjs::args::array(
+            &ctx,
+            decoder,
+            crate::get_decode_options().with_flags(flags),
+        )
+        .map_err(|e| {
+            minicbor::decode::Error::message(format!(
+                "failed to deserialize arguments: {}",
+                e.to_string()
+            ))
+        })?;
 
         let mut args = Args::new(ctx.clone(), arguments.len());
         args.push_args(arguments).map_err(|e| {
@@ -214,6 +307,169 @@ fn cbor_decode_run_call_module_function(
     Ok(())
 }
 
+pub(crate) fn cbor_decode_number_policy(
+    decoder: &mut Decoder,
+) -> Result<(NumberPolicy, bool), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let numbers = decoder.str()?;
+    let numbers = NumberPolicy::parse(numbers).ok_or_else(|| {
+        minicbor::decode::Error::message(format!("unsupported number policy {}", numbers))
+    })?;
+    let integral_floats_as_ints = decoder.bool()?;
+
+    Ok((numbers, integral_floats_as_ints))
+}
+
+fn cbor_decode_run_load_number_policy(
+    decoder: &mut Decoder,
+) -> Result<(), minicbor::decode::Error> {
+    let (numbers, integral_floats_as_ints) = cbor_decode_number_policy(decoder)?;
+    crate::set_number_policy(numbers, integral_floats_as_ints);
+    Ok(())
+}
+
+fn cbor_decode_run_load_schema(decoder: &mut Decoder) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let fn_name = decoder.str()?;
+    // null removes the schema
+    let schema = if decoder.datatype()? == minicbor::data::Type::Null {
+        decoder.null()?;
+        None
+    } else {
+        Some(cbor::schema::Value::decode(decoder)?)
+    };
+    crate::set_schema(fn_name, schema);
+    Ok(())
+}
+
+fn cbor_decode_run_load_asset(
+    decoder: &mut Decoder,
+    ctx: &Context,
+) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 3)?;
+
+    let name = decoder.str()?;
+    let data = decoder.bytes()?;
+    // empty to detect the mime type
+    let mime = decoder.str()?;
+
+    ctx.with(|ctx| {
+        crate::host::add_asset(&ctx, name, data, mime)
+            .catch(&ctx)
+            .map_err(|e| minicbor::decode::Error::message(format!("failed to add asset: {}", e)))
+    })
+}
+
+fn cbor_decode_run_register_module(decoder: &mut Decoder) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let module_name = decoder.str()?;
+    let module_code = decoder.bytes()?;
+
+    crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .register(module_name, modules::Source::Js(module_code.to_vec()));
+    Ok(())
+}
+
+fn cbor_decode_run_load_commonjs(decoder: &mut Decoder) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let module_name = decoder.str()?;
+    let module_code = decoder.bytes()?;
+
+    crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .register(module_name, modules::Source::CommonJs(module_code.to_vec()));
+    Ok(())
+}
+
+fn cbor_decode_run_register_synthetic_module(
+    decoder: &mut Decoder,
+    header: u8,
+) -> Result<(), minicbor::decode::Error> {
+    cbor::utils::array_fixed_length(decoder, 2)?;
+
+    let module_name = decoder.str()?;
+    let source = match header {
+        LOAD_JSON_MODULE => {
+            modules::Source::Json(cbor::json::validated_str(decoder.bytes()?)?.to_string())
+        }
+        LOAD_TEXT_MODULE => modules::Source::Text(decoder.str()?.to_string()),
+        _ => modules::Source::Bytes(decoder.bytes()?.to_vec()),
+    };
+
+    crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .register(module_name, source);
+    Ok(())
+}
+
+fn cbor_decode_run_module_base(decoder: &mut Decoder) -> Result<(), minicbor::decode::Error> {
+    let base = decoder.str()?;
+
+    crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .set_base(base);
+    Ok(())
+}
+
+fn cbor_decode_specifier_map(
+    decoder: &mut Decoder,
+) -> Result<modules::SpecifierMap, minicbor::decode::Error> {
+    let mut map = Vec::new();
+    for _ in 0..cbor::utils::map_length(decoder)? {
+        let key = decoder.str()?.to_string();
+        // null blocks the specifier
+        let address = if decoder.datatype()? == minicbor::data::Type::Null {
+            decoder.null()?;
+            None
+        } else {
+            Some(decoder.str()?.to_string())
+        };
+        map.push((key, address));
+    }
+    Ok(map)
+}
+
+pub(crate) fn cbor_decode_import_map(
+    decoder: &mut Decoder,
+) -> Result<(modules::SpecifierMap, Vec<(String, modules::SpecifierMap)>), minicbor::decode::Error>
+{
+    let mut imports = Vec::new();
+    let mut scopes = Vec::new();
+    for _ in 0..cbor::utils::map_length(decoder)? {
+        match decoder.str()? {
+            "imports" => imports = cbor_decode_specifier_map(decoder)?,
+            "scopes" => {
+                for _ in 0..cbor::utils::map_length(decoder)? {
+                    let scope = decoder.str()?.to_string();
+                    scopes.push((scope, cbor_decode_specifier_map(decoder)?));
+                }
+            }
+            // like `integrity`, which has no meaning here
+            _ => decoder.skip()?,
+        }
+    }
+    Ok((imports, scopes))
+}
+
+fn cbor_decode_run_import_map(decoder: &mut Decoder) -> Result<(), minicbor::decode::Error> {
+    let (imports, scopes) = cbor_decode_import_map(decoder)?;
+
+    crate::get_module_registry()
+        .map_err(minicbor::decode::Error::message)?
+        .borrow_mut()
+        .add_import_map(imports, scopes);
+    Ok(())
+}
+
 pub(crate) fn cbor_decode_run_load(
     decoder: &mut Decoder,
     ctx: &Context,
@@ -243,6 +499,36 @@ pub(crate) fn cbor_decode_run_load(
                 &LOAD_CALL_MODULE_FUNCTION => {
                     cbor_decode_run_call_module_function(&mut Decoder::new(&b[1..]), ctx)?;
                 }
+                &LOAD_NUMBER_POLICY => {
+                    cbor_decode_run_load_number_policy(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_EVAL_WITH => {
+                    cbor_decode_run_load_eval_with(&mut Decoder::new(&b[1..]), ctx)?;
+                }
+                &LOAD_DEFINE_GLOBALS => {
+                    cbor_decode_run_load_define_globals(&mut Decoder::new(&b[1..]), ctx)?;
+                }
+                &LOAD_SCHEMA => {
+                    cbor_decode_run_load_schema(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_ASSET => {
+                    cbor_decode_run_load_asset(&mut Decoder::new(&b[1..]), ctx)?;
+                }
+                &LOAD_REGISTER_MODULE => {
+                    cbor_decode_run_register_module(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_MODULE_BASE => {
+                    cbor_decode_run_module_base(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_IMPORT_MAP => {
+                    cbor_decode_run_import_map(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_COMMONJS => {
+                    cbor_decode_run_load_commonjs(&mut Decoder::new(&b[1..]))?;
+                }
+                &LOAD_JSON_MODULE | &LOAD_TEXT_MODULE | &LOAD_BYTES_MODULE => {
+                    cbor_decode_run_register_synthetic_module(&mut Decoder::new(&b[1..]), *h)?;
+                }
                 _ => Err(minicbor::decode::Error::message(format!(
                     "unsupported header {}",
                     h
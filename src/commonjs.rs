@@ -0,0 +1,241 @@
+use rquickjs::{module::Declared, Ctx, Exception, Function, Module, Object, Value};
+
+use crate::cbor::jsstring;
+use crate::modules::SharedRegistry;
+
+// hidden global which holds the module cache and the require functions
+const COMMONJS: &str = "__ctxjs_commonjs";
+
+// valid identifiers which can not be bound in a module, they stay reachable
+// through the default export
+const STRICT_NAMES: [&str; 2] = ["arguments", "eval"];
+
+// modules are cached before they run, so cyclic requires see the unfinished
+// exports like in node, a module which throws is removed from the cache
+const PRELUDE: &str = r##"((native) => {
+  const cache = new Map();
+
+  const dirname = (name) => name.includes("/") ? name.slice(0, name.lastIndexOf("/")) : "";
+
+  const load = (name) => {
+    const cached = cache.get(name);
+    if (cached) return cached.exports;
+
+    const code = native.source(name);
+    const module = { id: name, filename: name, exports: {}, loaded: false };
+    cache.set(name, module);
+    try {
+      const fn = (0, eval)("(function (exports, require, module, __filename, __dirname) {\n" + code + "\n})");
+      fn.call(module.exports, module.exports, requireFrom(name), module, name, dirname(name));
+    } catch (e) {
+      cache.delete(name);
+      throw e;
+    }
+    module.loaded = true;
+    return module.exports;
+  };
+
+  const requireFrom = (referrer) => {
+    const require = (specifier) => load(native.resolve(referrer, String(specifier)));
+    require.resolve = (specifier) => native.resolve(referrer, String(specifier));
+    return require;
+  };
+
+  const require = requireFrom("");
+  Object.defineProperty(globalThis, "__ctxjs_commonjs", {
+    value: Object.freeze({ load, require }),
+  });
+  // a `require` defined by the code of the context wins
+  if (!("require" in globalThis)) {
+    Object.defineProperty(globalThis, "require", {
+      value: require,
+      writable: true,
+      configurable: true,
+    });
+  }
+})"##;
+
+fn native<'js>(ctx: &Ctx<'js>, registry: SharedRegistry) -> rquickjs::Result<Object<'js>> {
+    let native = Object::new(ctx.clone())?;
+    let resolve_registry = registry.clone();
+    native.set(
+        "resolve",
+        Function::new(
+            ctx.clone(),
+            move |ctx: Ctx<'js>, referrer: String, specifier: String| {
+                resolve_registry
+                    .borrow()
+                    .resolve(&referrer, &specifier)
+                    .map_err(|e| Exception::throw_reference(&ctx, &e))
+            },
+        )?,
+    )?;
+    native.set(
+        "source",
+        Function::new(
+            ctx.clone(),
+            move |ctx: Ctx<'js>, name: String| -> rquickjs::Result<String> {
+                let source = registry
+                    .borrow()
+                    .commonjs_source(&name)
+                    .map_err(|e| Exception::throw_reference(&ctx, &e))?;
+                String::from_utf8(source).map_err(|e| {
+                    Exception::throw_syntax(&ctx, &format!("invalid module {}: {}", name, e))
+                })
+            },
+        )?,
+    )?;
+    Ok(native)
+}
+
+// installs `require` with the module cache, as hidden global and as global
+// `require` unless there is one already
+pub(crate) fn install<'js>(ctx: &Ctx<'js>, registry: SharedRegistry) -> rquickjs::Result<()> {
+    ctx.eval::<Function, _>(PRELUDE)?
+        .call::<_, ()>((native(ctx, registry)?,))
+}
+
+// runs the commonjs module and declares an es module with `module.exports` as
+// default export and its properties at this time as named exports
+pub(crate) fn declare<'js>(ctx: &Ctx<'js>, name: &str) -> rquickjs::Result<Module<'js, Declared>> {
+    let commonjs: Object = ctx.globals().get(COMMONJS)?;
+    let load: Function = commonjs.get("load")?;
+    let exports: Value = load.call((name,))?;
+
+    let mut source = format!(
+        "const exports = globalThis.{}.load({});\nexport default exports;\n",
+        COMMONJS,
+        jsstring::string(name)
+    );
+    if let Some(object) = exports.as_object() {
+        for key in object.keys::<String>() {
+            let key = key?;
+            if key != "default"
+                && crate::define_vars::is_identifier(&key)
+                && !STRICT_NAMES.contains(&key.as_str())
+            {
+                source += &format!("export const {0} = exports.{0};\n", key);
+            }
+        }
+    }
+    Module::declare(ctx.clone(), name, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use rquickjs::{CatchResultExt, Context, Ctx, Module, Runtime};
+
+    use crate::modules::{self, Source};
+
+    fn import<'js>(ctx: &Ctx<'js>, name: &str) -> Result<rquickjs::Object<'js>, String> {
+        Module::import(ctx, name)
+            .and_then(|promise| promise.finish())
+            .catch(ctx)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_commonjs() {
+        let runtime = Runtime::new().unwrap();
+        let registry = modules::install(&runtime);
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx, registry.clone()).unwrap();
+            let mut r = registry.borrow_mut();
+            r.register(
+                "lib/index.js",
+                Source::CommonJs(
+                    b"const { twice } = require('./math');
+                    globalThis.runs = (globalThis.runs || 0) + 1;
+                    exports.value = twice(21);
+                    exports.class = 'reserved';
+                    exports.eval = 'eval';
+                    exports.arguments = 'arguments';
+                    exports.yield = 'yield';
+                    exports.dir = __dirname;"
+                        .to_vec(),
+                ),
+            );
+            r.register(
+                "lib/math.js",
+                Source::CommonJs(b"module.exports = { twice: (x) => 2 * x };".to_vec()),
+            );
+            // the umd pattern
+            r.register(
+                "umd.js",
+                Source::CommonJs(
+                    b"(function (root, factory) {
+                      if (typeof module === 'object' && module.exports) module.exports = factory();
+                      else root.umd = factory();
+                    })(this, function () { return { name: 'umd' }; });"
+                        .to_vec(),
+                ),
+            );
+            r.register("esm.js", Source::Js(b"export const a = 1;".to_vec()));
+            r.register("broken.js", Source::CommonJs(b"throw new Error('broken');".to_vec()));
+            drop(r);
+
+            assert_eq!(
+                ctx.eval::<i32, _>("__ctxjs_commonjs.require('lib').value")
+                    .unwrap(),
+                42
+            );
+            assert_eq!(
+                ctx.eval::<String, _>("__ctxjs_commonjs.require('./lib/index.js').dir")
+                    .unwrap(),
+                "lib"
+            );
+
+            let m = import(&ctx, "lib").unwrap();
+            assert_eq!(m.get::<_, i32>("value").unwrap(), 42);
+            assert!(!m.contains_key("class").unwrap());
+            let default: rquickjs::Object = m.get("default").unwrap();
+            assert_eq!(default.get::<_, String>("class").unwrap(), "reserved");
+            for key in ["eval", "arguments", "yield"] {
+                assert!(!m.contains_key(key).unwrap());
+                assert_eq!(default.get::<_, String>(key).unwrap(), key);
+            }
+            // cached, not evaluated again
+            assert_eq!(ctx.eval::<i32, _>("globalThis.runs").unwrap(), 1);
+
+            let m = import(&ctx, "umd").unwrap();
+            assert_eq!(m.get::<_, String>("name").unwrap(), "umd");
+
+            Module::declare(
+                ctx.clone(),
+                "main.js",
+                "import math, { twice } from './lib/math.js'; export const value = twice(math.twice(2));",
+            )
+            .unwrap()
+            .eval()
+            .catch(&ctx)
+            .unwrap();
+            let m = import(&ctx, "main.js").unwrap();
+            assert_eq!(m.get::<_, i32>("value").unwrap(), 8);
+
+            assert!(ctx
+                .eval::<(), _>("__ctxjs_commonjs.require('esm.js')")
+                .catch(&ctx)
+                .unwrap_err()
+                .to_string()
+                .contains("es module"));
+            assert!(ctx.eval::<(), _>("__ctxjs_commonjs.require('missing')").is_err());
+            assert!(import(&ctx, "broken").unwrap_err().contains("broken"));
+
+            assert_eq!(ctx.eval::<i32, _>("require('lib').value").catch(&ctx).unwrap(), 42);
+        });
+    }
+
+    #[test]
+    fn test_existing_require() {
+        let runtime = Runtime::new().unwrap();
+        let registry = modules::install(&runtime);
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            ctx.eval::<(), _>("globalThis.require = () => 'own';")
+                .unwrap();
+            super::install(&ctx, registry.clone()).unwrap();
+            assert_eq!(ctx.eval::<String, _>("require('lib')").unwrap(), "own");
+        });
+    }
+}
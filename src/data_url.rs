@@ -0,0 +1,206 @@
+use base64::Engine as _;
+
+// https://www.rfc-editor.org/rfc/rfc2397 and https://fetch.spec.whatwg.org/#data-urls
+
+// infer still uses the deprecated font types
+fn font_mime_type(extension: &str) -> Option<&'static str> {
+    match extension {
+        "woff" => Some("font/woff"),
+        "woff2" => Some("font/woff2"),
+        "ttf" => Some("font/ttf"),
+        "otf" => Some("font/otf"),
+        _ => None,
+    }
+}
+
+// detects the mime type, images first, then everything infer knows, json and
+// text
+pub fn sniff_mime_type(data: &[u8]) -> String {
+    if let Ok(mime) = crate::image_mime_type(data, "") {
+        return mime.to_string();
+    }
+    if let Some(t) = infer::get(data) {
+        return font_mime_type(t.extension())
+            .unwrap_or(t.mime_type())
+            .to_string();
+    }
+    if crate::cbor::json::validate(data).is_ok() {
+        return "application/json".to_string();
+    }
+    if std::str::from_utf8(data).is_ok() {
+        return "text/plain".to_string();
+    }
+    "application/octet-stream".to_string()
+}
+
+// a base64 data url, the mime type is detected if it is empty
+pub fn encode(data: &[u8], mime: &str, charset: &str) -> String {
+    let mime = if mime.is_empty() {
+        sniff_mime_type(data)
+    } else {
+        mime.to_string()
+    };
+    let charset = if charset.is_empty() {
+        String::new()
+    } else {
+        format!(";charset={}", charset)
+    };
+    format!(
+        "data:{}{};base64,{}",
+        mime,
+        charset,
+        base64::prelude::BASE64_STANDARD.encode(data)
+    )
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DataUrl {
+    // lowercase `type/subtype`
+    pub mime: String,
+    // lowercase names, in order of appearance
+    pub params: Vec<(String, String)>,
+    pub base64: bool,
+    pub data: Vec<u8>,
+}
+
+fn hex(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|d| d as u8)
+}
+
+pub fn percent_decode(s: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        match (
+            s[i],
+            s.get(i + 1).copied().and_then(hex),
+            s.get(i + 2).copied().and_then(hex),
+        ) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push(high << 4 | low);
+                i += 3;
+            }
+            (b, _, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+// whitespace is ignored and the padding is optional
+pub fn base64_decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut data = data.to_vec();
+    data.retain(|b| !b.is_ascii_whitespace());
+    base64::prelude::BASE64_STANDARD
+        .decode(&data)
+        .or_else(|_| base64::prelude::BASE64_STANDARD_NO_PAD.decode(&data))
+        .map_err(|e| format!("invalid base64 data: {}", e))
+}
+
+pub fn parse(url: &str) -> Result<DataUrl, String> {
+    let url = url.trim();
+    let rest = url
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        .map(|_| &url[5..])
+        .ok_or_else(|| "not a data url".to_string())?;
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "missing comma in data url".to_string())?;
+
+    let mut parts: Vec<&str> = header.split(';').map(str::trim).collect();
+    let base64 = parts.len() > 1
+        && parts
+            .last()
+            .is_some_and(|part| part.eq_ignore_ascii_case("base64"));
+    if base64 {
+        parts.pop();
+    }
+
+    let mut mime = parts[0].to_ascii_lowercase();
+    let mut params = Vec::new();
+    for param in &parts[1..] {
+        if let Some((name, value)) = param.split_once('=') {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            params.push((name.trim().to_ascii_lowercase(), value.to_string()));
+        }
+    }
+    if mime.is_empty() {
+        // the default of rfc 2397
+        mime = "text/plain".to_string();
+        if params.is_empty() {
+            params.push(("charset".to_string(), "US-ASCII".to_string()));
+        }
+    } else if !mime.contains('/') {
+        return Err(format!("invalid mime type {}", mime));
+    }
+
+    let mut data = percent_decode(data.as_bytes());
+    if base64 {
+        data = base64_decode(&data)?;
+    }
+
+    Ok(DataUrl {
+        mime,
+        params,
+        base64,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, parse, sniff_mime_type, DataUrl};
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            encode(b"a", "text/css", "utf-8"),
+            "data:text/css;charset=utf-8;base64,YQ=="
+        );
+        assert_eq!(sniff_mime_type(b"wOF2\0\x01\0\0"), "font/woff2");
+        assert_eq!(sniff_mime_type(b"\0\x01\0\0\0"), "font/ttf");
+        assert_eq!(sniff_mime_type(b"<svg></svg>"), "image/svg+xml");
+        assert_eq!(sniff_mime_type(b"{\"a\": 1}"), "application/json");
+        assert_eq!(sniff_mime_type(b"a { color: red }"), "text/plain");
+        assert_eq!(sniff_mime_type(b"\xff\xfe\xfd"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("data:,A%20brief%20note").unwrap(),
+            DataUrl {
+                mime: "text/plain".to_string(),
+                params: vec![("charset".to_string(), "US-ASCII".to_string())],
+                base64: false,
+                data: b"A brief note".to_vec(),
+            }
+        );
+        assert_eq!(
+            parse("DATA:Image/SVG+xml;Charset=\"utf-8\",%3Csvg%3E%3C/svg%3E").unwrap(),
+            DataUrl {
+                mime: "image/svg+xml".to_string(),
+                params: vec![("charset".to_string(), "utf-8".to_string())],
+                base64: false,
+                data: b"<svg></svg>".to_vec(),
+            }
+        );
+
+        let url = encode(&[0, 1, 2, 255], "application/octet-stream", "");
+        assert_eq!(parse(&url).unwrap().data, vec![0, 1, 2, 255]);
+        assert_eq!(parse("data:;base64,YW Jj").unwrap().data, b"abc");
+        assert_eq!(parse("data:text/plain;base64,YQ%3D%3D").unwrap().data, b"a");
+
+        assert!(parse("http://example.com").is_err());
+        assert!(parse("data:text/plain").is_err());
+        assert!(parse("data:;base64,!!").is_err());
+        assert!(parse("data:plain,a").is_err());
+    }
+}
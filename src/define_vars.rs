@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use minicbor::Decoder;
+use rquickjs::{CatchResultExt, Ctx, Object, Value};
+
+use crate::cbor::{self, rquickjs::DecodeOptions};
+
+const RESERVED_WORDS: [&str; 46] = [
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+// only ascii identifiers are supported
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && !RESERVED_WORDS.contains(&name)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum DefineMode {
+    #[default]
+    Let,
+    Const,
+    Var,
+    // property of `globalThis`
+    Global,
+    // assigns an existing binding or creates a global property
+    Assign,
+}
+
+impl DefineMode {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s {
+            "let" => Some(DefineMode::Let),
+            "const" => Some(DefineMode::Const),
+            "var" => Some(DefineMode::Var),
+            "global" => Some(DefineMode::Global),
+            "assign" => Some(DefineMode::Assign),
+            _ => None,
+        }
+    }
+}
+
+// the generated code has to be evaluated as global non strict script
+pub(crate) fn source(variables: HashMap<&str, String>, mode: DefineMode) -> Result<String, String> {
+    let mut variables: Vec<(&str, String)> = variables.into_iter().collect();
+    variables.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut source = String::new();
+    for (name, value) in variables {
+        if !is_identifier(name) {
+            return Err(format!("invalid variable name {}", name));
+        }
+        source += &match mode {
+            DefineMode::Let => format!("let {}={};", name, value),
+            DefineMode::Const => format!("const {}={};", name, value),
+            DefineMode::Var => format!("var {}={};", name, value),
+            DefineMode::Global => format!("globalThis.{}={};", name, value),
+            DefineMode::Assign => format!("{}={};", name, value),
+        };
+    }
+    Ok(source)
+}
+
+// path segments which would reach a prototype instead of a plain property
+const PROTOTYPE_KEYS: [&str; 3] = ["__proto__", "constructor", "prototype"];
+
+// sets the decoded values directly as properties of `globalThis`, a path like
+// `config.theme` creates missing objects on the way
+pub(crate) fn define_globals<'js>(
+    ctx: &Ctx<'js>,
+    decoder: &mut Decoder,
+    options: DecodeOptions,
+) -> Result<(), minicbor::decode::Error> {
+    for _ in 0..cbor::utils::map_length(decoder)? {
+        let path = decoder.str()?;
+        let value = cbor::rquickjs::decode(decoder, ctx, options)?;
+
+        let mut segments: Vec<&str> = path.split('.').collect();
+        if !segments
+            .iter()
+            .all(|s| is_identifier(s) && !PROTOTYPE_KEYS.contains(s))
+        {
+            return Err(minicbor::decode::Error::message(format!(
+                "invalid variable path {}",
+                path
+            )));
+        }
+        let name = segments.pop().unwrap_or_default();
+
+        let mut object = ctx.globals();
+        for segment in segments {
+            let next: Value = object
+                .get(segment)
+                .catch(ctx)
+                .map_err(minicbor::decode::Error::message)?;
+            object = if next.is_undefined() {
+                let next = Object::new(ctx.clone()).map_err(minicbor::decode::Error::message)?;
+                object
+                    .set(segment, next.clone())
+                    .catch(ctx)
+                    .map_err(minicbor::decode::Error::message)?;
+                next
+            } else {
+                next.into_object().ok_or_else(|| {
+                    minicbor::decode::Error::message(format!(
+                        "{} in {} is not an object",
+                        segment, path
+                    ))
+                })?
+            };
+        }
+        object
+            .set(name, value)
+            .catch(ctx)
+            .map_err(minicbor::decode::Error::message)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rquickjs::{context::EvalOptions, CatchResultExt, Context, Ctx, Runtime};
+
+    use super::{is_identifier, source, DefineMode};
+
+    fn define(ctx: &Ctx, name: &str, value: &str, mode: DefineMode) -> Result<(), String> {
+        let mut options = EvalOptions::default();
+        options.global = true;
+        options.strict = false;
+        let js = source(HashMap::from([(name, value.to_string())]), mode)?;
+        ctx.eval_with_options::<(), _>(js, options)
+            .catch(ctx)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert!(is_identifier("value"));
+        assert!(is_identifier("_$value1"));
+        assert!(is_identifier("letter"));
+        assert!(!is_identifier("1value"));
+        assert!(!is_identifier("my-value"));
+        assert!(!is_identifier("let"));
+        assert!(!is_identifier("yield"));
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn test_modes() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            assert!(define(&ctx, "a", "1", DefineMode::Let).is_ok());
+            assert!(define(&ctx, "a", "2", DefineMode::Let).is_err());
+            assert!(define(&ctx, "a", "3", DefineMode::Assign).is_ok());
+            assert_eq!(ctx.eval::<i32, _>("a").unwrap(), 3);
+
+            assert!(define(&ctx, "b", "1", DefineMode::Var).is_ok());
+            assert!(define(&ctx, "b", "2", DefineMode::Var).is_ok());
+            assert_eq!(ctx.eval::<i32, _>("globalThis.b").unwrap(), 2);
+
+            assert!(define(&ctx, "c", "1", DefineMode::Global).is_ok());
+            assert!(define(&ctx, "c", "2", DefineMode::Global).is_ok());
+            assert_eq!(ctx.eval::<i32, _>("c").unwrap(), 2);
+
+            assert!(define(&ctx, "d", "1", DefineMode::Assign).is_ok());
+            assert_eq!(ctx.eval::<i32, _>("globalThis.d").unwrap(), 1);
+
+            assert!(define(&ctx, "e", "1", DefineMode::Const).is_ok());
+            assert!(define(&ctx, "e", "2", DefineMode::Assign).is_err());
+
+            assert_eq!(
+                define(&ctx, "my-var", "1", DefineMode::Let),
+                Err("invalid variable name my-var".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn test_define_globals() {
+        let mut data = vec![];
+        minicbor::Encoder::new(&mut data)
+            .map(4)
+            .and_then(|e| e.str("a")?.i64(1)?.str("config.theme")?.str("dark"))
+            .and_then(|e| {
+                e.str("config.size")?
+                    .f64(f64::NAN)?
+                    .str("big")?
+                    .u64(u64::MAX)
+            })
+            .unwrap();
+
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            ctx.eval::<(), _>("globalThis.config = { keep: true };")
+                .unwrap();
+            super::define_globals(&ctx, &mut minicbor::Decoder::new(&data), Default::default())
+                .unwrap();
+            assert_eq!(
+                ctx.eval::<String, _>(
+                    "[a, config.theme, config.keep, isNaN(config.size), typeof big].join()"
+                )
+                .unwrap(),
+                "1,dark,true,true,bigint"
+            );
+
+            let define = |path: &str| {
+                let mut data = vec![];
+                minicbor::Encoder::new(&mut data)
+                    .map(1)
+                    .and_then(|e| e.str(path)?.i64(1))
+                    .unwrap();
+                super::define_globals(&ctx, &mut minicbor::Decoder::new(&data), Default::default())
+                    .map_err(|e| e.to_string())
+            };
+            assert!(define("a.b").is_err());
+            for path in [
+                "x.__proto__.polluted",
+                "config.constructor.prototype.polluted",
+                "__proto__.polluted",
+                "config.a-b",
+                "config..a",
+            ] {
+                assert!(define(path).unwrap_err().contains("invalid variable path"));
+            }
+            assert!(ctx.eval::<bool, _>("({}).polluted === undefined").unwrap());
+
+            // exceptions of setters keep their message
+            ctx.eval::<(), _>(
+                "globalThis.guarded = { set value(v) { throw new Error('guarded setter'); } };",
+            )
+            .unwrap();
+            assert!(define("guarded.value")
+                .unwrap_err()
+                .contains("guarded setter"));
+        });
+    }
+}
@@ -0,0 +1,348 @@
+use base64::Engine as _;
+use rquickjs::{Ctx, Exception, Function, Module, Object, TypedArray, Value};
+
+// the built-in module, `import { dataUrl, base64 } from "ctxjs"`
+const MODULE_NAME: &str = "ctxjs";
+
+// hidden global to register and look up the named assets
+pub(crate) const ASSETS: &str = "__ctxjs_assets";
+
+// the api is also stored in the hidden global `__ctxjs_host` for the module,
+// the `ctxjs` global can be replaced by js code
+const PRELUDE: &str = r##"((native) => {
+  // strings are utf-8 encoded, array buffers and views are read without copying
+  const bytes = (data) => {
+    if (typeof data === "string" || data instanceof Uint8Array) return data;
+    if (data instanceof ArrayBuffer) return new Uint8Array(data);
+    if (ArrayBuffer.isView(data)) return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+    throw new TypeError("expected a string, an ArrayBuffer or a typed array");
+  };
+
+  // name -> { name, mime, data }, typed arrays can not be frozen, so every
+  // access gets a copy of the data and changes can not leak into later calls
+  const assets = new Map();
+  const asset = (name) => {
+    const stored = assets.get(name);
+    return stored && Object.freeze({ ...stored, data: stored.data.slice() });
+  };
+
+  const ctxjs = Object.freeze({
+    assets: Object.freeze({
+      get: (name) => asset(String(name)),
+      has: (name) => assets.has(String(name)),
+      list: () => [...assets.keys()],
+    }),
+    base64: Object.freeze({
+      encode: (data) => native.base64Encode(bytes(data)),
+      decode: (text) => native.base64Decode(String(text)),
+    }),
+    dataUrl: (data, mime = "", charset = "") => native.dataUrl(bytes(data), String(mime), String(charset)),
+    imageDataUrl: (data, format = "") => native.imageDataUrl(bytes(data), String(format)),
+    svgDataUrl: (svg, sanitize = false) => native.svgDataUrl(bytes(svg), Boolean(sanitize)),
+    isSvg: (data) => native.isSvg(bytes(data)),
+    imageFormat: (data) => native.imageFormat(bytes(data)) ?? null,
+    mimeType: (data) => native.mimeType(bytes(data)),
+  });
+
+  Object.defineProperty(globalThis, "__ctxjs_assets", {
+    value: Object.freeze({
+      add: (name, mime, data) => { assets.set(name, { name, mime, data }); },
+      get: asset,
+      data: (name) => {
+        if (!assets.has(name)) throw new ReferenceError(`unknown asset ${name}`);
+        return assets.get(name).data.slice();
+      },
+    }),
+  });
+  Object.defineProperty(globalThis, "__ctxjs_host", { value: ctxjs });
+  Object.defineProperty(globalThis, "ctxjs", { value: ctxjs, writable: true, configurable: true });
+})"##;
+
+const MODULE: &str = r##"const ctxjs = globalThis.__ctxjs_host;
+export const { assets, base64, dataUrl, imageDataUrl, svgDataUrl, isSvg, imageFormat, mimeType } = ctxjs;
+export default ctxjs;
+"##;
+
+fn bytes<'js>(ctx: &Ctx<'js>, data: &Value<'js>) -> rquickjs::Result<Vec<u8>> {
+    if let Some(text) = data.as_string() {
+        return Ok(text.to_string()?.into_bytes());
+    }
+    data.as_object()
+        .and_then(|object| object.as_typed_array::<u8>())
+        .and_then(|array| array.as_bytes())
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| Exception::throw_type(ctx, "expected a string or an Uint8Array"))
+}
+
+fn native<'js>(ctx: &Ctx<'js>) -> rquickjs::Result<Object<'js>> {
+    let native = Object::new(ctx.clone())?;
+    native.set(
+        "base64Encode",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>| -> rquickjs::Result<String> {
+                Ok(base64::prelude::BASE64_STANDARD.encode(bytes(&ctx, &data)?))
+            },
+        )?,
+    )?;
+    native.set(
+        "base64Decode",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, text: String| -> rquickjs::Result<TypedArray<'js, u8>> {
+                let data = crate::data_url::base64_decode(text.as_bytes())
+                    .map_err(|e| Exception::throw_message(&ctx, &e))?;
+                TypedArray::new(ctx, data)
+            },
+        )?,
+    )?;
+    native.set(
+        "dataUrl",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>, mime: String, charset: String| {
+                Ok::<_, rquickjs::Error>(crate::data_url::encode(
+                    &bytes(&ctx, &data)?,
+                    &mime,
+                    &charset,
+                ))
+            },
+        )?,
+    )?;
+    native.set(
+        "imageDataUrl",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>, format: String| -> rquickjs::Result<String> {
+                crate::encode_image_data_url(&bytes(&ctx, &data)?, &format)
+                    .map_err(|e| Exception::throw_message(&ctx, &e))
+            },
+        )?,
+    )?;
+    native.set(
+        "svgDataUrl",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, svg: Value<'js>, sanitize: bool| -> rquickjs::Result<String> {
+                let svg = String::from_utf8(bytes(&ctx, &svg)?)
+                    .map_err(|e| Exception::throw_type(&ctx, &format!("invalid svg: {}", e)))?;
+                if !sanitize {
+                    return Ok(crate::svg::data_url(&svg));
+                }
+                crate::svg::sanitize(&svg)
+                    .map(|svg| crate::svg::data_url(&svg))
+                    .map_err(|e| Exception::throw_message(&ctx, &e))
+            },
+        )?,
+    )?;
+    native.set(
+        "isSvg",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>| -> rquickjs::Result<bool> {
+                Ok(crate::is_svg(&bytes(&ctx, &data)?))
+            },
+        )?,
+    )?;
+    native.set(
+        "imageFormat",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>| -> rquickjs::Result<Option<&'static str>> {
+                Ok(crate::image_format(&bytes(&ctx, &data)?))
+            },
+        )?,
+    )?;
+    native.set(
+        "mimeType",
+        Function::new(
+            ctx.clone(),
+            |ctx: Ctx<'js>, data: Value<'js>| -> rquickjs::Result<String> {
+                Ok(crate::data_url::sniff_mime_type(&bytes(&ctx, &data)?))
+            },
+        )?,
+    )?;
+    Ok(native)
+}
+
+// registers or replaces a named asset, the mime type is detected if it is empty
+pub(crate) fn add_asset(ctx: &Ctx, name: &str, data: &[u8], mime: &str) -> rquickjs::Result<()> {
+    let mime = if mime.is_empty() {
+        crate::data_url::sniff_mime_type(data)
+    } else {
+        mime.to_string()
+    };
+    let assets: Object = ctx.globals().get(ASSETS)?;
+    let add: Function = assets.get("add")?;
+    add.call((name, mime, TypedArray::new(ctx.clone(), data)?))
+}
+
+// a copy of the bytes of an asset
+pub(crate) fn asset_data<'js>(ctx: &Ctx<'js>, name: &str) -> rquickjs::Result<Option<Value<'js>>> {
+    let assets: Object = ctx.globals().get(ASSETS)?;
+    let get: Function = assets.get("get")?;
+    get.call::<_, Option<Object>>((name,))?
+        .map(|asset| asset.get("data"))
+        .transpose()
+}
+
+// installs the `ctxjs` global and declares the `ctxjs` module
+pub(crate) fn install<'js>(ctx: &Ctx<'js>) -> rquickjs::Result<()> {
+    ctx.eval::<Function, _>(PRELUDE)?
+        .call::<_, ()>((native(ctx)?,))?;
+    _ = Module::declare(ctx.clone(), MODULE_NAME, MODULE)?.eval()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use minicbor::{Decoder, Encoder};
+    use rquickjs::{CatchResultExt, Context, Ctx, Module, Runtime};
+
+    use crate::cbor::{con, jsstring, rquickjs::decode};
+
+    fn eval<'js>(ctx: &Ctx<'js>, js: &str) -> String {
+        ctx.eval::<String, _>(js).unwrap()
+    }
+
+    #[test]
+    fn test_global() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+
+            assert_eq!(eval(&ctx, "ctxjs.base64.encode('abc')"), "YWJj");
+            assert_eq!(
+                eval(
+                    &ctx,
+                    "ctxjs.base64.encode(new Uint16Array([0x6261]).buffer)"
+                ),
+                "YWI="
+            );
+            assert_eq!(
+                eval(&ctx, "String(ctxjs.base64.decode('YW Jj'))"),
+                "97,98,99"
+            );
+            assert_eq!(
+                eval(&ctx, "ctxjs.dataUrl('a', 'text/css', 'utf-8')"),
+                "data:text/css;charset=utf-8;base64,YQ=="
+            );
+            assert_eq!(
+                eval(&ctx, "ctxjs.imageDataUrl('<svg></svg>')"),
+                "data:image/svg+xml;base64,PHN2Zz48L3N2Zz4="
+            );
+            assert_eq!(
+                eval(&ctx, "ctxjs.svgDataUrl('<svg onload=\"a()\"></svg>', true)"),
+                "data:image/svg+xml,%3Csvg%3E%3C/svg%3E"
+            );
+            assert_eq!(
+                eval(
+                    &ctx,
+                    "[ctxjs.isSvg('<svg/>'), ctxjs.isSvg(new Uint8Array([0x89]))].join()"
+                ),
+                "true,false"
+            );
+            assert_eq!(
+                eval(
+                    &ctx,
+                    "String(ctxjs.imageFormat(new Uint8Array([0x47, 0x49, 0x46, 0x38])))"
+                ),
+                "gif"
+            );
+            assert_eq!(eval(&ctx, "String(ctxjs.imageFormat('a'))"), "null");
+            assert_eq!(eval(&ctx, "ctxjs.mimeType('{}')"), "application/json");
+
+            assert!(ctx.eval::<(), _>("ctxjs.base64.decode('!')").is_err());
+            assert!(ctx.eval::<(), _>("ctxjs.base64.encode(1)").is_err());
+            assert!(ctx.eval::<(), _>("ctxjs.imageDataUrl('a')").is_err());
+        });
+    }
+
+    #[test]
+    fn test_assets() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+            super::add_asset(&ctx, "logo", b"<svg></svg>", "").unwrap();
+            super::add_asset(&ctx, "data", b"abc", "text/csv").unwrap();
+
+            assert_eq!(eval(&ctx, "ctxjs.assets.list().join()"), "logo,data");
+            assert_eq!(eval(&ctx, "ctxjs.assets.get('logo').mime"), "image/svg+xml");
+            assert_eq!(
+                eval(&ctx, "String(ctxjs.assets.get('data').data)"),
+                "97,98,99"
+            );
+            assert_eq!(eval(&ctx, "String(ctxjs.assets.has('font'))"), "false");
+            // changes of a copy are not visible to later accesses
+            assert_eq!(
+                eval(
+                    &ctx,
+                    "ctxjs.assets.get('data').data[0] = 0; String(ctxjs.assets.get('data').data)"
+                ),
+                "97,98,99"
+            );
+
+            let mut data = vec![];
+            Encoder::new(&mut data)
+                .array(2)
+                .and_then(|e| e.tag(con::ASSET)?.str("data")?.tag(con::ASSET)?.str("font"))
+                .unwrap();
+            let mut decoder = Decoder::new(&data);
+            decoder.array().unwrap();
+            let value = decode(&mut decoder, &ctx, Default::default()).unwrap();
+            ctx.globals().set("value", value).unwrap();
+            assert_eq!(
+                eval(&ctx, "value[0] = 0; String(ctxjs.assets.get('data').data)"),
+                "97,98,99"
+            );
+            assert!(decode(&mut decoder, &ctx, Default::default()).is_err());
+
+            let js = jsstring::decode(&mut Decoder::new(&data[1..]), Default::default()).unwrap();
+            assert_eq!(
+                ctx.eval::<String, _>(format!("const v = {}; v[1] = 0; String({})", js, js))
+                    .unwrap(),
+                "97,98,99"
+            );
+            assert!(ctx
+                .eval::<(), _>(
+                    jsstring::decode(&mut Decoder::new(&data[11..]), Default::default()).unwrap()
+                )
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_module() {
+        let runtime = Runtime::new().unwrap();
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install(&ctx).unwrap();
+
+            Module::declare(
+                ctx.clone(),
+                "test",
+                "import ctxjs, { base64, dataUrl } from 'ctxjs';
+                export const a = base64.encode('a');
+                export const b = dataUrl('b', 'text/plain');
+                export const c = ctxjs === globalThis.ctxjs;",
+            )
+            .unwrap()
+            .eval()
+            .catch(&ctx)
+            .unwrap();
+            let m: rquickjs::Object = Module::import(&ctx, "test")
+                .unwrap()
+                .finish()
+                .catch(&ctx)
+                .unwrap();
+            assert_eq!(m.get::<_, String>("a").unwrap(), "YQ==");
+            assert_eq!(
+                m.get::<_, String>("b").unwrap(),
+                "data:text/plain;base64,Yg=="
+            );
+            assert!(m.get::<_, bool>("c").unwrap());
+        });
+    }
+}
@@ -0,0 +1,503 @@
+// reads the size and a few properties from the image header, the image data
+// itself is never decoded
+
+#[derive(Debug, PartialEq)]
+pub struct ImageInfo {
+    pub format: &'static str,
+    // pixels, for svg css pixels (96 per inch)
+    pub width: f64,
+    pub height: f64,
+    // horizontal and vertical resolution, if the image stores it
+    pub dpi: Option<(f64, f64)>,
+    // none if the format does not tell it without decoding
+    pub alpha: Option<bool>,
+    // x, y, width and height
+    pub view_box: Option<[f64; 4]>,
+}
+
+impl ImageInfo {
+    fn new(format: &'static str, width: f64, height: f64) -> Self {
+        ImageInfo {
+            format,
+            width,
+            height,
+            dpi: None,
+            alpha: None,
+            view_box: None,
+        }
+    }
+}
+
+const INCH_PER_METER: f64 = 0.0254;
+
+fn u16_be(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_be(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u16_le(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u24_le(data: &[u8], at: usize) -> Option<u32> {
+    let b = data.get(at..at + 3)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+}
+
+fn u32_le(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+pub fn info(data: &[u8]) -> Option<ImageInfo> {
+    if infer::image::is_png(data) {
+        png(data)
+    } else if infer::image::is_jpeg(data) {
+        jpeg(data)
+    } else if infer::image::is_gif(data) {
+        gif(data)
+    } else if infer::image::is_webp(data) {
+        webp(data)
+    } else if infer::image::is_bmp(data) {
+        bmp(data)
+    } else if crate::is_svg(data) {
+        svg(data)
+    } else {
+        None
+    }
+}
+
+// https://www.w3.org/TR/png/
+fn png(data: &[u8]) -> Option<ImageInfo> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let mut info = ImageInfo::new("png", u32_be(data, 16)? as f64, u32_be(data, 20)? as f64);
+    // grayscale with alpha or rgba
+    info.alpha = Some(matches!(data.get(25)?, 4 | 6));
+
+    // pHYs and tRNS come before the image data
+    let mut at = 8;
+    while let (Some(len), Some(name)) = (u32_be(data, at), data.get(at + 4..at + 8)) {
+        match name {
+            b"IDAT" | b"IEND" => break,
+            b"tRNS" => info.alpha = Some(true),
+            // unit 1 is meter
+            b"pHYs" if data.get(at + 16) == Some(&1) => {
+                info.dpi = Some((
+                    u32_be(data, at + 8)? as f64 * INCH_PER_METER,
+                    u32_be(data, at + 12)? as f64 * INCH_PER_METER,
+                ));
+            }
+            _ => {}
+        }
+        // the length is untrusted, it must not wrap around (usize is 32 bit on wasm)
+        match at
+            .checked_add(12)
+            .and_then(|at| at.checked_add(len as usize))
+        {
+            Some(next) if next <= data.len() => at = next,
+            _ => break,
+        }
+    }
+    Some(info)
+}
+
+// https://www.w3.org/Graphics/JPEG/itu-t81.pdf and jfif
+fn jpeg(data: &[u8]) -> Option<ImageInfo> {
+    let mut dpi = None;
+    let mut at = 2;
+    loop {
+        // markers can be padded with 0xff
+        while data.get(at)? == &0xff && data.get(at + 1)? == &0xff {
+            at += 1;
+        }
+        if data.get(at)? != &0xff {
+            return None;
+        }
+        let marker = *data.get(at + 1)?;
+        if matches!(marker, 0x01 | 0xd0..=0xd9) {
+            // without length
+            at += 2;
+            continue;
+        }
+        let len = u16_be(data, at + 2)? as usize;
+        match marker {
+            // start of frame, except huffman (c4), arithmetic (cc) and jpeg extension (c8)
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let mut info = ImageInfo::new(
+                    "jpeg",
+                    u16_be(data, at + 7)? as f64,
+                    u16_be(data, at + 5)? as f64,
+                );
+                info.dpi = dpi;
+                info.alpha = Some(false);
+                return Some(info);
+            }
+            // jfif
+            0xe0 if data.get(at + 4..at + 9)? == b"JFIF\0" => {
+                let density = (u16_be(data, at + 12)? as f64, u16_be(data, at + 14)? as f64);
+                dpi = match data.get(at + 11)? {
+                    1 => Some(density),
+                    2 => Some((density.0 * 2.54, density.1 * 2.54)),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+        at += 2 + len;
+    }
+}
+
+// https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+fn gif(data: &[u8]) -> Option<ImageInfo> {
+    let mut info = ImageInfo::new("gif", u16_le(data, 6)? as f64, u16_le(data, 8)? as f64);
+    info.alpha = Some(false);
+
+    let flags = *data.get(10)?;
+    let mut at = 13;
+    if flags & 0x80 != 0 {
+        // global color table
+        at += 3 << ((flags & 0x07) + 1);
+    }
+    // the transparency of the graphic control extension before the first image
+    while let Some(0x21) = data.get(at) {
+        if data.get(at + 1) == Some(&0xf9) {
+            info.alpha = Some(data.get(at + 3)? & 0x01 != 0);
+        }
+        at += 2;
+        // sub-blocks
+        loop {
+            let len = *data.get(at)? as usize;
+            at += 1 + len;
+            if len == 0 {
+                break;
+            }
+        }
+    }
+    Some(info)
+}
+
+// https://developers.google.com/speed/webp/docs/riff_container
+fn webp(data: &[u8]) -> Option<ImageInfo> {
+    let (width, height, alpha) = match data.get(12..16)? {
+        b"VP8 " => {
+            if data.get(23..26)? != [0x9d, 0x01, 0x2a] {
+                return None;
+            }
+            (
+                u16_le(data, 26)? & 0x3fff,
+                u16_le(data, 28)? & 0x3fff,
+                false,
+            )
+        }
+        b"VP8L" => {
+            if data.get(20)? != &0x2f {
+                return None;
+            }
+            let bits = u32_le(data, 21)?;
+            (
+                ((bits & 0x3fff) + 1) as u16,
+                (((bits >> 14) & 0x3fff) + 1) as u16,
+                bits >> 28 & 0x01 != 0,
+            )
+        }
+        b"VP8X" => {
+            let flags = *data.get(20)?;
+            let mut info = ImageInfo::new(
+                "webp",
+                (u24_le(data, 24)? + 1) as f64,
+                (u24_le(data, 27)? + 1) as f64,
+            );
+            info.alpha = Some(flags & 0x10 != 0);
+            return Some(info);
+        }
+        _ => return None,
+    };
+    let mut info = ImageInfo::new("webp", width as f64, height as f64);
+    info.alpha = Some(alpha);
+    Some(info)
+}
+
+// https://learn.microsoft.com/en-us/windows/win32/gdi/bitmap-header-types
+fn bmp(data: &[u8]) -> Option<ImageInfo> {
+    let header = u32_le(data, 14)?;
+    if header == 12 {
+        // os/2 bitmap core header
+        let mut info = ImageInfo::new("bmp", u16_le(data, 18)? as f64, u16_le(data, 20)? as f64);
+        info.alpha = Some(false);
+        return Some(info);
+    }
+
+    let width = u32_le(data, 18)? as i32;
+    // negative for top-down bitmaps
+    let height = u32_le(data, 22)? as i32;
+    let mut info = ImageInfo::new(
+        "bmp",
+        width.unsigned_abs() as f64,
+        height.unsigned_abs() as f64,
+    );
+    let (x, y) = (u32_le(data, 38)?, u32_le(data, 42)?);
+    if x > 0 && y > 0 {
+        info.dpi = Some((x as f64 * INCH_PER_METER, y as f64 * INCH_PER_METER));
+    }
+    // 32 bits with an alpha mask (v3 header or later)
+    info.alpha = Some(u16_le(data, 28)? == 32 && header >= 56 && u32_le(data, 66)? != 0);
+    Some(info)
+}
+
+// the attributes of the first tag with the given name
+fn tag_attributes<'a>(data: &'a str, name: &str) -> Option<Vec<(&'a str, &'a str)>> {
+    let start = data.find(&format!("<{}", name))? + name.len() + 1;
+    let mut rest = &data[start..];
+    if !rest.starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/') {
+        return None;
+    }
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with('>') || rest.starts_with('/') {
+            return Some(attributes);
+        }
+        let (name, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let quote = after.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let (value, after) = after[1..].split_once(quote)?;
+        attributes.push((name.trim(), value));
+        rest = after;
+    }
+}
+
+// a length in css pixels, none for relative lengths (percent, em, ...)
+fn svg_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: f64 = number.parse().ok()?;
+    let factor = match unit.trim() {
+        "" | "px" => 1.0,
+        "pt" => 96.0 / 72.0,
+        "pc" => 16.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        _ => return None,
+    };
+    Some(number * factor)
+}
+
+// https://www.w3.org/TR/SVG2/coords.html#ViewBoxAttribute
+fn svg(data: &[u8]) -> Option<ImageInfo> {
+    let data = std::str::from_utf8(data).ok()?;
+    let attributes = tag_attributes(data, "svg")?;
+    let attribute = |name: &str| {
+        attributes
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| *value)
+    };
+
+    let view_box = attribute("viewBox").and_then(|value| {
+        let numbers: Vec<f64> = value
+            .split(|c: char| c.is_ascii_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().ok())
+            .collect::<Option<_>>()?;
+        <[f64; 4]>::try_from(numbers).ok()
+    });
+    let width = attribute("width").and_then(svg_length);
+    let height = attribute("height").and_then(svg_length);
+
+    // missing sizes follow the aspect ratio of the view box
+    let (width, height) = match (width, height, view_box) {
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Some([_, _, vw, vh])) if vw > 0.0 => (w, w * vh / vw),
+        (None, Some(h), Some([_, _, vw, vh])) if vh > 0.0 => (h * vw / vh, h),
+        (None, None, Some([_, _, vw, vh])) => (vw, vh),
+        // the default size of replaced elements
+        _ => (300.0, 150.0),
+    };
+
+    let mut info = ImageInfo::new("svg", width, height);
+    info.alpha = Some(true);
+    info.view_box = view_box;
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::{info, ImageInfo};
+
+    fn png(color_type: u8, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut ihdr = vec![];
+        ihdr.extend(640u32.to_be_bytes());
+        ihdr.extend(480u32.to_be_bytes());
+        ihdr.extend([8, color_type, 0, 0, 0]);
+        for (name, chunk) in [(b"IHDR", &ihdr[..])].iter().chain(chunks) {
+            data.extend((chunk.len() as u32).to_be_bytes());
+            data.extend(*name);
+            data.extend(*chunk);
+            // crc is not checked
+            data.extend([0; 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_png() {
+        let mut phys = vec![];
+        phys.extend(3780u32.to_be_bytes());
+        phys.extend(3780u32.to_be_bytes());
+        phys.push(1);
+
+        let info = info(&png(2, &[(b"pHYs", &phys), (b"IDAT", &[])])).unwrap();
+        assert_eq!(
+            (info.format, info.width, info.height),
+            ("png", 640.0, 480.0)
+        );
+        assert_eq!(info.alpha, Some(false));
+        let (x, y) = info.dpi.unwrap();
+        assert!((x - 96.012).abs() < 0.001 && (y - 96.012).abs() < 0.001);
+
+        assert_eq!(super::info(&png(6, &[])).unwrap().alpha, Some(true));
+        assert_eq!(
+            super::info(&png(3, &[(b"tRNS", &[0])])).unwrap().alpha,
+            Some(true)
+        );
+
+        // a chunk length which wraps `at` to the same chunk on 32 bit
+        let mut data = png(2, &[]);
+        data.extend((u32::MAX - 11).to_be_bytes());
+        data.extend(b"tEXt");
+        data.extend([0; 8]);
+        assert_eq!(super::info(&data).unwrap().alpha, Some(false));
+        let mut data = png(2, &[]);
+        data.extend(u32::MAX.to_be_bytes());
+        data.extend(b"tEXt");
+        assert_eq!(super::info(&data).unwrap().width, 640.0);
+    }
+
+    #[test]
+    fn test_jpeg() {
+        let mut data = vec![0xff, 0xd8];
+        // jfif with 300 dpi
+        data.extend([0xff, 0xe0, 0, 16]);
+        data.extend(b"JFIF\0\x01\x02\x01");
+        data.extend(300u16.to_be_bytes());
+        data.extend(300u16.to_be_bytes());
+        data.extend([0, 0]);
+        // baseline frame
+        data.extend([0xff, 0xc0, 0, 17, 8]);
+        data.extend(200u16.to_be_bytes());
+        data.extend(100u16.to_be_bytes());
+        data.extend([3; 12]);
+
+        let info = info(&data).unwrap();
+        assert_eq!(
+            (info.format, info.width, info.height, info.dpi),
+            ("jpeg", 100.0, 200.0, Some((300.0, 300.0)))
+        );
+    }
+
+    #[test]
+    fn test_gif_webp_bmp() {
+        // gif with a global color table of 2 colors and a transparent color
+        let mut gif = b"GIF89a\x0a\0\x14\0\x80\0\0".to_vec();
+        gif.extend([0; 6]);
+        gif.extend([0x21, 0xf9, 4, 0x01, 0, 0, 0, 0, 0x2c]);
+        let info = info(&gif).unwrap();
+        assert_eq!(
+            (info.width, info.height, info.alpha),
+            (10.0, 20.0, Some(true))
+        );
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\x10\0\0\0".to_vec();
+        webp.extend([0x3f, 0x01, 0, 0xc7, 0, 0]);
+        let info = super::info(&webp).unwrap();
+        assert_eq!(
+            (info.format, info.width, info.height, info.alpha),
+            ("webp", 320.0, 200.0, Some(true))
+        );
+
+        let mut lossless = b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f".to_vec();
+        lossless.extend((9u32 | (19 << 14)).to_le_bytes());
+        let info = super::info(&lossless).unwrap();
+        assert_eq!(
+            (info.width, info.height, info.alpha),
+            (10.0, 20.0, Some(false))
+        );
+
+        let mut bmp = b"BM".to_vec();
+        bmp.extend([0; 12]);
+        bmp.extend(40u32.to_le_bytes());
+        bmp.extend(16i32.to_le_bytes());
+        bmp.extend((-8i32).to_le_bytes());
+        bmp.extend(1u16.to_le_bytes());
+        bmp.extend(24u16.to_le_bytes());
+        bmp.extend([0; 8]);
+        bmp.extend(2835u32.to_le_bytes());
+        bmp.extend(2835u32.to_le_bytes());
+        bmp.extend([0; 8]);
+        let info = super::info(&bmp).unwrap();
+        assert_eq!(
+            (info.width, info.height, info.alpha),
+            (16.0, 8.0, Some(false))
+        );
+        assert_eq!(info.dpi.map(|(x, _)| x.round()), Some(72.0));
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = |s: &str| info(s.as_bytes()).unwrap();
+        assert_eq!(
+            svg(r#"<svg xmlns="http://www.w3.org/2000/svg" width="10mm" height='2in'>"#),
+            ImageInfo {
+                format: "svg",
+                width: 10.0 * 96.0 / 25.4,
+                height: 192.0,
+                dpi: None,
+                alpha: Some(true),
+                view_box: None,
+            }
+        );
+        let info = svg(r#"<?xml version="1.0"?><svg viewBox="0, 0 200 100" width="50"></svg>"#);
+        assert_eq!(
+            (info.width, info.height, info.view_box),
+            (50.0, 25.0, Some([0.0, 0.0, 200.0, 100.0]))
+        );
+        let info = svg(r#"<svg width="100%" height="100%" viewBox="0 0 4 3"/>"#);
+        assert_eq!((info.width, info.height), (4.0, 3.0));
+        let info = svg("<svg></svg>");
+        assert_eq!((info.width, info.height), (300.0, 150.0));
+    }
+
+    proptest! {
+        #[test]
+        fn test_truncated_headers(
+            header in prop_oneof![
+                Just(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec()),
+                Just(vec![0xff, 0xd8, 0xff]),
+                Just(b"GIF89a".to_vec()),
+                Just(b"RIFF\0\0\0\0WEBPVP8".to_vec()),
+                Just(b"BM".to_vec()),
+                Just(b"<svg ".to_vec()),
+            ],
+            rest in prop::collection::vec(any::<u8>(), 0..64),
+        ) {
+            // broken headers are never read out of bounds
+            let mut data = header;
+            data.extend(rest);
+            let _ = info(&data);
+        }
+    }
+}
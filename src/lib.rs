@@ -1,18 +1,33 @@
+use std::collections::HashMap;
+
 use base64::Engine as _;
 use minicbor::{Decoder, Encoder};
 use rquickjs::{context::EvalOptions, function::Args, CatchResultExt, Context, Module, Runtime};
 use wasm_minimal_protocol::*;
 
+use crate::cbor::rquickjs::{DecodeOptions, EncodeOptions, NumberPolicy};
 use crate::cbor_load::cbor_decode_run_load;
+use crate::define_vars::DefineMode;
 
 mod cbor;
 mod cbor_load;
+mod commonjs;
+mod data_url;
+mod define_vars;
+mod host;
+mod image;
+mod modules;
 mod strfmt;
+mod svg;
 
 initiate_protocol!();
 
 static mut CURRENT_CONTEXT: Option<Context> = None;
 static mut CURRENT_VALUE: Option<Vec<u8>> = None;
+static mut CURRENT_NUMBER_POLICY: NumberPolicy = NumberPolicy::Safe;
+static mut CURRENT_INTEGRAL_FLOATS_AS_INTS: bool = false;
+static mut CURRENT_SCHEMAS: Option<HashMap<String, cbor::schema::Value>> = None;
+static mut CURRENT_MODULES: Option<modules::SharedRegistry> = None;
 
 #[inline(always)]
 #[allow(static_mut_refs)]
@@ -46,9 +61,79 @@ fn set_stored_value(val: Vec<u8>) {
     }
 }
 
+#[inline(always)]
+#[allow(static_mut_refs)]
+fn get_module_registry() -> Result<modules::SharedRegistry, String> {
+    unsafe { CURRENT_MODULES.clone() }.ok_or_else(|| "module registry empty".to_string())
+}
+
+#[inline(always)]
+fn get_decode_options() -> DecodeOptions {
+    DecodeOptions {
+        numbers: unsafe { CURRENT_NUMBER_POLICY },
+        ..Default::default()
+    }
+}
+
+// the optional second byte of the store argument holds the decode flags
+#[inline(always)]
+fn get_call_decode_options(store: &[u8]) -> DecodeOptions {
+    get_decode_options().with_flags(store.get(1).copied().unwrap_or(0))
+}
+
+#[inline(always)]
+fn get_encode_options() -> EncodeOptions {
+    EncodeOptions {
+        integral_floats_as_ints: unsafe { CURRENT_INTEGRAL_FLOATS_AS_INTS },
+    }
+}
+
+#[inline(always)]
+fn set_number_policy(numbers: NumberPolicy, integral_floats_as_ints: bool) {
+    unsafe {
+        CURRENT_NUMBER_POLICY = numbers;
+        CURRENT_INTEGRAL_FLOATS_AS_INTS = integral_floats_as_ints;
+    }
+}
+
+#[inline(always)]
+#[allow(static_mut_refs)]
+fn set_schema(fn_name: &str, schema: Option<cbor::schema::Value>) {
+    unsafe {
+        let schemas = CURRENT_SCHEMAS.get_or_insert_with(HashMap::new);
+        match schema {
+            Some(schema) => schemas.insert(fn_name.to_string(), schema),
+            None => schemas.remove(fn_name),
+        };
+    }
+}
+
+// validates the arguments array against the schema registered for the function
+#[allow(static_mut_refs)]
+fn check_schema(fn_name: &str, arguments: &[u8]) -> Result<(), String> {
+    let Some(schema) = (unsafe { CURRENT_SCHEMAS.as_ref() }).and_then(|s| s.get(fn_name)) else {
+        return Ok(());
+    };
+    let arguments = cbor::schema::Value::decode(&mut Decoder::new(arguments))
+        .map_err(|e| format!("failed to deserialize arguments: {}", e))?;
+    let errors = cbor::schema::validate(schema, &arguments);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "invalid arguments for {}: {}",
+        fn_name,
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
 #[inline(always)]
 fn set_stored_value_from_rquickjs(store: bool, val: &rquickjs::Value) -> Result<Vec<u8>, String> {
-    let val = cbor::rquickjs::encode_to_bytes(val)
+    let val = cbor::rquickjs::encode_to_bytes(val, get_encode_options())
         .map_err(|e| format!("encode error: {}", e.to_string()))?;
     if store {
         set_stored_value(val.clone());
@@ -58,12 +143,44 @@ fn set_stored_value_from_rquickjs(store: bool, val: &rquickjs::Value) -> Result<
 
 #[wasm_func]
 fn new_context(load: &[u8]) -> Result<Vec<u8>, String> {
+    set_number_policy(NumberPolicy::default(), false);
+    unsafe {
+        CURRENT_SCHEMAS = None;
+    }
+
     let runtime =
         Runtime::new().map_err(|e| format!("failed to create runtime: {}", e.to_string()))?;
+    unsafe {
+        CURRENT_MODULES = Some(modules::install(&runtime));
+    }
 
     let ctx: Context = Context::full(&runtime)
         .map_err(|e| format!("failed to create context: {}", e.to_string()))?;
 
+    ctx.with(|ctx| {
+        cbor::rquickjs::typst::install(&ctx)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install typst values: {}", e.to_string()))?;
+        cbor::rquickjs::freeze::install(&ctx)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install freezing: {}", e.to_string()))?;
+        cbor::rquickjs::lazy::install(&ctx)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install lazy decoding: {}", e.to_string()))?;
+        cbor::rquickjs::eval_with::install(&ctx)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install eval with: {}", e.to_string()))?;
+        host::install(&ctx)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install ctxjs host module: {}", e.to_string()))?;
+        modules::install_context(&ctx, get_module_registry()?)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install bytes modules: {}", e.to_string()))?;
+        commonjs::install(&ctx, get_module_registry()?)
+            .catch(&ctx)
+            .map_err(|e| format!("failed to install commonjs: {}", e.to_string()))
+    })?;
+
     cbor_decode_run_load(&mut Decoder::new(load), &ctx)
         .map_err(|e| format!("failed to run load: {}", e.to_string()))?;
 
@@ -77,6 +194,58 @@ fn stored_value() -> Result<Vec<u8>, String> {
     Ok(get_stored_value())
 }
 
+#[allow(static_mut_refs)]
+fn get_stored_value_at(pointer: &[u8]) -> Result<Vec<u8>, String> {
+    let pointer =
+        std::str::from_utf8(pointer).map_err(|e| format!("failed to parse pointer: {}", e))?;
+    // borrowed, only the part at the pointer is copied
+    let value: &[u8] = unsafe {
+        match &CURRENT_VALUE {
+            Some(value) => value,
+            None => &[],
+        }
+    };
+    cbor::utils::lookup(value, &cbor::utils::parse_path(pointer))
+        .map_err(|e| format!("failed to read stored value: {}", e))?
+        .map(|value| value.to_vec())
+        .ok_or_else(|| format!("no stored value at \"{}\"", pointer))
+}
+
+#[wasm_func]
+fn stored_value_at(pointer: &[u8]) -> Result<Vec<u8>, String> {
+    get_stored_value_at(pointer)
+}
+
+#[wasm_func]
+fn stored_value_info(pointer: &[u8]) -> Result<Vec<u8>, String> {
+    let value = get_stored_value_at(pointer)?;
+    let info = cbor::utils::value_info(&value)
+        .map_err(|e| format!("failed to read stored value: {}", e))?;
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    encoder
+        .map(2)
+        .and_then(|e| e.str("type")?.str(info.0)?.str("length"))
+        .and_then(|e| match info.1 {
+            Some(len) => e.u64(len),
+            None => e.null(),
+        })
+        .map_err(|e| format!("failed to encode stored value info: {}", e))?;
+    Ok(buffer)
+}
+
+#[wasm_func]
+fn number_policy(policy: &[u8]) -> Result<Vec<u8>, String> {
+    let (numbers, integral_floats_as_ints) =
+        cbor_load::cbor_decode_number_policy(&mut Decoder::new(policy))
+            .map_err(|e| format!("failed to deserialize number policy: {}", e.to_string()))?;
+
+    set_number_policy(numbers, integral_floats_as_ints);
+
+    Ok(vec![])
+}
+
 #[wasm_func]
 fn load(run: &[u8]) -> Result<Vec<u8>, String> {
     let ctx = get_current_context()?;
@@ -109,12 +278,17 @@ fn eval(js: &[u8], store: &[u8]) -> Result<Vec<u8>, String> {
 }
 
 #[wasm_func]
-fn eval_format(js: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u8>, String> {
+fn eval_format(
+    js: &[u8],
+    arguments: &[u8],
+    strict: &[u8],
+    store: &[u8],
+) -> Result<Vec<u8>, String> {
     let ctx = get_current_context()?;
 
     let mut decoder = Decoder::new(arguments);
 
-    let arguments = cbor::rquickjs::args::string_map(&mut decoder)
+    let arguments = cbor::rquickjs::args::arguments(&mut decoder, get_decode_options())
         .map_err(|e| format!("failed to deserialize arguments: {}", e.to_string()))?;
 
     let store = !store.is_empty() && store[0] > 0;
@@ -125,7 +299,7 @@ fn eval_format(js: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u8>, Str
     ctx.with(|ctx| {
         let value = ctx
             .eval_with_options(
-                strfmt::strfmt(js, &arguments)
+                strfmt::strfmt(js, &arguments, !strict.is_empty() && strict[0] > 0)
                     .map_err(|e| format!("can not format js string: {}", e))?,
                 options,
             )
@@ -136,22 +310,121 @@ fn eval_format(js: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u8>, Str
 }
 
 #[wasm_func]
-fn define_vars(variables: &[u8]) -> Result<Vec<u8>, String> {
+fn format_placeholders(js: &[u8]) -> Result<Vec<u8>, String> {
+    let placeholders = strfmt::placeholders(js);
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    encoder
+        .array(placeholders.len() as u64)
+        .map_err(|e| format!("failed to encode placeholders: {}", e.to_string()))?;
+    for placeholder in placeholders {
+        encoder
+            .str(placeholder)
+            .map_err(|e| format!("failed to encode placeholders: {}", e.to_string()))?;
+    }
+    Ok(buffer)
+}
+
+#[wasm_func]
+fn validate_json(json: &[u8], max_depth: &[u8]) -> Result<Vec<u8>, String> {
+    let max_depth = if max_depth.is_empty() {
+        cbor::json::DEFAULT_MAX_DEPTH
+    } else {
+        Decoder::new(max_depth)
+            .u32()
+            .map_err(|e| format!("invalid max depth: {}", e))? as usize
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    match cbor::json::validate_with_max_depth(json, max_depth) {
+        Ok(()) => encoder.map(1).and_then(|e| e.str("valid")?.bool(true)),
+        Err(err) => encoder.map(6).and_then(|e| {
+            e.str("valid")?
+                .bool(false)?
+                .str("offset")?
+                .u64(err.offset as u64)?
+                .str("line")?
+                .u64(err.line as u64)?
+                .str("column")?
+                .u64(err.column as u64)?
+                .str("reason")?
+                .str(err.reason.name())?
+                .str("message")?
+                .str(&err.to_string())
+        }),
+    }
+    .map_err(|e| format!("failed to encode validation result: {}", e))?;
+    Ok(buffer)
+}
+
+#[wasm_func]
+fn validate_schema(value: &[u8], schema: &[u8]) -> Result<Vec<u8>, String> {
+    let errors = cbor::schema::validate_cbor(schema, value)?;
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    encoder
+        .array(errors.len() as u64)
+        .and_then(|mut e| {
+            for err in &errors {
+                e = e
+                    .map(3)?
+                    .str("path")?
+                    .str(&err.path)?
+                    .str("schema-path")?
+                    .str(&err.schema_path)?
+                    .str("message")?
+                    .str(&err.message)?;
+            }
+            Ok(e)
+        })
+        .map_err(|e| format!("failed to encode validation errors: {}", e))?;
+    Ok(buffer)
+}
+
+#[wasm_func]
+fn eval_with(js: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u8>, String> {
+    let ctx = get_current_context()?;
+
+    let options = get_call_decode_options(store);
+    let store = !store.is_empty() && store[0] > 0;
+
+    ctx.with(|ctx| {
+        let value =
+            cbor::rquickjs::eval_with::eval_with(&ctx, js, &mut Decoder::new(arguments), options)
+                .map_err(|e| format!("failed to eval with arguments: {}", e.to_string()))?;
+        set_stored_value_from_rquickjs(store, &value)
+    })
+}
+
+#[wasm_func]
+fn define_vars(variables: &[u8], mode: &[u8], flags: &[u8]) -> Result<Vec<u8>, String> {
     let ctx = get_current_context()?;
 
     let mut decoder = Decoder::new(variables);
 
-    let variables = cbor::rquickjs::args::string_map(&mut decoder)
+    let mode = match std::str::from_utf8(mode)
+        .map_err(|e| format!("failed to parse mode: {}", e.to_string()))?
+    {
+        "" => DefineMode::default(),
+        mode => DefineMode::parse(mode).ok_or_else(|| format!("unsupported mode {}", mode))?,
+    };
+
+    let options = get_decode_options().with_flags(flags.first().copied().unwrap_or(0));
+    let variables = cbor::rquickjs::args::string_map(&mut decoder, options)
         .map_err(|e| format!("failed to deserialize variables: {}", e.to_string()))?;
 
-    let variables: String = variables
-        .into_iter()
-        .map(|(k, v)| format!("let {}={}", k, v))
-        .fold(String::new(), |a, b| a + &b + ";");
+    let variables = define_vars::source(variables, mode)?;
+
+    let mut options = EvalOptions::default();
+    options.global = true;
+    options.strict = false;
 
     ctx.with(|ctx| {
         _ = ctx
-            .eval::<rquickjs::Value, std::string::String>(format!("{};", variables))
+            .eval_with_options::<rquickjs::Value, std::string::String>(variables, options)
             .catch(&ctx)
             .map_err(|e| format!("eval error: {}", e.to_string()))?;
 
@@ -159,6 +432,20 @@ fn define_vars(variables: &[u8]) -> Result<Vec<u8>, String> {
     })
 }
 
+#[wasm_func]
+fn define_globals(variables: &[u8], flags: &[u8]) -> Result<Vec<u8>, String> {
+    let ctx = get_current_context()?;
+
+    let options = get_decode_options().with_flags(flags.first().copied().unwrap_or(0));
+
+    ctx.with(|ctx| {
+        define_vars::define_globals(&ctx, &mut Decoder::new(variables), options)
+            .map_err(|e| format!("failed to define globals: {}", e.to_string()))?;
+
+        Ok(vec![])
+    })
+}
+
 #[wasm_func]
 fn call_function(fn_name: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u8>, String> {
     let ctx = get_current_context()?;
@@ -166,11 +453,14 @@ fn call_function(fn_name: &[u8], arguments: &[u8], store: &[u8]) -> Result<Vec<u
     let fn_name: &str = std::str::from_utf8(fn_name)
         .map_err(|e| format!("failed to parse fn_name: {}", e.to_string()))?;
 
+    check_schema(fn_name, arguments)?;
+
+    let options = get_call_decode_options(store);
     let store = store.len() > 0 && store[0] > 0;
 
     ctx.with(|ctx| {
         let arguments: Vec<rquickjs::Value> =
-            cbor::rquickjs::args::array(&ctx, &mut Decoder::new(arguments))
+            cbor::rquickjs::args::array(&ctx, &mut Decoder::new(arguments), options)
                 .map_err(|e| format!("failed to deserialize arguments: {}", e.to_string()))?;
 
         let mut args = Args::new(ctx.clone(), arguments.len());
@@ -227,6 +517,10 @@ fn load_module_bytecode(bytecode: &[u8]) -> Result<Vec<u8>, String> {
         let m = unsafe { Module::load(ctx.clone(), bytecode) }
             .catch(&ctx)
             .map_err(|e| format!("failed load bytecode: {}", e.to_string()))?;
+        let name: String = m
+            .name()
+            .map_err(|e| format!("failed to get module name: {}", e))?;
+        get_module_registry()?.borrow_mut().add_declared(&name);
         _ = m
             .eval()
             .catch(&ctx)
@@ -246,13 +540,116 @@ fn load_module_js(module_name: &[u8], module: &[u8]) -> Result<Vec<u8>, String>
     let module: &str = std::str::from_utf8(module)
         .map_err(|e| format!("failed to parse module: {}", e.to_string()))?;
 
+    let module_name = get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::Declared);
+
     ctx.with(|ctx| {
-        _ = Module::declare(ctx.clone(), module_name, module)
-            .catch(&ctx)
-            .map_err(|e| format!("failed load module code: {}", e.to_string()))?
-            .eval()
+        _ = Module::declare(
+            ctx.clone(),
+            module_name,
+            modules::strip_import_attributes(module.as_bytes()),
+        )
+        .catch(&ctx)
+        .map_err(|e| format!("failed load module code: {}", e.to_string()))?
+        .eval()
+        .catch(&ctx)
+        .map_err(|e| format!("failed eval module code: {}", e.to_string()))?;
+        Ok(vec![])
+    })
+}
+
+#[wasm_func]
+fn register_module(module_name: &[u8], module: &[u8]) -> Result<Vec<u8>, String> {
+    let module_name: &str = std::str::from_utf8(module_name)
+        .map_err(|e| format!("failed to parse module_name: {}", e))?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::Js(module.to_vec()));
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn load_commonjs(module_name: &[u8], module: &[u8]) -> Result<Vec<u8>, String> {
+    let module_name: &str = std::str::from_utf8(module_name)
+        .map_err(|e| format!("failed to parse module_name: {}", e))?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::CommonJs(module.to_vec()));
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn register_json_module(module_name: &[u8], json: &[u8]) -> Result<Vec<u8>, String> {
+    let module_name: &str = std::str::from_utf8(module_name)
+        .map_err(|e| format!("failed to parse module_name: {}", e))?;
+    let json = cbor::json::validated_str(json).map_err(|e| e.to_string())?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::Json(json.to_string()));
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn register_text_module(module_name: &[u8], text: &[u8]) -> Result<Vec<u8>, String> {
+    let module_name: &str = std::str::from_utf8(module_name)
+        .map_err(|e| format!("failed to parse module_name: {}", e))?;
+    let text: &str =
+        std::str::from_utf8(text).map_err(|e| format!("failed to parse text: {}", e))?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::Text(text.to_string()));
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn register_bytes_module(module_name: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let module_name: &str = std::str::from_utf8(module_name)
+        .map_err(|e| format!("failed to parse module_name: {}", e))?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .register(module_name, modules::Source::Bytes(data.to_vec()));
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn module_base(base: &[u8]) -> Result<Vec<u8>, String> {
+    let base: &str =
+        std::str::from_utf8(base).map_err(|e| format!("failed to parse base: {}", e))?;
+
+    get_module_registry()?.borrow_mut().set_base(base);
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn import_map(map: &[u8]) -> Result<Vec<u8>, String> {
+    let (imports, scopes) = cbor_load::cbor_decode_import_map(&mut Decoder::new(map))
+        .map_err(|e| format!("failed to deserialize import map: {}", e))?;
+
+    get_module_registry()?
+        .borrow_mut()
+        .add_import_map(imports, scopes);
+    Ok(vec![])
+}
+
+#[wasm_func]
+fn load_asset(name: &[u8], data: &[u8], mime: &[u8]) -> Result<Vec<u8>, String> {
+    let ctx = get_current_context()?;
+
+    let name: &str =
+        std::str::from_utf8(name).map_err(|e| format!("failed to parse name: {}", e))?;
+    let mime: &str =
+        std::str::from_utf8(mime).map_err(|e| format!("failed to parse mime: {}", e))?;
+
+    ctx.with(|ctx| {
+        host::add_asset(&ctx, name, data, mime)
             .catch(&ctx)
-            .map_err(|e| format!("failed eval module code: {}", e.to_string()))?;
+            .map_err(|e| format!("failed to add asset: {}", e))?;
         Ok(vec![])
     })
 }
@@ -273,11 +670,12 @@ fn call_module_function(
     let fn_name: &str = std::str::from_utf8(fn_name)
         .map_err(|e| format!("failed to parse fn_name: {}", e.to_string()))?;
 
+    let options = get_call_decode_options(store);
     let store = store.len() > 0 && store[0] > 0;
 
     ctx.with(|ctx| {
         let arguments: Vec<rquickjs::Value> =
-            cbor::rquickjs::args::array(&ctx, &mut Decoder::new(arguments))
+            cbor::rquickjs::args::array(&ctx, &mut Decoder::new(arguments), options)
                 .map_err(|e| format!("failed to deserialize arguments: {}", e.to_string()))?;
 
         let mut args = Args::new(ctx.clone(), arguments.len());
@@ -361,7 +759,7 @@ fn get_module_property(module_name: &[u8], property_name: &[u8]) -> Result<Vec<u
             .get(property_name)
             .map_err(|e| format!("failed to get module property: {}", e.to_string()))?;
 
-        cbor::rquickjs::encode_to_bytes(&res)
+        cbor::rquickjs::encode_to_bytes(&res, get_encode_options())
             .map_err(|e| format!("encode error: {}", e.to_string()))
     })
 }
@@ -384,38 +782,190 @@ pub fn is_svg(buf: &[u8]) -> bool {
     false
 }
 
+type Matcher = fn(&[u8]) -> bool;
+
+// name, mime type and detection, in the order of detection
+const IMAGE_FORMATS: [(&str, &str, Matcher); 9] = [
+    ("png", "image/png", infer::image::is_png),
+    ("jpeg", "image/jpeg", infer::image::is_jpeg),
+    ("gif", "image/gif", infer::image::is_gif),
+    ("webp", "image/webp", infer::image::is_webp),
+    ("avif", "image/avif", infer::image::is_avif),
+    ("bmp", "image/bmp", infer::image::is_bmp),
+    ("ico", "image/x-icon", infer::image::is_ico),
+    ("tiff", "image/tiff", infer::image::is_tiff),
+    ("svg", "image/svg+xml", is_svg),
+];
+
+// the name of the detected image format
+fn image_format(data: &[u8]) -> Option<&'static str> {
+    IMAGE_FORMATS
+        .iter()
+        .find(|(_, _, matches)| matches(data))
+        .map(|(name, _, _)| *name)
+}
+
+// the mime type of the image, detected if the format is empty
+fn image_mime_type(data: &[u8], format: &str) -> Result<&'static str, String> {
+    if format.is_empty() {
+        return IMAGE_FORMATS
+            .iter()
+            .find(|(_, _, matches)| matches(data))
+            .map(|(_, mime, _)| *mime)
+            .ok_or_else(|| "data not supported".to_owned());
+    }
+
+    let name = match format.to_lowercase().as_str() {
+        "jpg" => "jpeg".to_string(),
+        "tif" => "tiff".to_string(),
+        name => name.to_string(),
+    };
+    IMAGE_FORMATS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, mime, _)| *mime)
+        .ok_or_else(|| format!("format {} not supported", format))
+}
+
+fn encode_image_data_url(data: &[u8], format: &str) -> Result<String, String> {
+    Ok(format!(
+        "data:{};base64,{}",
+        image_mime_type(data, format)?,
+        base64::prelude::BASE64_STANDARD.encode(data)
+    ))
+}
+
 #[wasm_func]
 pub fn image_data_url(data: &[u8], format: &[u8]) -> Result<Vec<u8>, String> {
     let format: &str = std::str::from_utf8(format)
         .map_err(|e| format!("failed to parse format: {}", e.to_string()))?;
 
-    let t: &str;
-    if format.is_empty() {
-        if infer::image::is_png(data) {
-            t = "png"
-        } else if infer::image::is_jpeg(data) {
-            t = "jpeg"
-        } else if infer::image::is_gif(data) {
-            t = "gif"
-        } else if is_svg(data) {
-            t = "svg"
-        } else {
-            return Err("data not supported".to_owned());
-        }
+    Ok(encode_image_data_url(data, format)?.into_bytes())
+}
+
+#[wasm_func]
+pub fn svg_data_url(svg: &[u8], sanitize: &[u8]) -> Result<Vec<u8>, String> {
+    let svg: &str = std::str::from_utf8(svg).map_err(|e| format!("failed to parse svg: {}", e))?;
+    let sanitize = !sanitize.is_empty() && sanitize[0] > 0;
+
+    Ok(if sanitize {
+        svg::data_url(&svg::sanitize(svg)?)
     } else {
-        match format.to_lowercase().as_str() {
-            "png" => t = "png",
-            "jpeg" => t = "jpeg",
-            "gif" => t = "gif",
-            "svg" => t = "svg",
-            _ => return Err(format!("format {} not supported", format)),
-        }
+        svg::data_url(svg)
     }
-
-    Ok(format!(
-        "data:image/{};base64,{}",
-        t,
-        base64::prelude::BASE64_STANDARD.encode(&data)
-    )
     .into_bytes())
 }
+
+#[wasm_func]
+pub fn sanitize_svg(svg: &[u8]) -> Result<Vec<u8>, String> {
+    let svg: &str = std::str::from_utf8(svg).map_err(|e| format!("failed to parse svg: {}", e))?;
+
+    Ok(svg::sanitize(svg)?.into_bytes())
+}
+
+#[wasm_func]
+pub fn image_info(data: &[u8]) -> Result<Vec<u8>, String> {
+    let info = image::info(data).ok_or_else(|| "data not supported".to_owned())?;
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    encoder
+        .map(6)
+        .and_then(|e| {
+            e.str("format")?
+                .str(info.format)?
+                .str("width")?
+                .f64(info.width)?
+                .str("height")?
+                .f64(info.height)?
+                .str("dpi")
+        })
+        .and_then(|e| match info.dpi {
+            Some((x, y)) => e.array(2)?.f64(x)?.f64(y),
+            None => e.null(),
+        })
+        .and_then(|e| e.str("alpha"))
+        .and_then(|e| match info.alpha {
+            Some(alpha) => e.bool(alpha),
+            None => e.null(),
+        })
+        .and_then(|e| e.str("view-box"))
+        .and_then(|e| match info.view_box {
+            Some(view_box) => e
+                .array(4)?
+                .f64(view_box[0])?
+                .f64(view_box[1])?
+                .f64(view_box[2])?
+                .f64(view_box[3]),
+            None => e.null(),
+        })
+        .map_err(|e| format!("failed to encode image info: {}", e))?;
+    Ok(buffer)
+}
+
+#[wasm_func]
+pub fn data_url(data: &[u8], mime: &[u8], charset: &[u8]) -> Result<Vec<u8>, String> {
+    let mime: &str =
+        std::str::from_utf8(mime).map_err(|e| format!("failed to parse mime: {}", e))?;
+    let charset: &str =
+        std::str::from_utf8(charset).map_err(|e| format!("failed to parse charset: {}", e))?;
+
+    Ok(data_url::encode(data, mime, charset).into_bytes())
+}
+
+#[wasm_func]
+pub fn parse_data_url(url: &[u8]) -> Result<Vec<u8>, String> {
+    let url: &str = std::str::from_utf8(url).map_err(|e| format!("failed to parse url: {}", e))?;
+    let url = data_url::parse(url)?;
+
+    let mut buffer = Vec::new();
+    let mut encoder = minicbor::Encoder::new(&mut buffer);
+    encoder
+        .map(4)
+        .and_then(|e| {
+            e.str("mime")?
+                .str(&url.mime)?
+                .str("params")?
+                .map(url.params.len() as u64)
+        })
+        .and_then(|mut e| {
+            for (name, value) in &url.params {
+                e = e.str(name)?.str(value)?;
+            }
+            e.str("base64")?
+                .bool(url.base64)?
+                .str("data")?
+                .bytes(&url.data)
+        })
+        .map_err(|e| format!("failed to encode data url: {}", e))?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::image_mime_type;
+
+    #[test]
+    fn test_image_mime_type() {
+        let images: [(&[u8], &str); 9] = [
+            (b"\x89PNG\r\n\x1a\n", "image/png"),
+            (b"\xff\xd8\xff\xe0", "image/jpeg"),
+            (b"GIF89a", "image/gif"),
+            (b"RIFF\0\0\0\0WEBPVP8 ", "image/webp"),
+            (b"\0\0\0\x18ftypavif\0\0\0\0avifmif1", "image/avif"),
+            (b"BM\0\0", "image/bmp"),
+            (b"\0\0\x01\0\x01\0", "image/x-icon"),
+            (b"II*\0\x08\0\0\0\0\0", "image/tiff"),
+            (b"<?xml version=\"1.0\"?><svg></svg>", "image/svg+xml"),
+        ];
+        for (data, mime) in images {
+            assert_eq!(image_mime_type(data, ""), Ok(mime));
+        }
+
+        assert_eq!(image_mime_type(b"", "SVG"), Ok("image/svg+xml"));
+        assert_eq!(image_mime_type(b"", "jpg"), Ok("image/jpeg"));
+        assert_eq!(image_mime_type(b"", "tif"), Ok("image/tiff"));
+        assert!(image_mime_type(b"", "psd").is_err());
+        assert!(image_mime_type(b"text", "").is_err());
+    }
+}
@@ -0,0 +1,908 @@
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, rc::Rc};
+
+use rquickjs::{
+    loader::{Loader, Resolver},
+    module::Declared,
+    Ctx, Error, Exception, Function, Module, Runtime, TypedArray,
+};
+
+use crate::cbor::jsstring;
+
+// hidden global which creates the Uint8Array of a bytes module
+const MODULE_BYTES: &str = "__ctxjs_module_bytes";
+
+// the file names tried for a specifier without extension
+const SUFFIXES: [&str; 5] = ["", ".js", ".mjs", "/index.js", "/index.mjs"];
+
+pub(crate) enum Source {
+    // declared in the context (load_module_js, load_module_bytecode or loaded)
+    Declared,
+    // declared on the first import
+    Js(Vec<u8>),
+    // evaluated on the first require or import, never declared itself
+    CommonJs(Vec<u8>),
+    // synthetic modules with a default export, kept for require
+    Json(String),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Source {
+    // the js expression of a synthetic module's value
+    fn value(&self, name: &str) -> Option<String> {
+        match self {
+            // json is not a subset of js literals (`__proto__` keys)
+            Source::Json(json) => Some(format!("JSON.parse({})", jsstring::string(json))),
+            Source::Text(text) => Some(jsstring::string(text)),
+            // created natively, a literal would be much larger than the data
+            Source::Bytes(_) => Some(format!(
+                "globalThis.{}({})",
+                MODULE_BYTES,
+                jsstring::string(name)
+            )),
+            _ => None,
+        }
+    }
+}
+
+// specifier (or prefix ending with `/`) -> module path, none blocks the
+// specifier, sorted in descending order so longer prefixes match first
+pub(crate) type SpecifierMap = Vec<(String, Option<String>)>;
+
+// https://html.spec.whatwg.org/multipage/webappapis.html#import-maps
+#[derive(Default)]
+struct ImportMap {
+    imports: SpecifierMap,
+    // scope prefix -> specifier map, sorted like the specifier maps
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+// the modules of a context by normalized name, relative specifiers are
+// resolved against the importing module, bare names against the import map,
+// the registry and the base path
+#[derive(Default)]
+pub(crate) struct Registry {
+    base: String,
+    modules: HashMap<String, Source>,
+    import_map: ImportMap,
+}
+
+pub(crate) type SharedRegistry = Rc<RefCell<Registry>>;
+
+fn is_relative(specifier: &str) -> bool {
+    specifier.starts_with("./") || specifier.starts_with("../") || specifier.starts_with('/')
+}
+
+fn dirname(name: &str) -> &str {
+    name.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+// joins the path to the directory and removes `.`, `..` and empty segments,
+// `..` never leaves the root
+pub(crate) fn normalize(dir: &str, path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let dir = if path.starts_with('/') { "" } else { dir };
+    for segment in dir.split('/').chain(path.split('/')) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+fn merge<T>(entries: &mut Vec<(String, T)>, new: Vec<(String, T)>) {
+    for (key, value) in new {
+        entries.retain(|(k, _)| *k != key);
+        entries.push((key, value));
+    }
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+}
+
+fn map_specifier(map: &SpecifierMap, specifier: &str) -> Option<Result<String, String>> {
+    map.iter().find_map(|(key, address)| {
+        let rest = if key == specifier {
+            ""
+        } else if key.ends_with('/') && specifier.starts_with(key.as_str()) {
+            &specifier[key.len()..]
+        } else {
+            return None;
+        };
+        Some(match address {
+            None => Err(format!("{} is blocked by the import map", specifier)),
+            Some(address) if rest.is_empty() => Ok(address.clone()),
+            Some(address) if address.ends_with('/') => Ok(format!("{}{}", address, rest)),
+            Some(address) => Err(format!(
+                "the import map address {} for {} has to end with /",
+                address, key
+            )),
+        })
+    })
+}
+
+impl Registry {
+    pub(crate) fn set_base(&mut self, base: &str) {
+        self.base = normalize("", base);
+    }
+
+    // returns the normalized name under which the module has to be declared
+    pub(crate) fn register(&mut self, name: &str, source: Source) -> String {
+        let name = normalize("", name);
+        self.modules.insert(name.clone(), source);
+        name
+    }
+
+    // bytecode keeps the name it was compiled with
+    pub(crate) fn add_declared(&mut self, name: &str) {
+        self.modules.insert(name.to_string(), Source::Declared);
+    }
+
+    // keys and addresses are paths like module names, relative ones are
+    // relative to the base path, a trailing `/` marks a prefix
+    fn import_map_path(&self, path: &str) -> String {
+        let dir = if is_relative(path) {
+            self.base.as_str()
+        } else {
+            ""
+        };
+        let mut normalized = normalize(dir, path);
+        if path.ends_with('/') {
+            normalized.push('/');
+        }
+        normalized
+    }
+
+    // entries of later import maps replace the ones with the same key
+    pub(crate) fn add_import_map(
+        &mut self,
+        imports: SpecifierMap,
+        scopes: Vec<(String, SpecifierMap)>,
+    ) {
+        let normalize_map = |map: SpecifierMap| -> SpecifierMap {
+            map.into_iter()
+                .map(|(key, address)| {
+                    let key = if is_relative(&key) {
+                        self.import_map_path(&key)
+                    } else {
+                        key
+                    };
+                    (key, address.map(|address| self.import_map_path(&address)))
+                })
+                .collect()
+        };
+
+        let imports = normalize_map(imports);
+        let scopes: Vec<(String, SpecifierMap)> = scopes
+            .into_iter()
+            .map(|(scope, map)| (self.import_map_path(&scope), normalize_map(map)))
+            .collect();
+
+        merge(&mut self.import_map.imports, imports);
+        for (scope, map) in scopes {
+            match self.import_map.scopes.iter_mut().find(|(s, _)| *s == scope) {
+                Some((_, entries)) => merge(entries, map),
+                None => {
+                    let mut entries = Vec::new();
+                    merge(&mut entries, map);
+                    self.import_map.scopes.push((scope, entries));
+                }
+            }
+        }
+        self.import_map.scopes.sort_by(|(a, _), (b, _)| b.cmp(a));
+    }
+
+    pub(crate) fn commonjs_source(&self, name: &str) -> Result<Vec<u8>, String> {
+        match self.modules.get(name) {
+            Some(Source::CommonJs(source)) => Ok(source.clone()),
+            Some(source) => match source.value(name) {
+                Some(value) => Ok(format!("module.exports = {};", value).into_bytes()),
+                None => Err(format!(
+                    "module {} is an es module, it can only be imported",
+                    name
+                )),
+            },
+            None => Err(format!("module {} not found", name)),
+        }
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+    }
+
+    fn find(&self, path: &str) -> Option<String> {
+        SUFFIXES
+            .iter()
+            .map(|suffix| format!("{}{}", path, suffix))
+            .find(|name| self.contains(name))
+    }
+
+    // unknown specifiers stay as they are (normalized), they can still be
+    // declared modules like `ctxjs` or fail in the loader
+    pub(crate) fn resolve(&self, referrer: &str, specifier: &str) -> Result<String, String> {
+        let relative = is_relative(specifier);
+        let specifier = if relative {
+            let dir = if self.contains(referrer) {
+                dirname(referrer)
+            } else {
+                &self.base
+            };
+            normalize(dir, specifier)
+        } else {
+            specifier.to_string()
+        };
+
+        // the most specific scope of the referrer first, then the imports
+        let address = self
+            .import_map
+            .scopes
+            .iter()
+            .filter(|(scope, _)| {
+                referrer == scope || (scope.ends_with('/') && referrer.starts_with(scope.as_str()))
+            })
+            .map(|(_, map)| map)
+            .chain(std::iter::once(&self.import_map.imports))
+            .find_map(|map| map_specifier(map, &specifier))
+            .transpose()?;
+        if let Some(path) = address {
+            return Ok(self.find(&path).unwrap_or(path));
+        }
+
+        if relative {
+            return Ok(self.find(&specifier).unwrap_or(specifier));
+        }
+        Ok(self
+            .find(&specifier)
+            .or_else(|| self.find(&normalize(&self.base, &specifier)))
+            .unwrap_or(specifier))
+    }
+}
+
+// quickjs doesn't parse import attributes (`with { type: "json" }`), they are
+// removed since the kind of a module is given by how it was registered
+pub(crate) fn strip_import_attributes(code: &[u8]) -> Cow<'_, [u8]> {
+    let contains = |word: &[u8]| code.windows(word.len()).any(|w| w == word);
+    if !contains(b"with") && !contains(b"assert") {
+        return Cow::Borrowed(code);
+    }
+    let attributes = Scanner { code, at: 0 }.attributes();
+    if attributes.is_empty() {
+        return Cow::Borrowed(code);
+    }
+    let mut stripped = Vec::with_capacity(code.len());
+    let mut at = 0;
+    for (start, end) in attributes {
+        stripped.extend_from_slice(&code[at..start]);
+        at = end;
+    }
+    stripped.extend_from_slice(&code[at..]);
+    Cow::Owned(stripped)
+}
+
+// keywords after which a `/` starts a regular expression
+const REGEX_KEYWORDS: [&[u8]; 14] = [
+    b"await",
+    b"case",
+    b"delete",
+    b"do",
+    b"else",
+    b"in",
+    b"instanceof",
+    b"new",
+    b"of",
+    b"return",
+    b"throw",
+    b"typeof",
+    b"void",
+    b"yield",
+];
+
+fn is_identifier_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+// just enough of a js tokenizer to find import attributes outside of strings,
+// templates, comments and regular expressions
+struct Scanner<'a> {
+    code: &'a [u8],
+    at: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.code.get(self.at).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.code.get(self.at + offset).copied()
+    }
+
+    // returns the ranges of the attributes in order
+    fn attributes(mut self) -> Vec<(usize, usize)> {
+        let mut attributes = Vec::new();
+        // open braces in each template substitution `${`
+        let mut templates: Vec<usize> = Vec::new();
+        let mut regex = true;
+        let mut member = false;
+        loop {
+            self.skip_trivia();
+            let Some(c) = self.peek() else {
+                break;
+            };
+            let start = self.at;
+            let dot = c == b'.';
+            match c {
+                b'"' | b'\'' => {
+                    self.skip_string();
+                    regex = false;
+                }
+                b'`' => {
+                    self.at += 1;
+                    if self.skip_template() {
+                        templates.push(0);
+                    }
+                    regex = false;
+                }
+                b'/' if regex => {
+                    self.skip_regex();
+                    regex = false;
+                }
+                b'}' if templates.last() == Some(&0) => {
+                    templates.pop();
+                    self.at += 1;
+                    if self.skip_template() {
+                        templates.push(0);
+                    }
+                    regex = false;
+                }
+                c if is_identifier_byte(c) => {
+                    while self.peek().is_some_and(is_identifier_byte) {
+                        self.at += 1;
+                    }
+                    let word = &self.code[start..self.at];
+                    regex = REGEX_KEYWORDS.contains(&word);
+                    if !member && (word == b"from" || word == b"import") {
+                        attributes.extend(self.attribute(word == b"import"));
+                    }
+                }
+                _ => {
+                    if let Some(depth) = templates.last_mut() {
+                        match c {
+                            b'{' => *depth += 1,
+                            b'}' => *depth -= 1,
+                            _ => {}
+                        }
+                    }
+                    self.at += 1;
+                    regex = !matches!(c, b')' | b']');
+                }
+            }
+            member = dot;
+        }
+        attributes
+    }
+
+    // the attributes after `from "x"`, `import "x"` or `import("x"`, the
+    // scanner stays after the keyword if there are none
+    fn attribute(&mut self, import: bool) -> Option<(usize, usize)> {
+        let keyword = self.at;
+        let attribute = self.attribute_at(import);
+        if attribute.is_none() {
+            self.at = keyword;
+        }
+        attribute
+    }
+
+    fn attribute_at(&mut self, import: bool) -> Option<(usize, usize)> {
+        self.skip_trivia();
+        let call = import && self.peek() == Some(b'(');
+        if call {
+            self.at += 1;
+            self.skip_trivia();
+        }
+        if !matches!(self.peek(), Some(b'"' | b'\'')) {
+            return None;
+        }
+        self.skip_string();
+        let start = self.at;
+        let newline = self.skip_trivia();
+        if call {
+            // import("x", { with: {...} })
+            if self.peek() != Some(b',') {
+                return None;
+            }
+            self.at += 1;
+            self.skip_trivia();
+            if !self.skip_object() {
+                return None;
+            }
+            self.skip_trivia();
+            if self.peek() == Some(b',') {
+                self.at += 1;
+                self.skip_trivia();
+            }
+            return (self.peek() == Some(b')')).then_some((start, self.at));
+        }
+        // `assert` may not follow on a new line
+        let keyword = &self.code[self.at..];
+        let length = if keyword.starts_with(b"with") {
+            4
+        } else if keyword.starts_with(b"assert") && !newline {
+            6
+        } else {
+            return None;
+        };
+        if self.peek_at(length).is_some_and(is_identifier_byte) {
+            return None;
+        }
+        self.at += length;
+        self.skip_trivia();
+        self.skip_object().then_some((start, self.at))
+    }
+
+    // skips whitespace and comments, returns whether there was a line break
+    fn skip_trivia(&mut self) -> bool {
+        let mut newline = false;
+        while let Some(c) = self.peek() {
+            match c {
+                b'\n' | b'\r' => {
+                    newline = true;
+                    self.at += 1;
+                }
+                b'/' if self.peek_at(1) == Some(b'/') => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.at += 1;
+                    }
+                }
+                b'/' if self.peek_at(1) == Some(b'*') => {
+                    let comment = self.at;
+                    self.at += 2;
+                    while self.peek().is_some()
+                        && !(self.peek() == Some(b'*') && self.peek_at(1) == Some(b'/'))
+                    {
+                        self.at += 1;
+                    }
+                    self.at = (self.at + 2).min(self.code.len());
+                    newline |= self.code[comment..self.at].contains(&b'\n');
+                }
+                c if c.is_ascii_whitespace() => self.at += 1,
+                _ => break,
+            }
+        }
+        newline
+    }
+
+    // a string ends at its quote or the end of the line
+    fn skip_string(&mut self) {
+        let quote = self.code[self.at];
+        self.at += 1;
+        while let Some(c) = self.peek() {
+            self.at += 1;
+            match c {
+                b'\\' => self.at += 1,
+                b'\n' => break,
+                c if c == quote => break,
+                _ => {}
+            }
+        }
+        self.at = self.at.min(self.code.len());
+    }
+
+    // returns whether the template continues with a substitution
+    fn skip_template(&mut self) -> bool {
+        while let Some(c) = self.peek() {
+            self.at += 1;
+            match c {
+                b'\\' => self.at += 1,
+                b'`' => return false,
+                b'$' if self.peek() == Some(b'{') => {
+                    self.at += 1;
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        self.at = self.at.min(self.code.len());
+        false
+    }
+
+    fn skip_regex(&mut self) {
+        self.at += 1;
+        let mut class = false;
+        while let Some(c) = self.peek() {
+            self.at += 1;
+            match c {
+                b'\\' => self.at += 1,
+                b'[' => class = true,
+                b']' => class = false,
+                b'/' if !class => break,
+                b'\n' => break,
+                _ => {}
+            }
+        }
+        self.at = self.at.min(self.code.len());
+        while self.peek().is_some_and(is_identifier_byte) {
+            self.at += 1;
+        }
+    }
+
+    // skips an object literal of the attributes
+    fn skip_object(&mut self) -> bool {
+        if self.peek() != Some(b'{') {
+            return false;
+        }
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return false,
+                Some(b'"' | b'\'') => self.skip_string(),
+                Some(c) => {
+                    self.at += 1;
+                    match c {
+                        b'{' => depth += 1,
+                        b'}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return true;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            self.skip_trivia();
+        }
+    }
+}
+
+struct RegistryResolver(SharedRegistry);
+
+impl Resolver for RegistryResolver {
+    fn resolve<'js>(
+        &mut self,
+        _ctx: &Ctx<'js>,
+        base: &str,
+        name: &str,
+    ) -> rquickjs::Result<String> {
+        self.0
+            .borrow()
+            .resolve(base, name)
+            .map_err(|message| Error::new_resolving_message(base, name, message))
+    }
+}
+
+struct RegistryLoader(SharedRegistry);
+
+impl Loader for RegistryLoader {
+    fn load<'js>(&mut self, ctx: &Ctx<'js>, name: &str) -> rquickjs::Result<Module<'js, Declared>> {
+        let code = match self.0.borrow_mut().modules.get_mut(name) {
+            None => return Err(Error::new_loading_message(name, "module not found")),
+            Some(Source::CommonJs(_)) => None,
+            // only if the declaration failed before
+            Some(Source::Declared) => {
+                return Err(Error::new_loading_message(name, "module failed to load"))
+            }
+            Some(source @ Source::Js(_)) => match std::mem::replace(source, Source::Declared) {
+                Source::Js(code) => Some(strip_import_attributes(&code).into_owned()),
+                _ => unreachable!(),
+            },
+            Some(source) => source
+                .value(name)
+                .map(|value| format!("export default {};\n", value).into_bytes()),
+        };
+        match code {
+            Some(code) => Module::declare(ctx.clone(), name, code),
+            // commonjs runs with the registry released since require needs it
+            None => crate::commonjs::declare(ctx, name),
+        }
+    }
+}
+
+pub(crate) fn install(runtime: &Runtime) -> SharedRegistry {
+    let registry = SharedRegistry::default();
+    runtime.set_loader(
+        RegistryResolver(registry.clone()),
+        RegistryLoader(registry.clone()),
+    );
+    registry
+}
+
+// installs the function which creates the values of bytes modules
+pub(crate) fn install_context<'js>(
+    ctx: &Ctx<'js>,
+    registry: SharedRegistry,
+) -> rquickjs::Result<()> {
+    let bytes = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, name: String| -> rquickjs::Result<TypedArray<'js, u8>> {
+            match registry.borrow().modules.get(&name) {
+                Some(Source::Bytes(bytes)) => TypedArray::new(ctx.clone(), bytes.as_slice()),
+                _ => Err(Exception::throw_reference(
+                    &ctx,
+                    &format!("bytes module {} not found", name),
+                )),
+            }
+        },
+    )?;
+    ctx.eval::<Function, _>(format!(
+        "(bytes) => Object.defineProperty(globalThis, \"{}\", {{ value: bytes }})",
+        MODULE_BYTES
+    ))?
+    .call((bytes,))
+}
+
+#[cfg(test)]
+mod tests {
+    use rquickjs::{CatchResultExt, Context, Module, Runtime};
+
+    use super::{install, normalize, strip_import_attributes, Registry, Source};
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(normalize("lib/a", "../b/./c.js"), "lib/b/c.js");
+        assert_eq!(normalize("lib", "../../a.js"), "a.js");
+        assert_eq!(normalize("lib", "/a.js"), "a.js");
+
+        let mut registry = Registry::default();
+        registry.register("./lib/index.js", Source::Declared);
+        registry.register("lib/util/index.js", Source::Declared);
+        registry.register("lib/math.mjs", Source::Declared);
+        registry.register("vendor/d3.js", Source::Declared);
+
+        assert_eq!(
+            registry.resolve("lib/index.js", "./util").unwrap(),
+            "lib/util/index.js"
+        );
+        assert_eq!(
+            registry.resolve("lib/index.js", "./math").unwrap(),
+            "lib/math.mjs"
+        );
+        assert_eq!(
+            registry
+                .resolve("lib/util/index.js", "../index.js")
+                .unwrap(),
+            "lib/index.js"
+        );
+        assert_eq!(
+            registry.resolve("lib/index.js", "./missing.js").unwrap(),
+            "lib/missing.js"
+        );
+        assert_eq!(registry.resolve("", "lib").unwrap(), "lib/index.js");
+        assert_eq!(registry.resolve("", "./lib/math").unwrap(), "lib/math.mjs");
+        assert_eq!(registry.resolve("", "d3").unwrap(), "d3");
+        assert_eq!(registry.resolve("", "ctxjs").unwrap(), "ctxjs");
+
+        registry.set_base("./vendor/");
+        assert_eq!(registry.resolve("", "d3").unwrap(), "vendor/d3.js");
+        assert_eq!(
+            registry.resolve("<eval>", "./d3.js").unwrap(),
+            "vendor/d3.js"
+        );
+        assert_eq!(
+            registry.resolve("", "lib/math.mjs").unwrap(),
+            "lib/math.mjs"
+        );
+    }
+
+    #[test]
+    fn test_import_map() {
+        let entry = |key: &str, address: Option<&str>| (key.to_string(), address.map(String::from));
+
+        let mut registry = Registry::default();
+        registry.set_base("app");
+        registry.register("vendor/d3-scale/index.js", Source::Declared);
+        registry.register("vendor/lodash-es/merge.js", Source::Declared);
+        registry.register("vendor/lodash-old/merge.js", Source::Declared);
+        registry.register("app/shim.js", Source::Declared);
+        registry.register("legacy/index.js", Source::Declared);
+        registry.add_import_map(
+            vec![
+                entry("d3-scale", Some("/vendor/d3-scale/index.js")),
+                entry("lodash-es/", Some("/vendor/lodash-es/")),
+                entry("./shim.js", Some("/vendor/d3-scale/index.js")),
+                entry("fs", None),
+                entry("broken/", Some("/vendor/broken")),
+            ],
+            vec![(
+                "/legacy/".to_string(),
+                vec![entry("lodash-es/", Some("/vendor/lodash-old/"))],
+            )],
+        );
+
+        assert_eq!(
+            registry.resolve("", "d3-scale").unwrap(),
+            "vendor/d3-scale/index.js"
+        );
+        assert_eq!(
+            registry.resolve("app/shim.js", "lodash-es/merge").unwrap(),
+            "vendor/lodash-es/merge.js"
+        );
+        assert_eq!(
+            registry
+                .resolve("legacy/index.js", "lodash-es/merge.js")
+                .unwrap(),
+            "vendor/lodash-old/merge.js"
+        );
+        assert_eq!(
+            registry.resolve("", "./shim.js").unwrap(),
+            "vendor/d3-scale/index.js"
+        );
+        assert_eq!(
+            registry.resolve("legacy/index.js", "./shim.js").unwrap(),
+            "legacy/shim.js"
+        );
+        assert!(registry.resolve("", "fs").is_err());
+        assert!(registry.resolve("", "broken/a.js").is_err());
+
+        // later import maps replace entries
+        registry.add_import_map(vec![entry("fs", Some("./shim.js"))], vec![]);
+        assert_eq!(registry.resolve("", "fs").unwrap(), "app/shim.js");
+    }
+
+    #[test]
+    fn test_loader() {
+        let runtime = Runtime::new().unwrap();
+        let registry = install(&runtime);
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            registry.borrow_mut().register(
+                "lib/util.js",
+                Source::Js(b"export const twice = (x) => 2 * x;".to_vec()),
+            );
+            let name = registry
+                .borrow_mut()
+                .register("./lib/index.js", Source::Declared);
+            Module::declare(
+                ctx.clone(),
+                name,
+                "import { twice } from './util'; export const value = twice(21);",
+            )
+            .unwrap()
+            .eval()
+            .catch(&ctx)
+            .unwrap();
+
+            let m: rquickjs::Object = Module::import(&ctx, "./lib/index.js")
+                .unwrap()
+                .finish()
+                .catch(&ctx)
+                .unwrap();
+            assert_eq!(m.get::<_, i32>("value").unwrap(), 42);
+
+            let m: rquickjs::Object = Module::import(&ctx, "lib/util")
+                .unwrap()
+                .finish()
+                .catch(&ctx)
+                .unwrap();
+            assert!(m.contains_key("twice").unwrap());
+
+            registry.borrow_mut().add_import_map(
+                vec![("util".to_string(), Some("./lib/util.js".to_string()))],
+                vec![],
+            );
+            let m: rquickjs::Object = Module::import(&ctx, "util")
+                .unwrap()
+                .finish()
+                .catch(&ctx)
+                .unwrap();
+            assert!(m.contains_key("twice").unwrap());
+
+            let error = Module::import(&ctx, "lib/missing.js")
+                .and_then(|promise| promise.finish::<rquickjs::Object>())
+                .catch(&ctx)
+                .unwrap_err();
+            assert!(error.to_string().contains("module not found"));
+        });
+    }
+
+    #[test]
+    fn test_strip_import_attributes() {
+        let strip = |code: &str| {
+            String::from_utf8(strip_import_attributes(code.as_bytes()).into_owned()).unwrap()
+        };
+        assert_eq!(
+            strip("import data from \"./data.json\" with { type: \"json\" };"),
+            "import data from \"./data.json\";"
+        );
+        assert_eq!(
+            strip(
+                "import './a.json' assert {type:'json'}\nexport * from 'b.json'with{type:'json'}"
+            ),
+            "import './a.json'\nexport * from 'b.json'"
+        );
+        assert_eq!(
+            strip("const data = await import('./data.json', { with: { type: 'json' } });"),
+            "const data = await import('./data.json');"
+        );
+        let code = "import { with as w } from './a.js'; const from = { with: 1 };";
+        assert_eq!(strip(code), code);
+        // only code is rewritten
+        for code in [
+            "const s = \"from 'a' with {b}\";",
+            "const s = `import 'a' with {b} ${'x'}`;",
+            "// import 'a' with { type: 'json' }",
+            "/* from 'a' with {b} */",
+            "const r = /from 'a' with {b}/;",
+            "x.import('a', { with: {} })",
+        ] {
+            assert_eq!(strip(code), code);
+        }
+        assert_eq!(
+            strip("const t = `${await import('./a.json', { with: { type: 'json' } })}`;"),
+            "const t = `${await import('./a.json')}`;"
+        );
+        assert_eq!(
+            strip("const n = (a) / 2; import 'b.json' /* json */ with { type: 'json' };"),
+            "const n = (a) / 2; import 'b.json';"
+        );
+    }
+
+    #[test]
+    fn test_synthetic_modules() {
+        let runtime = Runtime::new().unwrap();
+        let registry = install(&runtime);
+        let context = Context::full(&runtime).unwrap();
+        context.with(|ctx| {
+            super::install_context(&ctx, registry.clone()).unwrap();
+            crate::commonjs::install(&ctx, registry.clone()).unwrap();
+            let mut r = registry.borrow_mut();
+            r.register(
+                "data/table.json",
+                Source::Json(r#"{"rows": [1, 2], "__proto__": "key"}"#.to_string()),
+            );
+            r.register("data/note.txt", Source::Text("it's \"ü\"\n".to_string()));
+            r.register("data/blob.bin", Source::Bytes(vec![0, 1, 255]));
+            r.register(
+                "main.js",
+                Source::Js(
+                    b"import table from './data/table.json' with { type: 'json' };
+                    import note from './data/note.txt';
+                    import blob from './data/blob.bin';
+                    export const rows = table.rows.length;
+                    export const key = Object.keys(table).includes('__proto__');
+                    export const text = note;
+                    export const bytes = blob instanceof Uint8Array ? Array.from(blob).join() : '';"
+                        .to_vec(),
+                ),
+            );
+            r.register(
+                "lib.js",
+                Source::CommonJs(b"module.exports = require('./data/table.json').rows;".to_vec()),
+            );
+            drop(r);
+
+            let m: rquickjs::Object = Module::import(&ctx, "main.js")
+                .unwrap()
+                .finish()
+                .catch(&ctx)
+                .unwrap();
+            assert_eq!(m.get::<_, i32>("rows").unwrap(), 2);
+            assert!(m.get::<_, bool>("key").unwrap());
+            assert_eq!(m.get::<_, String>("text").unwrap(), "it's \"ü\"\n");
+            assert_eq!(m.get::<_, String>("bytes").unwrap(), "0,1,255");
+
+            assert_eq!(
+                ctx.eval::<String, _>("__ctxjs_commonjs.require('lib.js').join()")
+                    .unwrap(),
+                "1,2"
+            );
+            assert_eq!(
+                ctx.eval::<String, _>("__ctxjs_commonjs.require('./data/note.txt')")
+                    .unwrap(),
+                "it's \"ü\"\n"
+            );
+            assert_eq!(
+                ctx.eval::<String, _>(
+                    "Array.from(__ctxjs_commonjs.require('./data/blob.bin')).join()"
+                )
+                .unwrap(),
+                "0,1,255"
+            );
+            assert!(ctx
+                .eval::<(), _>("__ctxjs_module_bytes('main.js')")
+                .is_err());
+        });
+    }
+}
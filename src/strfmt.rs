@@ -1,69 +1,207 @@
-use std::{collections::HashMap, str::Utf8Error};
+use std::{collections::HashSet, fmt};
 
-pub fn strfmt(s: &[u8], m: &HashMap<&str, String>) -> Result<Vec<u8>, Utf8Error> {
-    let l = s.len();
-    let mut output = Vec::with_capacity(l);
+// `{name}` is a placeholder if the name only contains a-zA-Z0-9_-, everything else
+// (like `{}` or `{ a: 1 }`) is kept as it is. A placeholder can be escaped with a
+// backslash, `\{name}` is written as `{name}` in strict mode or if `name` is an
+// argument, otherwise it is kept as it is (like `/\{id}/u` in a regex).
+// The name can be followed by a path (`{name.key.0}`) and a specifier (`{name:json}`).
+
+pub const SPECIFIERS: [&str; 4] = ["json", "str", "raw", "bytes"];
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownPlaceholder(String),
+    UnusedArgument(String),
+    Argument(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownPlaceholder(name) => write!(f, "unknown placeholder {{{}}}", name),
+            Error::UnusedArgument(name) => write!(f, "unused argument {}", name),
+            Error::Argument(name, err) => write!(f, "invalid placeholder {{{}}}: {}", name, err),
+        }
+    }
+}
+
+pub struct Placeholder<'a> {
+    // the full placeholder without braces
+    pub text: &'a str,
+    pub name: &'a str,
+    pub path: Vec<&'a str>,
+    pub specifier: Option<&'a str>,
+}
+
+pub trait Arguments {
+    // none if the argument or the path does not exist
+    fn get(&self, placeholder: &Placeholder) -> Result<Option<String>, String>;
+    fn names(&self) -> Vec<&str>;
+}
+
+enum Part<'a> {
+    Text(&'a [u8]),
+    Placeholder(Placeholder<'a>),
+    // `\{name}`
+    Escaped(Placeholder<'a>),
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|c| matches!(c, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-'))
+}
+
+fn placeholder(text: &str) -> Option<Placeholder<'_>> {
+    let (path, specifier) = match text.split_once(':') {
+        Some((path, specifier)) if SPECIFIERS.contains(&specifier) => (path, Some(specifier)),
+        Some(_) => return None,
+        None => (text, None),
+    };
+    let mut path: Vec<&str> = path.split('.').collect();
+    if !path.iter().all(|segment| is_name(segment)) {
+        return None;
+    }
+    Some(Placeholder {
+        text,
+        name: path.remove(0),
+        path,
+        specifier,
+    })
+}
+
+// returns the end of the placeholder which starts at `start`
+fn placeholder_end(s: &[u8], start: usize) -> Option<(usize, Placeholder<'_>)> {
+    if s.get(start) != Some(&b'{') {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < s.len()
+        && matches!(s[i], b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b':')
+    {
+        i += 1;
+    }
+    if s.get(i) != Some(&b'}') {
+        return None;
+    }
+    // only ascii characters are allowed, so it is always valid utf8
+    let text = std::str::from_utf8(&s[start + 1..i]).ok()?;
+    Some((i + 1, placeholder(text)?))
+}
+
+fn parse(s: &[u8]) -> Vec<Part<'_>> {
+    let mut parts = vec![];
+    let mut text_start = 0;
     let mut i = 0;
-    while i < l {
+    while i < s.len() {
         match s[i] {
+            b'\\' => {
+                if let Some((end, placeholder)) = placeholder_end(s, i + 1) {
+                    parts.push(Part::Text(&s[text_start..i]));
+                    parts.push(Part::Escaped(placeholder));
+                    text_start = end;
+                    i = end;
+                } else {
+                    i += 1;
+                }
+            }
             b'{' => {
-                i += 1;
-                let mut key_start = i;
-                while i < l {
-                    let c = s[i];
-                    match c {
-                        b'}' => {
-                            if let Some(value) = m.get(&str::from_utf8(&s[key_start..i])?) {
-                                for ele in value.as_bytes() {
-                                    output.push(*ele);
-                                }
-                            } else {
-                                output.push(b'{');
-                                for k in key_start..i {
-                                    output.push(s[k]);
-                                }
-                                output.push(c);
-                            }
-                            i += 1;
-                            break;
-                        }
-                        (b'a'..=b'z') | (b'A'..=b'Z') | (b'0'..=b'9') | b'_' | b'-' => {
-                            i += 1;
-                        }
-                        b'{' => {
-                            output.push(b'{');
-                            i += 1;
-                            key_start = i;
-                        }
-                        _ => {
-                            output.push(b'{');
-                            for k in key_start..i {
-                                output.push(s[k]);
-                            }
-                            output.push(c);
-                            i += 1;
-                            break;
-                        }
-                    }
+                if let Some((end, placeholder)) = placeholder_end(s, i) {
+                    parts.push(Part::Text(&s[text_start..i]));
+                    parts.push(Part::Placeholder(placeholder));
+                    text_start = end;
+                    i = end;
+                } else {
+                    i += 1;
                 }
             }
-            b => {
-                output.push(b);
-                i += 1;
+            _ => i += 1,
+        }
+    }
+    parts.push(Part::Text(&s[text_start..]));
+    parts
+}
+
+// in strict mode unknown placeholders and unused arguments are errors
+pub fn strfmt(s: &[u8], m: &impl Arguments, strict: bool) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::with_capacity(s.len());
+    let mut used = HashSet::new();
+    let names = m.names();
+    for part in parse(s) {
+        match part {
+            Part::Text(text) => output.extend_from_slice(text),
+            Part::Escaped(placeholder) => {
+                if !(strict || names.contains(&placeholder.name)) {
+                    output.push(b'\\');
+                }
+                output.push(b'{');
+                output.extend_from_slice(placeholder.text.as_bytes());
+                output.push(b'}');
             }
+            Part::Placeholder(placeholder) => match m
+                .get(&placeholder)
+                .map_err(|err| Error::Argument(placeholder.text.to_string(), err))?
+            {
+                Some(value) => {
+                    output.extend_from_slice(value.as_bytes());
+                    used.insert(placeholder.name);
+                }
+                None if strict => {
+                    return Err(Error::UnknownPlaceholder(placeholder.text.to_string()))
+                }
+                None => {
+                    output.push(b'{');
+                    output.extend_from_slice(placeholder.text.as_bytes());
+                    output.push(b'}');
+                }
+            },
+        }
+    }
+    if strict {
+        let mut unused: Vec<&str> = names.into_iter().filter(|k| !used.contains(k)).collect();
+        unused.sort();
+        if let Some(name) = unused.first() {
+            return Err(Error::UnusedArgument(name.to_string()));
         }
     }
     Ok(output)
 }
 
+// all placeholders in the order of their first occurrence
+pub fn placeholders(s: &[u8]) -> Vec<&str> {
+    let mut seen = HashSet::new();
+    parse(s)
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Placeholder(placeholder) if seen.insert(placeholder.text) => {
+                Some(placeholder.text)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use super::strfmt;
+    use super::{placeholders, strfmt, Arguments, Placeholder};
+
+    impl Arguments for HashMap<&str, String> {
+        fn get(&self, placeholder: &Placeholder) -> Result<Option<String>, String> {
+            if !placeholder.path.is_empty() || placeholder.specifier.is_some() {
+                return Ok(None);
+            }
+            Ok(HashMap::get(self, placeholder.name).cloned())
+        }
+
+        fn names(&self) -> Vec<&str> {
+            self.keys().copied().collect()
+        }
+    }
 
     fn test(s: &[u8], m: &HashMap<&str, String>, t: &[u8]) -> bool {
-        match strfmt(s, m) {
+        match strfmt(s, m, false) {
             Ok(res) => res.iter().eq(t.iter()),
             Err(_) => false,
         }
@@ -93,4 +231,51 @@ mod tests {
         assert!(test(b"{{key}", &kv, b"{value"));
         assert!(test(b"{key}}", &kv, b"value}"));
     }
+
+    #[test]
+    fn test_escape() {
+        let mut kv = HashMap::<&str, String>::new();
+        kv.insert("key", "value".to_string());
+
+        assert!(test(b"\\{key}", &kv, b"{key}"));
+        assert!(test(b"\\{key} {key}", &kv, b"{key} value"));
+        assert!(test(b"/\\{/", &kv, b"/\\{/"));
+        assert!(test(b"\\{}", &kv, b"\\{}"));
+        assert!(test(b"{ a: {key} }", &kv, b"{ a: value }"));
+        // not an argument, kept like before escapes existed
+        assert!(test(b"/\\{id}/u", &kv, b"/\\{id}/u"));
+        assert!(test(b"\\{key.a:json}", &kv, b"{key.a:json}"));
+        assert_eq!(
+            strfmt(b"/\\{id}/u {key}", &kv, true).unwrap(),
+            b"/{id}/u value"
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut kv = HashMap::<&str, String>::new();
+        kv.insert("key", "value".to_string());
+        kv.insert("foo", "bar".to_string());
+
+        assert!(strfmt(b"{key} {foo}", &kv, true).is_ok());
+        assert!(strfmt(b"{key} {foo} \\{other} {}", &kv, true).is_ok());
+        assert_eq!(
+            strfmt(b"{key} {foo} {other}", &kv, true)
+                .unwrap_err()
+                .to_string(),
+            "unknown placeholder {other}"
+        );
+        assert_eq!(
+            strfmt(b"{key}", &kv, true).unwrap_err().to_string(),
+            "unused argument foo"
+        );
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders(b"{b} {a} {b} \\{c} {} { d } {e.0:json} {f:g}"),
+            vec!["b", "a", "e.0:json"]
+        );
+    }
 }
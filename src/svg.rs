@@ -0,0 +1,415 @@
+// compact svg data urls and a small sanitizer for svgs from untrusted sources
+
+use std::borrow::Cow;
+
+// elements which are removed with their content, they run code or embed other
+// documents (html in foreignObject)
+const REMOVED_ELEMENTS: [&str; 5] = ["script", "foreignobject", "iframe", "object", "embed"];
+
+// attributes with a url or a document as value
+const URL_ATTRIBUTES: [&str; 6] = ["href", "src", "data", "action", "formaction", "srcdoc"];
+
+// the percent-encoded form is about 30% smaller than base64, only the bytes
+// which are not allowed or ambiguous in urls are encoded, see
+// https://codepen.io/tigt/post/optimizing-svgs-in-data-uris
+// whitespace is kept (`xml:space="preserve"`), newlines and tabs are encoded
+pub fn data_url(svg: &str) -> String {
+    // double quotes become single quotes so the url fits in a double quoted
+    // attribute, this is only safe if there are no single quotes yet
+    let swap_quotes = !svg.contains('\'');
+
+    let mut url = String::with_capacity(svg.len() + 19);
+    url.push_str("data:image/svg+xml,");
+    for b in svg.bytes() {
+        match b {
+            b'"' if swap_quotes => url.push('\''),
+            b'"' | b'%' | b'#' | b'<' | b'>' | b'{' | b'}' | b'|' | b'\\' | b'^' | b'`' => {
+                url.push_str(&format!("%{:02X}", b))
+            }
+            b if b.is_ascii_control() || !b.is_ascii() => url.push_str(&format!("%{:02X}", b)),
+            b => url.push(b as char),
+        }
+    }
+    url
+}
+
+// xml character references (`&#114;`, `&#x72;`) and the predefined entities,
+// unknown references are kept
+fn decode_references(value: &str) -> Cow<'_, str> {
+    if !value.contains('&') {
+        return Cow::Borrowed(value);
+    }
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let c = reference.and_then(|reference| match reference {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => reference
+                .strip_prefix("#x")
+                .or_else(|| reference.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| reference.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        });
+        match (reference, c) {
+            (Some(reference), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+// css escapes (`\75 rl(`, `\@import`), https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+fn css_unescape(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        let mut hex = String::new();
+        while hex.len() < 6 && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(chars.next().unwrap_or_default());
+        }
+        if hex.is_empty() {
+            // an escaped newline is removed, every other character is itself
+            match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => unescaped.push(c),
+            }
+            continue;
+        }
+        if chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            chars.next();
+        }
+        let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+        unescaped.push(match char::from_u32(code) {
+            Some(c) if code != 0 => c,
+            _ => char::REPLACEMENT_CHARACTER,
+        });
+    }
+    Cow::Owned(unescaped)
+}
+
+// only fragments and embedded raster images stay, everything else could load
+// or run something (javascript:, http:, data:text/html, nested svgs)
+fn is_external(reference: &str) -> bool {
+    let reference = decode_references(reference);
+    let reference = reference
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'')
+        .trim();
+    let lowercase = reference.to_ascii_lowercase();
+    !(reference.is_empty()
+        || reference.starts_with('#')
+        || (lowercase.starts_with("data:image/") && !lowercase.starts_with("data:image/svg")))
+}
+
+// the value is checked after references and css escapes are decoded, like a
+// browser reads it
+fn has_external_url(value: &str) -> bool {
+    let value = decode_references(value);
+    let value = css_unescape(&value);
+    let lowercase = value.to_ascii_lowercase();
+    lowercase.contains("@import")
+        || lowercase.match_indices("url(").any(|(start, _)| {
+            let rest = &value[start + 4..];
+            is_external(&rest[..rest.find(')').unwrap_or(rest.len())])
+        })
+}
+
+fn local_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_ascii_lowercase()
+}
+
+fn keep_attribute(name: &str, value: &str) -> bool {
+    let local = local_name(name);
+    if local.starts_with("on") {
+        return false;
+    }
+    match local.as_str() {
+        local if URL_ATTRIBUTES.contains(&local) => !is_external(value),
+        // <set attributeName="href" to="javascript:..."/>
+        "attributename" => {
+            let target = local_name(decode_references(value).trim());
+            !(target.starts_with("on") || URL_ATTRIBUTES.contains(&target.as_str()))
+        }
+        _ => !has_external_url(value),
+    }
+}
+
+fn find(svg: &str, from: usize, pattern: &str) -> Result<usize, String> {
+    svg[from..]
+        .find(pattern)
+        .map(|position| from + position)
+        .ok_or_else(|| format!("missing {} at offset {}", pattern, from))
+}
+
+struct StartTag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str, &'a str)>,
+    self_closing: bool,
+    end: usize,
+}
+
+// parses the start tag at `start` (the `<`), attributes are returned as
+// (name, value, source) to keep their original quoting
+fn start_tag(svg: &str, start: usize) -> Result<StartTag<'_>, String> {
+    let bytes = svg.as_bytes();
+    let unterminated = || format!("unterminated tag at offset {}", start);
+    let is_delimiter = |b: u8| b.is_ascii_whitespace() || matches!(b, b'/' | b'>' | b'=');
+
+    let mut i = start + 1;
+    while i < bytes.len() && !is_delimiter(bytes[i]) {
+        i += 1;
+    }
+    let name = &svg[start + 1..i];
+    if name.is_empty() {
+        return Err(format!("invalid tag at offset {}", start));
+    }
+
+    let mut attributes = Vec::new();
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => return Err(unterminated()),
+            Some(b'>') => {
+                return Ok(StartTag {
+                    name,
+                    attributes,
+                    self_closing: false,
+                    end: i + 1,
+                })
+            }
+            Some(b'/') if bytes.get(i + 1) == Some(&b'>') => {
+                return Ok(StartTag {
+                    name,
+                    attributes,
+                    self_closing: true,
+                    end: i + 2,
+                })
+            }
+            Some(b'/') | Some(b'=') => {
+                i += 1;
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let attribute_start = i;
+        while i < bytes.len() && !is_delimiter(bytes[i]) {
+            i += 1;
+        }
+        let attribute_name = &svg[attribute_start..i];
+        let mut j = i;
+        while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+            j += 1;
+        }
+        let mut value = "";
+        if bytes.get(j) == Some(&b'=') {
+            j += 1;
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            match bytes.get(j) {
+                None => return Err(unterminated()),
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let end = svg[j + 1..]
+                        .find(quote as char)
+                        .map(|position| j + 1 + position)
+                        .ok_or_else(unterminated)?;
+                    value = &svg[j + 1..end];
+                    i = end + 1;
+                }
+                Some(_) => {
+                    let value_start = j;
+                    while j < bytes.len() && !(bytes[j].is_ascii_whitespace() || bytes[j] == b'>') {
+                        j += 1;
+                    }
+                    value = &svg[value_start..j];
+                    i = j;
+                }
+            }
+        }
+        attributes.push((attribute_name, value, &svg[attribute_start..i]));
+    }
+}
+
+// removes script, foreignObject, iframe, object and embed elements, event
+// handler attributes (on*), references to anything but fragments and embedded
+// raster images (href, src, data, action, formaction, srcdoc, url(), css
+// @import), doctypes (entities), comments and processing instructions other
+// than the xml declaration, malformed markup is an error instead of a guess
+pub fn sanitize(svg: &str) -> Result<String, String> {
+    let mut sanitized = String::with_capacity(svg.len());
+    let mut i = 0;
+    while let Some(position) = svg[i..].find('<') {
+        let start = i + position;
+        sanitized.push_str(&svg[i..start]);
+        let rest = &svg[start..];
+
+        if rest.starts_with("<!--") {
+            i = find(svg, start + 4, "-->")? + 3;
+        } else if rest.starts_with("<![CDATA[") {
+            i = find(svg, start + 9, "]]>")? + 3;
+            sanitized.push_str(&svg[start..i]);
+        } else if rest.starts_with("<!") {
+            // a doctype may have an internal subset with entity declarations
+            let close = find(svg, start, ">")?;
+            i = match svg[start..close].find('[') {
+                Some(_) => find(svg, find(svg, start, "]")?, ">")? + 1,
+                None => close + 1,
+            };
+        } else if rest.starts_with("<?") {
+            i = find(svg, start + 2, "?>")? + 2;
+            if rest.starts_with("<?xml ") {
+                sanitized.push_str(&svg[start..i]);
+            }
+        } else if rest.starts_with("</") {
+            i = find(svg, start, ">")? + 1;
+            sanitized.push_str(&svg[start..i]);
+        } else {
+            let tag = start_tag(svg, start)?;
+            i = tag.end;
+            let local = local_name(tag.name);
+            let content_end = |i| -> Result<(usize, usize), String> {
+                let close = find(svg, i, &format!("</{}", tag.name))?;
+                Ok((close, find(svg, close, ">")? + 1))
+            };
+
+            if REMOVED_ELEMENTS.contains(&local.as_str()) {
+                if !tag.self_closing {
+                    i = content_end(i)?.1;
+                }
+                continue;
+            }
+            if local == "style" && !tag.self_closing {
+                let (close, end) = content_end(i)?;
+                if has_external_url(&svg[i..close]) {
+                    i = end;
+                    continue;
+                }
+            }
+
+            sanitized.push('<');
+            sanitized.push_str(tag.name);
+            for (name, value, source) in &tag.attributes {
+                if keep_attribute(name, value) {
+                    sanitized.push(' ');
+                    sanitized.push_str(source);
+                }
+            }
+            sanitized.push_str(if tag.self_closing { "/>" } else { ">" });
+        }
+    }
+    sanitized.push_str(&svg[i..]);
+    Ok(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_url, sanitize};
+
+    #[test]
+    fn test_data_url() {
+        assert_eq!(
+            data_url("<svg xmlns=\"http://www.w3.org/2000/svg\">\n  <path fill=\"#f00\" d=\"M0 0\"/>\n</svg>\n"),
+            "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg'%3E%0A  %3Cpath fill='%23f00' d='M0 0'/%3E%0A%3C/svg%3E%0A"
+        );
+        assert_eq!(
+            data_url("<text title=\"it's\">50% ü</text>"),
+            "data:image/svg+xml,%3Ctext title=%22it's%22%3E50%25 %C3%BC%3C/text%3E"
+        );
+
+        let svg = "<svg><text>a\"b</text></svg>";
+        let url = crate::data_url::parse(&data_url(svg)).unwrap();
+        assert_eq!(url.mime, "image/svg+xml");
+        assert_eq!(url.data, b"<svg><text>a'b</text></svg>");
+
+        let svg = "<text xml:space='preserve'>\t a  \r\n b </text>";
+        assert_eq!(
+            crate::data_url::parse(&data_url(svg)).unwrap().data,
+            svg.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize("<?xml version=\"1.0\"?><svg onload=\"alert(1)\" width='10'><script>alert(1)</script><rect/></svg>")
+                .unwrap(),
+            "<?xml version=\"1.0\"?><svg width='10'><rect/></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><a xlink:href=\"javascript:alert(1)\"><use href=\"#a\"/></a><image href='https://example.com/a.png'/><image href='data:image/png;base64,AA=='/></svg>")
+                .unwrap(),
+            "<svg><a><use href=\"#a\"/></a><image/><image href='data:image/png;base64,AA=='/></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><rect fill=\"url(#g)\" style=\"fill: url(http://a/b)\"/><set attributeName=\"href\" to=\"javascript:alert(1)\"/></svg>")
+                .unwrap(),
+            "<svg><rect fill=\"url(#g)\"/><set to=\"javascript:alert(1)\"/></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><style>@import 'http://a/b.css';</style><style>rect { fill: red }</style></svg>").unwrap(),
+            "<svg><style>rect { fill: red }</style></svg>"
+        );
+        assert_eq!(
+            sanitize("<!DOCTYPE svg [<!ENTITY a SYSTEM \"http://a\">]><!-- c --><?xml-stylesheet href=\"a.css\"?><svg><svg:script/><text><![CDATA[<script>]]></text></svg>")
+                .unwrap(),
+            "<svg><text><![CDATA[<script>]]></text></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg ONCLICK=a disabled><p>1 &lt; 2</p></svg>").unwrap(),
+            "<svg disabled><p>1 &lt; 2</p></svg>"
+        );
+
+        // character references and css escapes
+        assert_eq!(
+            sanitize("<svg><rect style=\"fill:u&#114;l(http://x)\"/><a href=\"&#106;avascript:alert(1)\"/><style>@&#105;mport 'http://x';</style><style>a { b: \\75 rl(http://x) }</style><style>a { b: u\\rl('http://x') }</style></svg>")
+                .unwrap(),
+            "<svg><rect/><a/></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><rect fill=\"u&#114;l(#g)\" style=\"fill: \\75 rl(#g)\"/></svg>")
+                .unwrap(),
+            "<svg><rect fill=\"u&#114;l(#g)\" style=\"fill: \\75 rl(#g)\"/></svg>"
+        );
+        // embedded documents and forms
+        assert_eq!(
+            sanitize("<svg><foreignObject><iframe srcdoc=\"&lt;script&gt;alert(1)&lt;/script&gt;\"></iframe></foreignObject><object data=\"http://x\"/><g><form action=\"javascript:alert(1)\"><button formaction=\"javascript:alert(1)\">a</button></form></g></svg>")
+                .unwrap(),
+            "<svg><g><form><button>a</button></form></g></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><iframe srcdoc=\"a\"/><set attributeName=\"formaction\" to=\"javascript:alert(1)\"/><a data=\"#a\"/></svg>").unwrap(),
+            "<svg><set to=\"javascript:alert(1)\"/><a data=\"#a\"/></svg>"
+        );
+
+        assert!(sanitize("<svg><script>alert(1)").is_err());
+        assert!(sanitize("<svg onload='a").is_err());
+        assert!(sanitize("<svg><!-- a").is_err());
+        assert!(sanitize("< svg>").is_err());
+    }
+}
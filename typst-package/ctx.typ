@@ -65,9 +65,12 @@
   /// the js code which should be evaluate
   /// -> str | bytes
   js,
-  /// named args which replaces the name in the js code with the typst value as js value, only characters a-zA-Z0-9\_- as name are allowed
+  /// named args which replaces the name in the js code with the typst value as js value, only characters a-zA-Z0-9\_- as name are allowed. A path like `{data.series.0.name}` selects a part of the value and a specifier changes the output: `{x:json}` json, `{x:str}` a quoted string, `{x:raw}` the string as code, `{x:bytes}` an `Uint8Array`
   /// -> any
   ..args,
+  /// if unknown placeholders and unused args should be an error, a placeholder can be escaped with a backslash (`\{name}`), in non-strict mode only if `name` is an arg, otherwise the backslash is kept
+  /// -> bool
+  strict: false,
   /// if a new context should be created (with changed data)
   /// -> bool
   transition: false,
@@ -78,6 +81,48 @@
     transition,
     bytes(js),
     cbor.encode(args.named()),
+    bytes((if strict { 1 } else { 0 },)),
+  )
+}
+
+/// Evaluates the js code with the named args as js variables. The code is compiled once as a function with the names as parameters, so changed values do not create new code. A single expression is returned, otherwise the code has to use `return`.
+///
+/// ```examplec
+/// ctxjs.ctx.eval-with(
+///   current-context,
+///   "a + b",
+///   a: 1,
+///   b: 2,
+/// )
+/// ```
+/// -> (<module>, any)
+#let eval-with(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the js code which should be evaluate
+  /// -> str | bytes
+  js,
+  /// named args which are available as variables in the js code, only valid js identifiers are allowed as name
+  /// -> any
+  ..args,
+  /// if a new context should be created (with changed data)
+  /// -> bool
+  transition: false,
+  /// if large arrays and dictionaries should be decoded on access
+  /// -> bool
+  lazy: false,
+  /// if arrays, dictionaries, json values and typst values should be deep-frozen, so the js code can not change them (bytes are typed arrays, they can not be frozen, lazy arrays and dictionaries reject changes but `Object.isFrozen` is `false` for them)
+  /// -> bool
+  freeze: false,
+) = {
+  _internal.transition-call(
+    ctx,
+    ctx.eval_with,
+    transition,
+    bytes(js),
+    cbor.encode(args.named()),
+    flags: _internal.decode-flags(lazy: lazy, freeze: freeze),
   )
 }
 
@@ -96,9 +141,53 @@
   /// the context in which this function should run
   /// -> any
   ..vars,
+  /// if arrays, dictionaries, json values and typst values should be deep-frozen, so js code can not change them (bytes are typed arrays, they can not be frozen)
+  /// -> bool
+  freeze: false,
+  /// how the vars are declared: `"let"`, `"const"`, `"var"`, `"global"` (property of `globalThis`) or `"assign"` (assigns an existing variable or creates a global property)
+  /// -> str
+  mode: "let",
 ) = {
   (
-    plugin.transition(ctx.define_vars, cbor.encode(vars.named())),
+    plugin.transition(
+      ctx.define_vars,
+      cbor.encode(vars.named()),
+      bytes(mode),
+      bytes((_internal.decode-flags(freeze: freeze),)),
+    ),
+    none,
+  )
+}
+
+/// Sets the vars as properties of `globalThis` in a new context. The values are not converted to js code, so large data and bytes are faster and keep their exact values. Names can be paths like `config.theme`, missing objects are created.
+/// ```examplec
+/// ctxjs.ctx.define-globals(
+///   current-context,
+///   varname: "value",
+///   ..("config.theme": "dark"),
+/// )
+/// ```
+/// -> (<module>, none)
+#let define-globals(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the vars with their names or paths, every path segment must be an identifier, `__proto__`, `constructor` and `prototype` are not allowed
+  /// -> any
+  ..vars,
+  /// if large arrays and dictionaries should be decoded on access
+  /// -> bool
+  lazy: false,
+  /// if arrays, dictionaries, json values and typst values should be deep-frozen, so js code can not change them (bytes are typed arrays, they can not be frozen, lazy arrays and dictionaries reject changes but `Object.isFrozen` is `false` for them)
+  /// -> bool
+  freeze: false,
+) = {
+  (
+    plugin.transition(
+      ctx.define_globals,
+      cbor.encode(vars.named()),
+      bytes((_internal.decode-flags(lazy: lazy, freeze: freeze),)),
+    ),
     none,
   )
 }
@@ -130,6 +219,12 @@
   /// if a new context should be created (with changed data)
   /// -> bool
   transition: false,
+  /// if large arrays and dictionaries should be decoded on access
+  /// -> bool
+  lazy: false,
+  /// if arrays, dictionaries, json values and typst values should be deep-frozen, so the function can not change them (bytes are typed arrays, they can not be frozen, lazy arrays and dictionaries reject changes but `Object.isFrozen` is `false` for them)
+  /// -> bool
+  freeze: false,
 ) = {
   _internal.transition-call(
     ctx,
@@ -137,6 +232,7 @@
     transition,
     bytes(fnname),
     cbor.encode(args.pos()),
+    flags: _internal.decode-flags(lazy: lazy, freeze: freeze),
   )
 }
 
@@ -164,6 +260,7 @@
 }
 
 /// Loads js module code into a new context.
+/// The module can import modules loaded or registered (see @register-module) before by name or relative path.
 /// ```examplec
 /// ctxjs.ctx.load-module-js(
 ///   current-context,
@@ -193,6 +290,249 @@
   )
 }
 
+/// Registers js module code without evaluating it, the module is loaded on its first import.
+/// Module names are paths (`"lib/util.js"`), modules can import each other with relative specifiers (`"./util.js"`, `"../index.js"`),
+/// the extensions `.js` and `.mjs` and `/index.js` can be omitted. Bare names (`"util"`) are looked up as registered name and relative to the base path (see @module-base).
+/// ```examplec
+/// ctxjs.ctx.register-module(
+///   current-context,
+///   "lib/util.js",
+///   "export const twice = (x) => 2 * x;",
+/// )
+/// ```
+/// -> (<module>, none)
+#let register-module(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the module name
+  /// -> str
+  modulename,
+  /// the js module code
+  /// -> str | bytes
+  module,
+) = {
+  (
+    plugin.transition(
+      ctx.register_module,
+      bytes(modulename),
+      bytes(module),
+    ),
+    none,
+  )
+}
+
+/// Registers a CommonJS or UMD module, it runs on its first `require` or import and is cached afterwards.
+/// The module gets `require`, `module`, `exports`, `__filename` and `__dirname`, `require` resolves like imports (see @register-module).
+/// Imported from js modules `module.exports` is the default export and its properties are named exports,
+/// so @call-module-function works with CommonJS modules too. CommonJS modules can't `require` js modules.
+/// Properties named `eval` or `arguments` are only reachable through the default export.
+/// Scripts reach CommonJS modules through the global `require`, which resolves from the root,
+/// it is only defined if the context had no global `require` before.
+/// ```examplec
+/// ctxjs.ctx.load-commonjs(
+///   current-context,
+///   "lib/util.js",
+///   "exports.twice = (x) => 2 * x;",
+/// )
+/// ```
+/// -> (<module>, none)
+#let load-commonjs(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the module name
+  /// -> str
+  modulename,
+  /// the CommonJS module code
+  /// -> str | bytes
+  module,
+) = {
+  (
+    plugin.transition(
+      ctx.load_commonjs,
+      bytes(modulename),
+      bytes(module),
+    ),
+    none,
+  )
+}
+
+/// Registers a JSON module, its default export is the parsed JSON (`import data from "./data.json" with { type: "json" }`).
+/// The JSON is validated at registration. CommonJS modules can `require` it too.
+/// Import attributes are accepted but not checked, the kind of a module is given by how it was registered.
+/// ```examplec
+/// ctxjs.ctx.register-json-module(
+///   current-context,
+///   "data/table.json",
+///   read("table.json"),
+/// )
+/// ```
+/// -> (<module>, none)
+#let register-json-module(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the module name
+  /// -> str
+  modulename,
+  /// the JSON text
+  /// -> str | bytes
+  json,
+) = {
+  (
+    plugin.transition(
+      ctx.register_json_module,
+      bytes(modulename),
+      bytes(json),
+    ),
+    none,
+  )
+}
+
+/// Registers a text module, its default export is the text as string (`import note from "./note.txt"`).
+/// ```examplec
+/// ctxjs.ctx.register-text-module(
+///   current-context,
+///   "data/note.txt",
+///   "some text",
+/// )
+/// ```
+/// -> (<module>, none)
+#let register-text-module(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the module name
+  /// -> str
+  modulename,
+  /// the UTF-8 text
+  /// -> str | bytes
+  text,
+) = {
+  (
+    plugin.transition(
+      ctx.register_text_module,
+      bytes(modulename),
+      bytes(text),
+    ),
+    none,
+  )
+}
+
+/// Registers a bytes module, its default export is a `Uint8Array` of the data (`import font from "./font.ttf"`).
+/// ```examplec
+/// ctxjs.ctx.register-bytes-module(
+///   current-context,
+///   "data/font.ttf",
+///   read("font.ttf", encoding: none),
+/// )
+/// ```
+/// -> (<module>, none)
+#let register-bytes-module(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the module name
+  /// -> str
+  modulename,
+  /// the raw data
+  /// -> bytes
+  data,
+) = {
+  (
+    plugin.transition(
+      ctx.register_bytes_module,
+      bytes(modulename),
+      bytes(data),
+    ),
+    none,
+  )
+}
+
+/// Sets the base path for bare module names and relative imports outside of modules.
+/// ```examplec
+/// ctxjs.ctx.module-base(current-context, "lib")
+/// ```
+/// -> (<module>, none)
+#let module-base(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the base path
+  /// -> str
+  base,
+) = {
+  (
+    plugin.transition(ctx.module_base, bytes(base)),
+    none,
+  )
+}
+
+/// Installs an import map (`imports` and `scopes` like in browsers) for bare specifiers like `"d3-scale"` or `"lodash-es/merge"`.
+/// Keys ending with `/` map prefixes, `none` blocks a specifier, relative keys and addresses are relative to the base path (see @module-base) and `/` is the root of the module names.
+/// Entries of later import maps replace the ones with the same key. Mapped names can be used in @call-module-function and @get-module-properties too.
+/// ```examplec
+/// ctxjs.ctx.import-map(
+///   current-context,
+///   (
+///     imports: ("lodash-es/": "/vendor/lodash-es/"),
+///     scopes: ("/legacy/": ("lodash-es/": "/vendor/lodash-4/")),
+///   ),
+/// )
+/// ```
+/// -> (<module>, none)
+#let import-map(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the import map, for example `json("importmap.json")`
+  /// -> dictionary
+  map,
+) = {
+  (
+    plugin.transition(ctx.import_map, cbor.encode(map)),
+    none,
+  )
+}
+
+/// Registers named bytes (fonts, images, data files) in the context, so they are passed from typst only once instead of on every call.
+/// In js they are available as `ctxjs.assets.get(name)` (`{ name, mime, data }` with `data` as `Uint8Array`) and `ctxjs.assets.list()`,
+/// arguments can reference them with @value.asset. An asset with the same name is replaced.
+/// Every access gets its own copy of the `Uint8Array` inside the context, so js code which changes it can not change the asset for later calls.
+/// ```examplec
+/// ctxjs.ctx.load-asset(
+///   current-context,
+///   "logo",
+///   bytes("<svg></svg>"),
+/// )
+/// ```
+/// -> (<module>, none)
+#let load-asset(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// the asset name
+  /// -> str
+  name,
+  /// the asset data
+  /// -> str | bytes
+  data,
+  /// the mime type, if it is `auto` it is detected (see @value.data-url)
+  /// -> str | auto
+  mime: auto,
+) = {
+  (
+    plugin.transition(
+      ctx.load_asset,
+      bytes(name),
+      bytes(data),
+      bytes(if mime == auto { "" } else { mime }),
+    ),
+    none,
+  )
+}
+
 /// Calls a js function in a module by function name with an args.
 /// ```examplec
 /// let (current-context,_) =  ctxjs.ctx.load-module-js(
@@ -224,6 +564,12 @@
   /// if a new context should be created (with changed data)
   /// -> bool
   transition: false,
+  /// if large arrays and dictionaries should be decoded on access
+  /// -> bool
+  lazy: false,
+  /// if arrays, dictionaries, json values and typst values should be deep-frozen, so the function can not change them (bytes are typed arrays, they can not be frozen, lazy arrays and dictionaries reject changes but `Object.isFrozen` is `false` for them)
+  /// -> bool
+  freeze: false,
 ) = {
   _internal.transition-call(
     ctx,
@@ -232,6 +578,7 @@
     bytes(modulename),
     bytes(fnname),
     cbor.encode(args.pos()),
+    flags: _internal.decode-flags(lazy: lazy, freeze: freeze),
   )
 }
 
@@ -293,3 +640,73 @@
     cbor(ctx.get_module_property(bytes(modulename), bytes(propertyname))),
   )
 }
+
+/// Sets how numbers are converted between typst and js in a new context.
+/// ```examplec
+/// ctxjs.ctx.number-policy(
+///   current-context,
+///   input: "bigint",
+///   integral-floats-as-ints: true,
+/// )
+/// ```
+/// -> (<module>, none)
+#let number-policy(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// how integers from typst are converted: `"safe"` as number if it is a safe integer otherwise as BigInt, `"number"` always as number, `"bigint"` always as BigInt
+  /// -> str
+  input: "safe",
+  /// if js numbers without a fractional part should be returned as typst integers instead of floats
+  /// -> bool
+  integral-floats-as-ints: false,
+) = {
+  (
+    plugin.transition(ctx.number_policy, cbor.encode((input, integral-floats-as-ints))),
+    none,
+  )
+}
+
+/// Returns only a part of the value stored by the last call with `transition: true`, without decoding the whole value again.
+/// ```examplec
+/// ctxjs.ctx.stored-value-at(
+///   current-context,
+///   "/series/0/name",
+/// )
+/// ```
+/// -> (<module>, any)
+#let stored-value-at(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// a json pointer (`/series/0`) or a dotted path (`series.0`), an empty string selects the whole value
+  /// -> str
+  pointer,
+) = {
+  (
+    ctx,
+    cbor(ctx.stored_value_at(bytes(pointer))),
+  )
+}
+
+/// Returns the type (`"none"`, `"bool"`, `"int"`, `"float"`, `"str"`, `"bytes"`, `"array"` or `"dictionary"`) and the length (characters, bytes or entries, `none` for other types) of a part of the stored value (see @stored-value-at).
+/// ```examplec
+/// ctxjs.ctx.stored-value-info(
+///   current-context,
+///   "/series",
+/// )
+/// ```
+/// -> (<module>, dictionary)
+#let stored-value-info(
+  /// the context in which this function should run
+  /// -> <module>
+  ctx,
+  /// a json pointer (`/series/0`) or a dotted path (`series.0`), an empty string selects the whole value
+  /// -> str
+  pointer,
+) = {
+  (
+    ctx,
+    cbor(ctx.stored_value_info(bytes(pointer))),
+  )
+}
@@ -9,6 +9,18 @@
 #let load-load-module-bytecode = 4;
 #let load-load-module-js = 5;
 #let load-call-module-function = 6;
+#let load-number-policy = 7;
+#let load-eval-with = 8;
+#let load-define-globals = 9;
+#let load-schema = 10;
+#let load-asset = 11;
+#let load-register-module = 12;
+#let load-module-base = 13;
+#let load-import-map = 14;
+#let load-commonjs = 15;
+#let load-json-module = 16;
+#let load-text-module = 17;
+#let load-bytes-module = 18;
 
 // ! same as cbor/con.rs ! //
 // https://www.iana.org/assignments/cbor-tags/cbor-tags.xhtml (private tags)
@@ -17,6 +29,14 @@
 #let eval = 80001
 #let eval-format = 80002
 #let json = 80003
+#let typst = 80004
+#let json5 = 80005
+#let asset = 80006
+
+// ! same as cbor/rquickjs/decode.rs ! //
+
+#let decode-flag-lazy = 1
+#let decode-flag-freeze = 2
 
 
 // ! additional ! //
@@ -63,9 +83,13 @@
   data
 }
 
-#let transition-call(ctx, fn, transition, ..args) = {
+#let decode-flags(lazy: false, freeze: false) = {
+  (if lazy { decode-flag-lazy } else { 0 }).bit-or(if freeze { decode-flag-freeze } else { 0 })
+}
+
+#let transition-call(ctx, fn, transition, ..args, flags: 0) = {
   if transition {
-    ctx = plugin.transition(fn, ..args.pos(), bytes((1,)))
+    ctx = plugin.transition(fn, ..args.pos(), bytes((1, flags)))
     return (
       ctx,
       cbor(ctx.stored_value()),
@@ -73,7 +97,7 @@
   } else {
     return (
       ctx,
-      cbor(fn(..args.pos(), bytes((0,)))),
+      cbor(fn(..args.pos(), bytes((0, flags)))),
     )
   }
 }
@@ -19,3 +19,64 @@
 ) = {
   return plugin.transition(_internal.wasm.new_context, _internal.build-load-data(load.pos()))
 }
+
+/// Lists the names of all placeholders (`{name}`) in a js code for @ctx.eval-format.
+/// ```examplec
+/// ctxjs.format-placeholders("{a} + {b} + \\{c}")
+/// ```
+/// -> array
+#let format-placeholders(
+  /// the js code with placeholders
+  /// -> str | bytes
+  js,
+) = {
+  return cbor(_internal.wasm.format_placeholders(bytes(js)))
+}
+
+/// Validates a json string and reports where and why it is invalid.
+/// ```examplec
+/// ctxjs.validate-json("{\"a\": 1,}")
+/// ```
+/// -> dictionary
+#let validate-json(
+  /// the json text
+  /// -> str | bytes
+  json,
+  /// the maximal nesting of arrays and objects, `none` uses the default (512)
+  /// -> int | none
+  max-depth: none,
+) = {
+  let max-depth = if max-depth == none { bytes(()) } else { cbor.encode(max-depth) }
+  return cbor(_internal.wasm.validate_json(bytes(json), max-depth))
+}
+
+/// Validates a value with a json schema (subset of draft 2020-12: `type`, `enum`, `const`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `minLength`, `maxLength`, `pattern`, `items`, `prefixItems`, `minItems`, `maxItems`, `properties`, `required`, `additionalProperties` and local `$ref`).
+/// Returns an array of errors with `path` and `schema-path` as json pointer and a `message`, the array is empty for valid values.
+/// ```examplec
+/// ctxjs.validate-schema((a: "1"), (properties: (a: (type: "integer"))))
+/// ```
+/// -> array
+#let validate-schema(
+  /// the value
+  /// -> any
+  value,
+  /// the json schema
+  /// -> dictionary | bool
+  schema,
+) = {
+  return cbor(_internal.wasm.validate_schema(cbor.encode(value), cbor.encode(schema)))
+}
+
+/// Unpacks a base64 or percent-encoded data url (for example returned by js).
+/// Returns a dictionary with the `mime` type, the `params` (like `charset`), whether it was `base64` encoded and the decoded `data` as bytes.
+/// ```examplec
+/// ctxjs.parse-data-url("data:text/plain;charset=utf-8,hello%20world")
+/// ```
+/// -> dictionary
+#let parse-data-url(
+  /// the data url
+  /// -> str
+  url,
+) = {
+  return cbor(_internal.wasm.parse_data_url(bytes(url)))
+}
@@ -16,8 +16,18 @@
 /// ctxjs.load.eval-format("function() {return value;}", value: 1)
 /// ```
 /// -> bytes
-#let eval-format(js, ..args) = {
-  _internal.build-load-argument(_internal.load-eval-format, cbor.encode((js, args.named())))
+#let eval-format(js, ..args, strict: false) = {
+  _internal.build-load-argument(_internal.load-eval-format, cbor.encode((bytes(js), args.named(), strict)))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.eval-with at loading.
+/// ```examplec
+/// ctxjs.load.eval-with("globalThis.value = value", value: 1)
+/// ```
+/// -> bytes
+#let eval-with(js, ..args) = {
+  _internal.build-load-argument(_internal.load-eval-with, cbor.encode((bytes(js), args.named())))
 }
 
 /// Creates load bytes for @ctxjs.new-context or @ctx.load.
@@ -26,8 +36,24 @@
 /// ctxjs.load.define-vars(var1: 1, var2: 2)
 /// ```
 /// -> bytes
-#let define-vars(..vars) = {
-  _internal.build-load-argument(_internal.load-define-vars, cbor.encode(vars.named()))
+#let define-vars(..vars, freeze: false, mode: "let") = {
+  _internal.build-load-argument(
+    _internal.load-define-vars,
+    cbor.encode((_internal.decode-flags(freeze: freeze), mode, vars.named())),
+  )
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.define-globals at loading.
+/// ```examplec
+/// ctxjs.load.define-globals(var1: 1, ..("config.theme": "dark"))
+/// ```
+/// -> bytes
+#let define-globals(..vars, lazy: false, freeze: false) = {
+  _internal.build-load-argument(
+    _internal.load-define-globals,
+    cbor.encode((_internal.decode-flags(lazy: lazy, freeze: freeze), vars.named())),
+  )
 }
 
 /// Creates load bytes for @ctxjs.new-context or @ctx.load.
@@ -36,8 +62,11 @@
 /// ctxjs.load.call-function("fnname", 1)
 /// ```
 /// -> bytes
-#let call-function(fnname, ..args) = {
-  _internal.build-load-argument(_internal.load-call-function, cbor.encode((fnname, args.pos())))
+#let call-function(fnname, ..args, lazy: false, freeze: false) = {
+  _internal.build-load-argument(
+    _internal.load-call-function,
+    cbor.encode((fnname, _internal.decode-flags(lazy: lazy, freeze: freeze), args.pos())),
+  )
 }
 
 /// Creates load bytes for @ctxjs.new-context or @ctx.load.
@@ -64,12 +93,125 @@
   _internal.build-load-argument(_internal.load-load-module-js, cbor.encode((modulename, module)))
 }
 
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.register-module at loading.
+/// ```examplec
+/// ctxjs.load.register-module(
+///   "lib/util.js",
+///   "export const twice = (x) => 2 * x;",
+/// )
+/// ```
+/// -> bytes
+#let register-module(modulename, module) = {
+  _internal.build-load-argument(_internal.load-register-module, cbor.encode((modulename, bytes(module))))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.load-commonjs at loading.
+/// ```examplec
+/// ctxjs.load.load-commonjs(
+///   "lib/util.js",
+///   "exports.twice = (x) => 2 * x;",
+/// )
+/// ```
+/// -> bytes
+#let load-commonjs(modulename, module) = {
+  _internal.build-load-argument(_internal.load-commonjs, cbor.encode((modulename, bytes(module))))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.register-json-module at loading.
+/// ```examplec
+/// ctxjs.load.register-json-module("data/table.json", read("table.json"))
+/// ```
+/// -> bytes
+#let register-json-module(modulename, json) = {
+  _internal.build-load-argument(_internal.load-json-module, cbor.encode((modulename, bytes(json))))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.register-text-module at loading.
+/// ```examplec
+/// ctxjs.load.register-text-module("data/note.txt", "some text")
+/// ```
+/// -> bytes
+#let register-text-module(modulename, text) = {
+  _internal.build-load-argument(_internal.load-text-module, cbor.encode((modulename, str(text))))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.register-bytes-module at loading.
+/// ```examplec
+/// ctxjs.load.register-bytes-module("data/font.ttf", read("font.ttf", encoding: none))
+/// ```
+/// -> bytes
+#let register-bytes-module(modulename, data) = {
+  _internal.build-load-argument(_internal.load-bytes-module, cbor.encode((modulename, bytes(data))))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.module-base at loading.
+/// ```examplec
+/// ctxjs.load.module-base("lib")
+/// ```
+/// -> bytes
+#let module-base(base) = {
+  _internal.build-load-argument(_internal.load-module-base, cbor.encode(base))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.import-map at loading.
+/// ```examplec
+/// ctxjs.load.import-map((imports: ("d3-scale": "/vendor/d3-scale/index.js")))
+/// ```
+/// -> bytes
+#let import-map(map) = {
+  _internal.build-load-argument(_internal.load-import-map, cbor.encode(map))
+}
+
 /// Creates load bytes for @ctxjs.new-context or @ctx.load.
 /// Same function as @ctx.call-module-function at loading.
 /// ```examplec
 /// ctxjs.load.call-module-function("example_module", "text", arg1: 1)
 /// ```
 /// -> bytes
-#let call-module-function(modulename, fnname, ..args) = {
-  _internal.build-load-argument(_internal.load-call-module-function, cbor.encode((modulename, fnname, args.pos())))
+#let call-module-function(modulename, fnname, ..args, lazy: false, freeze: false) = {
+  _internal.build-load-argument(
+    _internal.load-call-module-function,
+    cbor.encode((modulename, fnname, _internal.decode-flags(lazy: lazy, freeze: freeze), args.pos())),
+  )
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.number-policy at loading.
+/// ```examplec
+/// ctxjs.load.number-policy(input: "number", integral-floats-as-ints: true)
+/// ```
+/// -> bytes
+#let number-policy(input: "safe", integral-floats-as-ints: false) = {
+  _internal.build-load-argument(_internal.load-number-policy, cbor.encode((input, integral-floats-as-ints)))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Registers a json schema (see @ctxjs.validate-schema) for the arguments array of a function called with @ctx.call-function,
+/// invalid arguments fail before they reach js. `none` removes the schema.
+/// ```examplec
+/// ctxjs.load.schema("chart", (type: "array", prefixItems: ((type: "object", required: ("data",)),)))
+/// ```
+/// -> bytes
+#let schema(fnname, schema) = {
+  _internal.build-load-argument(_internal.load-schema, cbor.encode((fnname, schema)))
+}
+
+/// Creates load bytes for @ctxjs.new-context or @ctx.load.
+/// Same function as @ctx.load-asset at loading.
+/// ```examplec
+/// ctxjs.load.asset("logo", bytes("<svg></svg>"))
+/// ```
+/// -> bytes
+#let asset(name, data, mime: auto) = {
+  _internal.build-load-argument(
+    _internal.load-asset,
+    cbor.encode((name, bytes(data), if mime == auto { "" } else { mime })),
+  )
 }
@@ -46,11 +46,14 @@
   /// the js code which should be evaluate
   /// -> str | bytes
   js,
-  /// named args which replaces the name in the js code with the typst value as js value, only characters a-zA-Z0-9\_- as name are allowed
+  /// named args which replaces the name in the js code with the typst value as js value, only characters a-zA-Z0-9\_- as name are allowed. A path like `{data.series.0.name}` selects a part of the value and a specifier changes the output: `{x:json}` json, `{x:str}` a quoted string, `{x:raw}` the string as code, `{x:bytes}` an `Uint8Array`
   /// -> any
   ..args,
+  /// if unknown placeholders and unused args should be an error, a placeholder can be escaped with a backslash (`\{name}`), in non-strict mode only if `name` is an arg, otherwise the backslash is kept
+  /// -> bool
+  strict: false,
 ) = {
-  _internal.cbor-tagged-data(_internal.eval-format, cbor.encode((bytes(js), args.named())))
+  _internal.cbor-tagged-data(_internal.eval-format, cbor.encode((bytes(js), args.named(), strict)))
 }
 
 /// Similar to @eval the function returns a special formated bytes (`$ctxjs_cbor_` + tagged cbor) but with raw json code which will be also validated as pure json code in ctxjs.
@@ -66,16 +69,59 @@
   _internal.cbor-tagged-data(_internal.json, cbor.encode(bytes(json)))
 }
 
-/// Returns a data url from an image.
+/// Similar to @json but accepts json5 (and jsonc): comments, trailing commas, unquoted keys, single quoted strings, hexadecimal numbers, `Infinity` and `NaN`. The code is validated and converted in ctxjs, it is never evaluated as js code.
+/// ```examplec
+/// ctxjs.value.json5("{a: 0xff, b: 'c', /* comment */ }")
+/// ```
+/// -> bytes
+#let json5(
+  /// json5 code
+  /// -> str | bytes
+  json5,
+) = {
+  _internal.cbor-tagged-data(_internal.json5, cbor.encode(bytes(json5)))
+}
+
+/// References an asset registered with @ctx.load-asset or @load.asset by name, in js the value is the `Uint8Array` of the asset.
+/// Every argument gets its own copy inside the context, changing it does not change the asset.
+/// ```examplec
+/// ctxjs.value.asset("logo")
+/// ```
+/// -> bytes
+#let asset(
+  /// the asset name
+  /// -> str
+  name,
+) = {
+  _internal.cbor-tagged-data(_internal.asset, cbor.encode(name))
+}
+
+/// Similar to @eval the function returns a special formated bytes (`$ctxjs_cbor_` + tagged cbor) but marks the value as typst data.
+/// Lengths, angles, ratios, fractions, colors, datetimes and durations inside the value are not passed as strings,
+/// instead they become js objects (`typst.Length`, `typst.Angle`, `typst.Ratio`, `typst.Relative`, `typst.Fraction`, `typst.Color`, `typst.Datetime`, `typst.Duration`)
+/// with conversion helpers like `toPt()` or `toCss()`. Returned objects are converted back into the typst string representation.
+/// ```examplec
+/// ctxjs.value.typst((width: 12pt, fill: red))
+/// ```
+/// -> bytes
+#let typst(
+  /// the typst value, can also be an array or dictionary which contains typst values
+  /// -> any
+  value,
+) = {
+  _internal.cbor-tagged-data(_internal.typst, cbor.encode(value))
+}
+
+/// Returns a base64 data url from an image, for svgs @svg-data-url returns a smaller url.
 /// ```examplec
 /// ctxjs.value.image-data-url(bytes("<svg></svg>"))
 /// ```
 /// -> str
 #let image-data-url(
-  /// the png, jpg, gif or svg image as bytes
+  /// the png, jpeg, gif, webp, avif, bmp, ico, tiff or svg image as bytes
   /// -> bytes
   data,
-  /// can be used to override and pre set the image format, if format is `auto` the format with automatically detected
+  /// can be used to override and pre set the image format (`"png"`, `"jpeg"`, `"gif"`, `"webp"`, `"avif"`, `"bmp"`, `"ico"`, `"tiff"` or `"svg"`), if format is `auto` the format with automatically detected
   /// -> bytes
   format: auto,
 ) = {
@@ -84,3 +130,71 @@
   }
   return str(_internal.wasm.image_data_url(data, bytes(format)))
 }
+
+/// Returns a percent-encoded svg data url, which is about 30% smaller than the base64 data url of @image-data-url.
+/// ```examplec
+/// ctxjs.value.svg-data-url("<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>")
+/// ```
+/// -> str
+#let svg-data-url(
+  /// the svg
+  /// -> str | bytes
+  data,
+  /// if the svg should be sanitized before (see @sanitize-svg)
+  /// -> bool
+  sanitize: false,
+) = {
+  return str(_internal.wasm.svg_data_url(bytes(data), bytes((if sanitize { 1 } else { 0 },))))
+}
+
+/// Removes `<script>`, `<foreignObject>`, `<iframe>`, `<object>` and `<embed>` elements, event handler attributes (`onload`, `onclick`, ...) and external references (`href`, `src`, `data`, `action`, `formaction`, `srcdoc`, `url()` and `@import` to anything but `#fragments` and embedded raster images, checked after character references and css escapes are decoded) from an untrusted svg. Doctypes, comments and processing instructions are removed too, malformed markup is an error.
+/// ```examplec
+/// ctxjs.value.sanitize-svg("<svg onload=\"alert(1)\"><script>alert(1)</script></svg>")
+/// ```
+/// -> str
+#let sanitize-svg(
+  /// the svg
+  /// -> str | bytes
+  data,
+) = {
+  return str(_internal.wasm.sanitize_svg(bytes(data)))
+}
+
+/// Reads the size and properties of a png, jpeg, gif, webp, bmp or svg image without decoding it.
+/// Returns a dictionary with `format`, `width` and `height` (pixels, css pixels for svg), `dpi` (`(x, y)` or `none`), `alpha` (`none` if unknown) and the svg `view-box` (`(x, y, width, height)` or `none`).
+/// ```examplec
+/// ctxjs.value.image-info(bytes("<svg viewBox='0 0 40 30'></svg>"))
+/// ```
+/// -> dictionary
+#let image-info(
+  /// the image as bytes
+  /// -> bytes
+  data,
+) = {
+  return cbor(_internal.wasm.image_info(data))
+}
+
+/// Returns a base64 data url for any data, like fonts, json or css.
+/// ```examplec
+/// ctxjs.value.data-url(bytes("a { color: red }"), mime: "text/css", charset: "utf-8")
+/// ```
+/// -> str
+#let data-url(
+  /// the data
+  /// -> str | bytes
+  data,
+  /// the mime type, if it is `auto` it is detected (images, fonts and other binary formats, json, text)
+  /// -> str | auto
+  mime: auto,
+  /// the charset parameter of the data url
+  /// -> str | none
+  charset: none,
+) = {
+  if mime == auto {
+    mime = ""
+  }
+  if charset == none {
+    charset = ""
+  }
+  return str(_internal.wasm.data_url(bytes(data), bytes(mime), bytes(charset)))
+}